@@ -1,9 +1,21 @@
-use clap::{builder::PossibleValue, Parser, ValueEnum};
-use std::{env, fs::create_dir_all};
-use std::{fmt::Display, path::PathBuf};
+use clap::{
+    builder::{PossibleValue, TypedValueParser},
+    error::ErrorKind,
+    Parser, ValueEnum,
+};
+use std::{env, ffi::OsStr, fs::create_dir_all, str::FromStr};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use terminal_size::{terminal_size, Height, Width};
 
-use crate::cli::io::{colorize, MessageType};
+use crate::{
+    cli::io::{colorize, MessageType},
+    crypto::Cipher,
+    pass::normalized_url,
+};
 
 use super::io::bold;
 const ASCII_ART_ABOUT: &str = r#"
@@ -27,20 +39,178 @@ const ASCII_ART_ABOUT: &str = r#"
         ....              ....
 
 "#;
-const ABOUT: &str = "L🦀CKBOX: A password manager and generator";
+pub const ABOUT: &str = "L🦀CKBOX: A password manager and generator";
 pub const DEFAULT_PASSWORD_FILENAME: &str = "store";
+/// Passing this as the file name reads the encrypted store from stdin and writes it to stdout.
+pub const STDIO_FILE_NAME: &str = "-";
+/// The largest `generate --count` accepted unless `--max-count` raises it.
+pub const DEFAULT_MAX_COUNT: usize = 100_000;
+/// A project-local store, found in the current directory or one of its ancestors when no
+/// `--file-name` is given.
+pub const PROJECT_STORE_FILENAME: &str = ".lockbox.store";
 
-pub fn get_password_store_path(file_name: String) -> anyhow::Result<PathBuf> {
+fn get_home_dir() -> anyhow::Result<String> {
     #[cfg(not(windows))]
-    let home_dir = env::var("HOME")?;
+    let home_dir = env::var("HOME");
     #[cfg(windows)]
-    let home_dir = env::var("USERPROFILE")?;
-    let home_path = PathBuf::from(home_dir);
+    let home_dir = env::var("USERPROFILE");
+    home_dir.map_err(|_| anyhow::anyhow!("Unable to determine the home directory"))
+}
+
+/// Expands a leading `~` and any `$VAR` or `${VAR}` references in the file name.
+pub fn expand_file_name(file_name: &str) -> anyhow::Result<String> {
+    let mut expanded = String::new();
+    let mut rest = file_name;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        expanded.push_str(&get_home_dir()?);
+        rest = &rest[1..];
+    }
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        let braced = chars.next_if_eq(&'{').is_some();
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            name.push(c);
+        }
+        if braced && chars.next_if_eq(&'}').is_none() {
+            return Err(anyhow::anyhow!(
+                "Unterminated variable reference in file name: {file_name}"
+            ));
+        }
+        if name.is_empty() {
+            expanded.push('$');
+            if braced {
+                return Err(anyhow::anyhow!(
+                    "Empty variable reference in file name: {file_name}"
+                ));
+            }
+            continue;
+        }
+        let value = env::var(&name).map_err(|_| {
+            anyhow::anyhow!("Environment variable {name} used in the file name is not set")
+        })?;
+        expanded.push_str(&value);
+    }
+    Ok(expanded)
+}
+
+/// Parses `key=value` metadata. A `url` is checked and gets `https://` when it has no scheme, see
+/// `normalized_url`.
+pub fn parse_metadata(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((key, value)) if key.trim() == "url" => {
+            Ok(("url".to_string(), normalized_url(value)?))
+        }
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("invalid metadata `{input}`, expected key=value")),
+    }
+}
+
+/// Parses a separator, where `\t`, `\n` and `\\` stand for a tab, a newline and a backslash,
+/// since shells don't expand them in quotes.
+pub fn parse_separator(input: &str) -> Result<String, String> {
+    let mut separator = String::new();
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            separator.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => separator.push('\t'),
+            Some('n') => separator.push('\n'),
+            Some('\\') => separator.push('\\'),
+            _ => {
+                return Err(format!(
+                    "invalid separator `{input}`, only \\t, \\n and \\\\ can be escaped"
+                ))
+            }
+        }
+    }
+    Ok(separator)
+}
+
+/// Parses a duration like `30s`, `90m`, `12h`, `7d` or `2w`.
+pub fn parse_ttl(input: &str) -> Result<Duration, String> {
+    let invalid =
+        || format!("invalid duration `{input}`, expected a number followed by s, m, h, d, w or y");
+    let unit_index = input.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = input.split_at(unit_index);
+    let seconds_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    amount
+        .parse::<u64>()
+        .ok()
+        .filter(|amount| *amount > 0)
+        .and_then(|amount| amount.checked_mul(seconds_per_unit))
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// Finds the closest project store in `directory` or its ancestors, like git finds `.git`.
+pub fn find_project_store(directory: &Path) -> Option<PathBuf> {
+    directory
+        .ancestors()
+        .map(|ancestor| ancestor.join(PROJECT_STORE_FILENAME))
+        .find(|file_path| file_path.is_file())
+}
+
+/// The path of the store named `file_name` in the `.lockbox` directory. Without a `file_name`, a
+/// project store found from the current directory is used, or else the default store. A
+/// `--file-name` that is given is always used, even when it is the name of the default store.
+pub fn get_password_store_path(file_name: Option<String>) -> anyhow::Result<PathBuf> {
+    let Some(file_name) = file_name else {
+        if let Some(file_path) = env::current_dir()
+            .ok()
+            .and_then(|directory| find_project_store(&directory))
+        {
+            return Ok(file_path);
+        }
+        return get_password_store_path(Some(DEFAULT_PASSWORD_FILENAME.to_string()));
+    };
+    let file_name = expand_file_name(&file_name)?;
+    let home_path = PathBuf::from(get_home_dir()?);
     let file_path = home_path.join(".lockbox").join(file_name);
     create_dir_all(file_path.parent().unwrap())?;
     Ok(file_path)
 }
 
+/// What to ask for when prompting for the master password of the store at `file_path`, or of the
+/// store read from stdin. Stores other than the default one are named, e.g.
+/// `master password for the store '/home/me/.lockbox/work'`.
+pub fn master_password_prompt(file_path: Option<&Path>) -> String {
+    match file_path {
+        None => "master password for the store read from stdin".to_string(),
+        Some(file_path) if file_path.file_name() == Some(OsStr::new(DEFAULT_PASSWORD_FILENAME)) => {
+            "master password".to_string()
+        }
+        Some(file_path) => format!("master password for the store '{}'", file_path.display()),
+    }
+}
+
+pub fn parse_prompt_style(input: &str) -> Result<String, String> {
+    if input.contains("{prompt}") {
+        Ok(input.to_string())
+    } else {
+        Err(format!(
+            "invalid prompt style `{input}`, expected a text containing {{prompt}}"
+        ))
+    }
+}
+
 fn get_about(terminal_size: Option<(Width, Height)>) -> String {
     let about = bold(ABOUT);
     if let Some((Width(w), Height(h))) = terminal_size {
@@ -99,6 +269,104 @@ fn get_about(terminal_size: Option<(Width, Height)>) -> String {
 pub struct Args {
     #[clap(subcommand)]
     pub command: Command,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = ClipboardBackend::Auto,
+        help = "The clipboard used to copy passwords. osc52 copies through the terminal, which also works over SSH."
+    )]
+    pub clipboard_backend: ClipboardBackend,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = false,
+        help = "Initialize the password store if it does not exist yet. [default: false]"
+    )]
+    pub create: bool,
+    #[clap(
+        long,
+        global = true,
+        value_name = "TEMPLATE",
+        value_parser = parse_prompt_style,
+        help = "The text of password prompts, where {prompt} is replaced by what is asked for, e.g. \"{prompt}:\". [default: \"Please enter the {prompt}\"]"
+    )]
+    pub prompt_style: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        value_name = "FD",
+        value_parser = clap::value_parser!(u32).range(3..),
+        help = "Read the master password from this open file descriptor, e.g. a pipe from the parent process, instead of prompting for it. Only the first line is read. Unix only. --master takes precedence."
+    )]
+    pub master_fd: Option<u32>,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = Cipher::default(),
+        help = "The cipher a password store initialized with --create is encrypted with. Existing stores keep their cipher."
+    )]
+    pub cipher: Cipher,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = false,
+        requires = "create",
+        help = "Make a password store initialized with --create append-only: entries can be added but never removed or changed, a newer entry for the same service and username supersedes the older ones. [default: false]"
+    )]
+    pub append_only: bool,
+    #[clap(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Append a line to this file for every password revealed by list, show or get, with the time, the command, the service and the username but never the password"
+    )]
+    pub audit_log: Option<PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Refuse to reveal more than N distinct passwords with list, show or get in this invocation or REPL session, to limit what someone with access to it can take."
+    )]
+    pub max_reveals: Option<usize>,
+    #[clap(
+        long,
+        global = true,
+        value_name = "DURATION",
+        value_parser = parse_ttl,
+        requires = "max_reveals",
+        help = "Apply --max-reveals to any span of this length, e.g. 10m, instead of the whole session."
+    )]
+    pub reveal_window: Option<Duration>,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = false,
+        help = "Accept a weak new master password without asking, when initializing a store or updating the master password. Without it, a weak master password passed with --master, --new-master or --master-fd is refused. [default: false]"
+    )]
+    pub allow_weak_master: bool,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = false,
+        help = "Refuse master passwords passed on the command line with --master or --new-master, which leak into the shell history and the process list. [default: false]"
+    )]
+    pub strict_security: bool,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = false,
+        conflicts_with = "strict_security",
+        help = "Don't warn about master passwords passed on the command line, for those who accept the risk. [default: false]"
+    )]
+    pub no_master_warning: bool,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = false,
+        help = "Don't warn that a running clipboard manager may keep copied passwords in its history. [default: false]"
+    )]
+    pub no_clipboard_manager_warning: bool,
 }
 
 #[derive(Debug, Copy, Clone, Parser, PartialEq)]
@@ -133,6 +401,135 @@ impl ValueEnum for Length {
     }
 }
 
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Length::value_variants()
+            .iter()
+            .find(|length| length.to_string() == value.trim())
+            .copied()
+            .ok_or_else(|| {
+                let accepted = Length::value_variants()
+                    .iter()
+                    .map(|length| length.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("Unsupported password length `{value}`. Accepted values are: {accepted}")
+            })
+    }
+}
+
+#[derive(Clone)]
+pub struct LengthValueParser;
+
+impl TypedValueParser for LengthValueParser {
+    type Value = Length;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        value.to_string_lossy().parse::<Length>().map_err(|err| {
+            let message = match arg {
+                Some(arg) => format!("invalid value for '{arg}': {err}\n"),
+                None => format!("{err}\n"),
+            };
+            clap::Error::raw(ErrorKind::InvalidValue, message).with_cmd(cmd)
+        })
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            Length::value_variants()
+                .iter()
+                .filter_map(|length| length.to_possible_value()),
+        ))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ClipboardBackend {
+    Auto,
+    X11,
+    Wayland,
+    Osc52,
+}
+
+impl Display for ClipboardBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backend = self
+            .to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .to_string();
+        write!(f, "{backend}")
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum GenerateFormat {
+    Password,
+    Hex,
+    Base64,
+    #[value(name = "base64url")]
+    Base64Url,
+}
+
+impl Display for GenerateFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = self
+            .to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .to_string();
+        write!(f, "{format}")
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ListFormat {
+    Text,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl Display for ListFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = self
+            .to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .to_string();
+        write!(f, "{format}")
+    }
+}
+
+/// How `show` prints an entry. The formats other than text print only the username and the
+/// password, without colors or labels, for password pickers like rofi or dmenu.
+#[derive(Debug, Copy, Clone, Default, PartialEq, ValueEnum)]
+pub enum ShowFormat {
+    #[default]
+    Text,
+    /// The username and the password on separate lines
+    Lines,
+    /// The username, a tab and the password without a trailing newline, for `xdotool type`
+    Type,
+}
+
+impl Display for ShowFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = self
+            .to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .to_string();
+        write!(f, "{format}")
+    }
+}
+
 #[derive(Parser, Debug, PartialEq)]
 pub enum Command {
     #[clap(
@@ -140,8 +537,12 @@ pub enum Command {
         long_about = "Use this command to add a new password entry to your password store. You can specify the service, username, and password, or choose to generate a new password with custom properties. You can also specify the name of the password file and the master password used to encrypt the password store."
     )]
     Add {
-        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use.")]
-        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
         #[clap(
             short,
             long,
@@ -165,7 +566,7 @@ pub enum Command {
             help = "Whether to generate a new password instead of specifying one. [default: false]"
         )]
         generate: bool,
-        #[clap(short, long, default_value_t = Length::Sixteen, help="The length of the generated password.")]
+        #[clap(short, long, default_value_t = Length::Sixteen, value_parser = LengthValueParser, help="The length of the generated password.")]
         length: Length,
         #[clap(
             long,
@@ -191,6 +592,69 @@ pub enum Command {
             help = "Whether to include numbers in the generated password. [default: true]"
         )]
         numbers: bool,
+        #[clap(
+            long,
+            overrides_with = "no_strict",
+            help = "Require at least one character of every enabled kind in the generated password. This is the default."
+        )]
+        strict: bool,
+        #[clap(
+            long,
+            overrides_with = "strict",
+            help = "Allow generated passwords that miss some of the enabled kinds of characters."
+        )]
+        no_strict: bool,
+        #[clap(
+            long,
+            value_parser = parse_metadata,
+            help = "Metadata to store with the password as key=value. Can be repeated. A url without a scheme gets https://."
+        )]
+        meta: Vec<(String, String)>,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Mark the password as a favorite. [default: false]"
+        )]
+        favorite: bool,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Refuse to add the password when the service already has an entry for the username, comparing service names ignoring case, e.g. GitHub and github. [default: false]"
+        )]
+        ignore_case: bool,
+        #[clap(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_ttl,
+            help = "Make the password expire after this long, e.g. 90m, 12h or 7d. Expired passwords are hidden and removed by `lockbox gc`."
+        )]
+        ttl: Option<Duration>,
+        #[clap(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_ttl,
+            help = "Clear the password from the clipboard this long after `show` copied it, e.g. 30s or 5m. By default it stays on the clipboard."
+        )]
+        clipboard_ttl: Option<Duration>,
+        #[clap(
+            long,
+            requires = "generate",
+            conflicts_with_all = ["meta", "favorite", "ttl", "clipboard_ttl", "folder"],
+            help = "Replace the password of an existing entry for the service and username with the generated one, keeping the rest of the entry. Without it, --generate refuses to touch an existing entry. The options that set the rest of the entry can't be combined with it."
+        )]
+        upsert: bool,
+        #[clap(
+            long,
+            requires = "generate",
+            help = "Print the generated password spelled out with the NATO alphabet and named symbols, for reading it aloud."
+        )]
+        phonetic: bool,
+        #[clap(
+            long,
+            value_name = "PATH",
+            help = "File the password under this folder, e.g. work/aws/prod. By default it is in the root folder."
+        )]
+        folder: Option<String>,
     },
 
     #[clap(
@@ -198,8 +662,14 @@ pub enum Command {
         long_about = "Use this command to generate a random password with custom properties. You can specify the length of the generated password and choose whether to include symbols, uppercase letters, lowercase letters, and numbers. You can also generate multiple passwords at once by specifying the count option."
     )]
     Generate {
-        #[clap(short, long, default_value_t = Length::Sixteen, help = "The length of the generated password.")]
+        #[clap(short, long, default_value_t = Length::Sixteen, value_parser = LengthValueParser, help = "The length of the generated password.")]
         length: Length,
+        #[clap(
+            long,
+            conflicts_with_all = ["length", "bytes"],
+            help = "Pick the shortest length that reaches this many bits of entropy with the enabled kinds of characters, instead of --length. With a token format, pick the number of bytes."
+        )]
+        bits: Option<u32>,
         #[clap(
             short,
             long,
@@ -228,6 +698,18 @@ pub enum Command {
             help = "Whether to include numbers in the generated password. [default: true]"
         )]
         numbers: bool,
+        #[clap(
+            long,
+            overrides_with = "no_strict",
+            help = "Require at least one character of every enabled kind in the generated password. This is the default."
+        )]
+        strict: bool,
+        #[clap(
+            long,
+            overrides_with = "strict",
+            help = "Allow generated passwords that miss some of the enabled kinds of characters."
+        )]
+        no_strict: bool,
         #[clap(
             short,
             long,
@@ -235,6 +717,63 @@ pub enum Command {
             help = "The number of passwords to generate. [default: 1]"
         )]
         count: usize,
+        #[clap(
+            long,
+            default_value_t = DEFAULT_MAX_COUNT,
+            help = "The largest --count accepted, to catch typos like an extra zero. [default: 100000]"
+        )]
+        max_count: usize,
+        #[clap(
+            long,
+            default_value_t = GenerateFormat::Password,
+            help = "The format of the generated value. Use hex, base64 or base64url to generate a random token instead of a password."
+        )]
+        format: GenerateFormat,
+        #[clap(
+            long,
+            default_value_t = 32,
+            help = "The number of random bytes in a generated token. Only used with a token format. [default: 32]"
+        )]
+        bytes: usize,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Copy the generated value to the clipboard instead of printing it. Can't be combined with --count. [default: false]"
+        )]
+        clipboard: bool,
+        #[clap(
+            long,
+            default_value_t = 45,
+            value_name = "SECONDS",
+            help = "With --clipboard, wait this long and then clear the clipboard. 0 leaves the value on the clipboard. [default: 45]"
+        )]
+        clear_after: u64,
+        #[clap(
+            long,
+            conflicts_with = "clipboard",
+            help = "Print each generated value spelled out with the NATO alphabet and named symbols, for reading it aloud."
+        )]
+        phonetic: bool,
+        #[clap(
+            long,
+            conflicts_with = "clipboard",
+            help = "INSECURE, for tests and demos only: generate the same passwords for the same seed. Anyone who knows the seed can reproduce them, so never use them as real secrets."
+        )]
+        seed: Option<u64>,
+        #[clap(
+            long,
+            default_value = "",
+            hide_default_value = true,
+            help = "A fixed text every generated password starts with, counted in --length, e.g. a letter for sites that require one first."
+        )]
+        prefix: String,
+        #[clap(
+            long,
+            default_value = "",
+            hide_default_value = true,
+            help = "A fixed text every generated password ends with, counted in --length."
+        )]
+        suffix: String,
     },
 
     #[clap(
@@ -242,8 +781,12 @@ pub enum Command {
         long_about = "Use this command to list all passwords stored in your password manager. You can specify the name of the password file and the master password used to decrypt the password store. You can also choose whether to show the actual passwords or just the service and username information."
     )]
     List {
-        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
-        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
         #[clap(
             short,
             long,
@@ -252,6 +795,82 @@ pub enum Command {
         master: Option<String>,
         #[clap(short, long, default_value_t = false, aliases=&["show", "show-passwords", "reveal"], help="Whether to show the actual passwords or just the service and username information. [default: false]")]
         show_passwords: bool,
+        #[clap(
+            long,
+            value_name = "SERVICE",
+            help = "Show the passwords of the entries for this service and keep the others hidden. Can be repeated."
+        )]
+        reveal_service: Vec<String>,
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["show_passwords", "reveal_service"],
+            help = "Only list the unique service names, sorted alphabetically. [default: false]"
+        )]
+        services_only: bool,
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with = "services_only",
+            help = "Only list passwords marked as favorites. [default: false]"
+        )]
+        favorites: bool,
+        #[clap(
+            long,
+            help = "Hide entries whose service or username contains this text, ignoring case. Applied after the other filters. Can be repeated."
+        )]
+        exclude: Vec<String>,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Also list the entries disabled with `edit --disable`. [default: false]"
+        )]
+        include_disabled: bool,
+        #[clap(
+            long,
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+            help = "Show this many lines at a time, waiting for Enter between pages. Ignored when the output is not a terminal."
+        )]
+        page_size: Option<usize>,
+        #[clap(
+            long,
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+            help = "Only show the first N entries after filtering, followed by how many were left out."
+        )]
+        limit: Option<usize>,
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with = "services_only",
+            help = "Number each entry, e.g. `[3]`, for `show --index` and `remove --index`. Indices follow the unfiltered listing and change when the store changes. [default: false]"
+        )]
+        show_index: bool,
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["services_only", "format"],
+            help = "Print the entries as a table with aligned columns. Values longer than 32 characters are shortened with an ellipsis. [default: false]"
+        )]
+        table: bool,
+        #[clap(
+            long,
+            default_value_t = ListFormat::Text,
+            help = "The output format. ndjson prints one JSON object per entry and line."
+        )]
+        format: ListFormat,
+        #[clap(
+            long,
+            value_name = "PATH",
+            help = "Only list the entries in this folder and its subfolders, e.g. work/aws."
+        )]
+        folder: Option<String>,
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["services_only", "table", "format"],
+            help = "Print the entries as a tree of their folders. [default: false]"
+        )]
+        tree: bool,
     },
 
     #[clap(
@@ -259,14 +878,19 @@ pub enum Command {
         long_about = "Use this command to remove a password entry from your password store. You can specify the service and username associated with the password you want to remove. You can also specify the name of the password file and the master password used to encrypt the password store."
     )]
     Remove {
-        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
-        file_name: String,
         #[clap(
             short,
             long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            required_unless_present_any = ["index", "older_than"],
             help = "The name of the service for which you are removing a password."
         )]
-        service: String,
+        service: Option<String>,
         #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to remove. [Optional]")]
         username: Option<String>,
         #[clap(
@@ -275,65 +899,503 @@ pub enum Command {
             help = "The master password used to encrypt the password store."
         )]
         master: Option<String>,
-    },
-
-    #[clap(
-        about = "Show a specific password in the password manager",
-        long_about = "Use this command to show a specific password stored in your password manager. You can specify the service and username associated with the password you want to show. You can also specify the name of the password file and the master password used to decrypt the password store."
-    )]
-    Show {
-        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
-        file_name: String,
         #[clap(
-            short,
             long,
-            help = "The name of the service for which you are showing a password."
+            conflicts_with_all = ["service", "username"],
+            help = "Remove the entry numbered INDEX by `list --show-index` instead of naming its service."
         )]
-        service: String,
-        #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to show. [Optional]")]
-        username: Option<String>,
+        index: Option<usize>,
+        #[clap(
+            long,
+            value_name = "AGE",
+            value_parser = parse_ttl,
+            conflicts_with_all = ["service", "username", "index"],
+            help = "Remove every entry added or last changed more than AGE ago, e.g. 90d or 2y (also s, m, h and w). The entries are listed and only removed after confirming. Entries added before lockbox recorded when are kept."
+        )]
+        older_than: Option<Duration>,
         #[clap(
             short,
             long,
-            help = "The master password used to decrypt the password store."
+            default_value_t = false,
+            requires = "older_than",
+            conflicts_with_all = ["service", "username", "index"],
+            help = "Remove the entries found by --older-than without asking. [default: false]"
         )]
-        master: Option<String>,
+        yes: bool,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Print nothing when the entry does not exist, the exit code 3 tells. [default: false]"
+        )]
+        quiet: bool,
     },
 
     #[clap(
-        about = "Update the master password",
-        long_about = "Update the master password used to encrypt and decrypt the password store"
+        about = "Change a password entry",
+        long_about = "Use this command to change an entry of your password store without removing it. `--disable` marks it as inactive, e.g. for a closed account: it is kept for reference and can still be shown, but `list` hides it unless `--include-disabled` is given. `--enable` makes it active again."
     )]
-    UpdateMaster {
-        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
-        file_name: String,
+    Edit {
         #[clap(
             short,
             long,
-            help = "The original master password used to encrypt and decrypt the password store."
+            help = "The name of the password file to use. [default: passwords]"
         )]
-        master: Option<String>,
+        file_name: Option<String>,
         #[clap(
             short,
             long,
-            help = "The new master password to be used to encrypt and decrypt the password store."
+            required_unless_present = "index",
+            help = "The name of the service for which you are changing the entry."
         )]
-        new_master: Option<String>,
-    },
-
-    #[clap(
+        service: Option<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the entry you want to change. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to encrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "Change the entry numbered INDEX by `list --show-index` instead of naming its service."
+        )]
+        index: Option<usize>,
+        #[clap(
+            long,
+            default_value_t = false,
+            required_unless_present = "enable",
+            conflicts_with = "enable",
+            help = "Mark the entry as inactive, hiding it from `list`. [default: false]"
+        )]
+        disable: bool,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Make a disabled entry active again. [default: false]"
+        )]
+        enable: bool,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Print nothing when the entry does not exist, the exit code 3 tells. [default: false]"
+        )]
+        quiet: bool,
+    },
+
+    #[clap(
+        about = "Rename a service on all its entries",
+        long_about = "Use this command when a provider changes its name, e.g. from twitter to x. Every entry of the service is renamed, keeping its username and password. Nothing is renamed when the new service already has an entry for one of the usernames."
+    )]
+    RenameService {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(long, help = "The current name of the service.")]
+        from: String,
+        #[clap(long, help = "The new name of the service.")]
+        to: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+    },
+
+    #[clap(
+        about = "Show a specific password in the password manager",
+        long_about = "Use this command to show a specific password stored in your password manager. You can specify the service and username associated with the password you want to show. You can also specify the name of the password file and the master password used to decrypt the password store."
+    )]
+    Show {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            required_unless_present_any = ["index", "url"],
+            help = "The name of the service for which you are showing a password. Can be repeated to show several entries while decrypting the store only once. Their passwords are then not copied to the clipboard, and `--format type` prints one entry per line."
+        )]
+        service: Vec<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to show. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Show the password on the alternate screen of the terminal until a key is pressed, so that it does not stay in the scrollback. [default: false]"
+        )]
+        alt_screen: bool,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Fuzzy-match the service name against the stored services, e.g. `gh` for github. When several services match equally well you are asked to pick one. [default: false]"
+        )]
+        fuzzy: bool,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username", "fuzzy", "url"],
+            help = "Show the entry numbered INDEX by `list --show-index` instead of naming its service."
+        )]
+        index: Option<usize>,
+        #[clap(
+            long,
+            default_value_t = ShowFormat::Text,
+            conflicts_with = "alt_screen",
+            help = "The output format. `lines` and `type` print only the username and the password for password pickers and autotype. Errors and warnings always go to stderr."
+        )]
+        format: ShowFormat,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username", "fuzzy"],
+            help = "Show the entry whose url metadata, set with --meta url=..., is on the host of URL, e.g. github.com. The scheme, path and a leading www. are ignored."
+        )]
+        url: Option<String>,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Print nothing when the entry does not exist, the exit code 3 tells. [default: false]"
+        )]
+        quiet: bool,
+    },
+
+    #[clap(
+        about = "Print fields of a password entry",
+        long_about = "Use this command to print the raw value of one field of a password entry, without colors or labels, e.g. `PASS=$(lockbox get -s github)`. The field can be password, username, service or the key of a metadata entry. `--fields` prints several fields on one line, separated by a tab or `--separator`, e.g. for `IFS=$'\\t' read -r user pass < <(lockbox get -s github --fields username,password)`. Errors are printed to stderr."
+    )]
+    Get {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The name of the service for which you are getting a field."
+        )]
+        service: String,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password entry. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            long,
+            default_value_t = String::from("password"),
+            help = "The field to print: password, username, service or a metadata key. [default: password]"
+        )]
+        field: String,
+        #[clap(
+            long,
+            value_delimiter = ',',
+            conflicts_with = "field",
+            help = "Print several fields on one line, in this order, e.g. username,password."
+        )]
+        fields: Vec<String>,
+        #[clap(
+            long,
+            default_value = "\\t",
+            value_parser = parse_separator,
+            requires = "fields",
+            help = "The text printed between the --fields. \\t, \\n and \\\\ stand for a tab, a newline and a backslash. [default: \\t]"
+        )]
+        separator: String,
+        #[clap(
+            long,
+            help = "Print the value without a trailing newline, like printf."
+        )]
+        no_newline: bool,
+    },
+
+    #[clap(
+        about = "Remove duplicate passwords from the password manager",
+        long_about = "Use this command to remove exact duplicate entries (same service, username and password) from your password store, keeping one of each. Entries with the same service and username but different passwords are reported so you can review them, but they are not removed.",
+        aliases = &["deduplicate"]
+    )]
+    Dedup {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+    },
+
+    #[clap(
+        about = "Remove expired passwords from the password manager",
+        long_about = "Use this command to delete the passwords added with `add --ttl` whose time to live has passed. Expired passwords are already hidden from every other command."
+    )]
+    Gc {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+    },
+
+    #[clap(
+        about = "Print a fingerprint of the store encryption key",
+        long_about = "Use this command to check that two copies of a password store, e.g. on different machines, are encrypted with the same key. The fingerprint is derived from the key and reveals nothing about the master password. Stores created separately have different fingerprints even with the same master password."
+    )]
+    Fingerprint {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+    },
+
+    #[clap(
+        about = "Measure how long deriving the store key takes",
+        long_about = "Use this command to see how long opening a password store spends deriving the encryption key from the master password. The key is derived from a throwaway password, so no store or master password is needed."
+    )]
+    BenchKdf {
+        #[clap(
+            long,
+            default_value_t = 3,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "The number of derivations to average over. [default: 3]"
+        )]
+        runs: u32,
+    },
+
+    #[clap(
+        about = "Check that encryption works on this machine",
+        long_about = "Use this command when troubleshooting lockbox on a new platform. It creates a throwaway store in memory with every cipher, adds an entry, saves the store, reopens it and decrypts the entry, printing whether each step passed. Your password stores are not touched.",
+        aliases = &["self-check"]
+    )]
+    SelfTest,
+
+    #[clap(
+        about = "Update the master password",
+        long_about = "Update the master password used to encrypt and decrypt the password store"
+    )]
+    UpdateMaster {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The original master password used to encrypt and decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The new master password to be used to encrypt and decrypt the password store."
+        )]
+        new_master: Option<String>,
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with = "new_master",
+            help = "Generate a random new master password with letters, numbers and symbols, and print it once. [default: false]"
+        )]
+        generate: bool,
+        #[clap(short, long, default_value_t = Length::ThirtyTwo, value_parser = LengthValueParser, requires = "generate", help = "The length of the generated master password.")]
+        length: Length,
+    },
+
+    #[clap(
+        about = "Export recovery shares of the store encryption key",
+        long_about = "Use this command to split the encryption key of your password store into recovery shares with Shamir's Secret Sharing. Store the shares separately; any `threshold` of them can be used with `recover` to reset a forgotten master password. The shares stop working once the master password is updated."
+    )]
+    ExportRecovery {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to encrypt and decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            long,
+            default_value_t = 5,
+            value_parser = clap::value_parser!(u8).range(1..),
+            help = "The number of recovery shares to create. [default: 5]"
+        )]
+        shares: u8,
+        #[clap(
+            long,
+            default_value_t = 3,
+            value_parser = clap::value_parser!(u8).range(1..),
+            help = "The number of recovery shares needed to recover the store. [default: 3]"
+        )]
+        threshold: u8,
+    },
+
+    #[clap(
+        about = "Recover a password store from recovery shares",
+        long_about = "Use this command to rebuild the encryption key of your password store from the shares created by `export-recovery` and set a new master password."
+    )]
+    Recover {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            long,
+            required = true,
+            help = "A recovery share created by export-recovery. Repeat once per share."
+        )]
+        share: Vec<String>,
+        #[clap(
+            short,
+            long,
+            help = "The new master password to be used to encrypt and decrypt the password store."
+        )]
+        new_master: Option<String>,
+    },
+
+    #[clap(
+        about = "Print the lockbox man page",
+        long_about = "Use this command to print a roff man page for lockbox and all of its subcommands to stdout, e.g. `lockbox man > lockbox.1`."
+    )]
+    Man,
+
+    #[cfg(feature = "serve")]
+    #[clap(
+        about = "Serve credentials to a browser extension over a local HTTP endpoint",
+        long_about = "Use this command to let a browser extension look up credentials by domain. It listens on 127.0.0.1 only and prints a session token that every request must send as `Authorization: Bearer <token>`. `GET /entries?domain=<domain>` returns the service, username and password of the entries whose service is the domain or one of its parent domains. Only available when lockbox is built with the `serve` feature."
+    )]
+    Serve {
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            long,
+            default_value_t = 0,
+            help = "The port to listen on. 0 picks a free port, which is printed on start."
+        )]
+        port: u16,
+    },
+
+    #[clap(
         about = "Start an interactive REPL session",
         long_about = "Use this command to start an interactive REPL (Read-Eval-Print Loop) session with your password manager. In this mode, you can enter commands interactively and see their results immediately."
     )]
     Repl {
-        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
-        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The name of the password file to use. [default: passwords]"
+        )]
+        file_name: Option<String>,
+        #[clap(
+            long,
+            default_value_t = ListFormat::Text,
+            help = "The output format. ndjson leaves out the banner, menus and prompts and prints the result of each command as one JSON object per line."
+        )]
+        format: ListFormat,
     },
 }
 
+impl Command {
+    /// The master password option of the command, for the commands that open a store with one.
+    pub fn master_mut(&mut self) -> Option<&mut Option<String>> {
+        match self {
+            Command::Add { master, .. }
+            | Command::List { master, .. }
+            | Command::Remove { master, .. }
+            | Command::Edit { master, .. }
+            | Command::RenameService { master, .. }
+            | Command::Show { master, .. }
+            | Command::Get { master, .. }
+            | Command::Dedup { master, .. }
+            | Command::Gc { master, .. }
+            | Command::Fingerprint { master, .. }
+            | Command::UpdateMaster { master, .. }
+            | Command::ExportRecovery { master, .. } => Some(master),
+            #[cfg(feature = "serve")]
+            Command::Serve { master, .. } => Some(master),
+            _ => None,
+        }
+    }
+
+    /// The options of the command given a password on the command line, where it can be read
+    /// from the shell history and the process list.
+    pub fn secret_options(&mut self) -> Vec<&'static str> {
+        let mut options = Vec::new();
+        if self.master_mut().is_some_and(|master| master.is_some()) {
+            options.push("--master");
+        }
+        if let Command::UpdateMaster {
+            new_master: Some(_),
+            ..
+        }
+        | Command::Recover {
+            new_master: Some(_),
+            ..
+        } = self
+        {
+            options.push("--new-master");
+        }
+        options
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use clap::CommandFactory;
     use rstest::rstest;
     use tempfile::NamedTempFile;
 
@@ -371,7 +1433,7 @@ mod test {
         &["lockbox", "add", "-f", "test_passwords", "-s", "test_service", "-u", "test_username", "-p", "test_password"],
         Args {
             command: Command::Add {
-                file_name: "test_passwords".to_string(),
+                file_name: Some("test_passwords".to_string()),
                 service: "test_service".to_string(),
                 username: Some("test_username".to_string()),
                 password: Some("test_password".to_string()),
@@ -382,14 +1444,37 @@ mod test {
                 uppercase: true,
                 lowercase: true,
                 numbers: true,
+                strict: false,
+                no_strict: false,
+                meta: vec![],
+                favorite: false,
+                ignore_case: false,
+                ttl: None,
+                clipboard_ttl: None,
+                upsert: false,
+                phonetic: false,
+                folder: None,
             },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
         }
     ),
     case(
         &["lockbox", "add", "-s", "test_service", "-u", "test_username", "-p", "test_password"],
         Args {
             command: Command::Add {
-                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                file_name: None,
                 service: "test_service".to_string(),
                 username: Some("test_username".to_string()),
                 password: Some("test_password".to_string()),
@@ -400,52 +1485,720 @@ mod test {
                 uppercase: true,
                 lowercase: true,
                 numbers: true,
+                strict: false,
+                no_strict: false,
+                meta: vec![],
+                favorite: false,
+                ignore_case: false,
+                ttl: None,
+                clipboard_ttl: None,
+                upsert: false,
+                phonetic: false,
+                folder: None,
             },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
         }
     ),
     case(
-        &["lockbox", "generate", "-l", "32", "-s"],
+        &[
+            "lockbox",
+            "add",
+            "-s",
+            "test_service",
+            "--create",
+            "--cipher",
+            "chacha20-poly1305",
+        ],
+        Args {
+            command: Command::Add {
+                file_name: None,
+                service: "test_service".to_string(),
+                username: None,
+                password: None,
+                master: None,
+                generate: false,
+                length: Length::Sixteen,
+                symbols: false,
+                uppercase: true,
+                lowercase: true,
+                numbers: true,
+                strict: false,
+                no_strict: false,
+                meta: vec![],
+                favorite: false,
+                ignore_case: false,
+                ttl: None,
+                clipboard_ttl: None,
+                upsert: false,
+                phonetic: false,
+                folder: None,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: true,
+            cipher: Cipher::ChaCha20Poly1305,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "add", "-s", "test_service", "--meta", "recovery-email=x@y.com", "--meta", "phone=555", "--favorite", "--ignore-case", "--ttl", "1h", "--clipboard-ttl", "30s"],
+        Args {
+            command: Command::Add {
+                file_name: None,
+                service: "test_service".to_string(),
+                username: None,
+                password: None,
+                master: None,
+                generate: false,
+                length: Length::Sixteen,
+                symbols: false,
+                uppercase: true,
+                lowercase: true,
+                numbers: true,
+                strict: false,
+                no_strict: false,
+                meta: vec![
+                    ("recovery-email".to_string(), "x@y.com".to_string()),
+                    ("phone".to_string(), "555".to_string()),
+                ],
+                favorite: true,
+                ignore_case: true,
+                ttl: Some(Duration::from_secs(3600)),
+                clipboard_ttl: Some(Duration::from_secs(30)),
+                upsert: false,
+                phonetic: false,
+                folder: None,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "generate", "-l", "32", "-s", "--max-count", "500000"],
         Args {
             command: Command::Generate {
                 length: Length::ThirtyTwo,
+                bits: None,
                 symbols: true,
                 uppercase: true,
                 lowercase: true,
                 numbers: true,
+                strict: false,
+                no_strict: false,
                 count: 1,
+                max_count: 500_000,
+                format: GenerateFormat::Password,
+                bytes: 32,
+                clipboard: false,
+                clear_after: 45,
+                phonetic: false,
+                seed: None,
+                prefix: String::new(),
+                suffix: String::new(),
             },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "generate", "--strict", "--no-strict", "--bits", "80", "--prefix", "X-"],
+        Args {
+            command: Command::Generate {
+                length: Length::Sixteen,
+                bits: Some(80),
+                symbols: false,
+                uppercase: true,
+                lowercase: true,
+                numbers: true,
+                strict: false,
+                no_strict: true,
+                count: 1,
+                max_count: DEFAULT_MAX_COUNT,
+                format: GenerateFormat::Password,
+                bytes: 32,
+                clipboard: false,
+                clear_after: 45,
+                phonetic: false,
+                seed: None,
+                prefix: "X-".to_string(),
+                suffix: String::new(),
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "generate", "--format", "base64url", "--bytes", "16", "--clipboard", "--clear-after", "10"],
+        Args {
+            command: Command::Generate {
+                length: Length::Sixteen,
+                bits: None,
+                symbols: false,
+                uppercase: true,
+                lowercase: true,
+                numbers: true,
+                strict: false,
+                no_strict: false,
+                count: 1,
+                max_count: DEFAULT_MAX_COUNT,
+                format: GenerateFormat::Base64Url,
+                bytes: 16,
+                clipboard: true,
+                clear_after: 10,
+                phonetic: false,
+                seed: None,
+                prefix: String::new(),
+                suffix: String::new(),
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
         }
     ),
     case(
         &["lockbox", "list", "--master", "master_password"],
         Args {
             command: Command::List {
-                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                file_name: None,
                 master: Some("master_password".to_string()),
                 show_passwords: false,
+                reveal_service: vec![],
+                services_only: false,
+                favorites: false,
+                exclude: vec![],
+                include_disabled: false,
+                page_size: None,
+                limit: None,
+                show_index: false,
+                table: false,
+                format: ListFormat::Text,
+                folder: None,
+                tree: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "list", "--services-only"],
+        Args {
+            command: Command::List {
+                file_name: None,
+                master: None,
+                show_passwords: false,
+                reveal_service: vec![],
+                services_only: true,
+                favorites: false,
+                exclude: vec![],
+                include_disabled: false,
+                page_size: None,
+                limit: None,
+                show_index: false,
+                table: false,
+                format: ListFormat::Text,
+                folder: None,
+                tree: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "list", "--favorites", "--exclude", "old", "--format", "ndjson", "--limit", "10", "--show-index", "--reveal-service", "github"],
+        Args {
+            command: Command::List {
+                file_name: None,
+                master: None,
+                show_passwords: false,
+                reveal_service: vec!["github".to_string()],
+                services_only: false,
+                favorites: true,
+                exclude: vec!["old".to_string()],
+                include_disabled: false,
+                page_size: None,
+                limit: Some(10),
+                show_index: true,
+                table: false,
+                format: ListFormat::Ndjson,
+                folder: None,
+                tree: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "dedup", "-m", "master_password"],
+        Args {
+            command: Command::Dedup {
+                file_name: None,
+                master: Some("master_password".to_string()),
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "show", "-s", "service", "--clipboard-backend", "osc52"],
+        Args {
+            command: Command::Show {
+                file_name: None,
+                service: vec!["service".to_string()],
+                username: None,
+                master: None,
+                alt_screen: false,
+                fuzzy: false,
+                index: None,
+                format: ShowFormat::Text,
+                url: None,
+                quiet: false,
+            },
+            clipboard_backend: ClipboardBackend::Osc52,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "export-recovery", "--shares", "3", "--threshold", "2"],
+        Args {
+            command: Command::ExportRecovery {
+                file_name: None,
+                master: None,
+                shares: 3,
+                threshold: 2,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "recover", "--share", "1-ab", "--share", "2-cd", "-n", "new_master"],
+        Args {
+            command: Command::Recover {
+                file_name: None,
+                share: vec!["1-ab".to_string(), "2-cd".to_string()],
+                new_master: Some("new_master".to_string()),
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "get", "-s", "service", "--field", "recovery-email"],
+        Args {
+            command: Command::Get {
+                file_name: None,
+                service: "service".to_string(),
+                username: None,
+                master: None,
+                field: "recovery-email".to_string(),
+                fields: vec![],
+                separator: "\t".to_string(),
+                no_newline: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "fingerprint", "--file-name", "vault", "--master", "master_password"],
+        Args {
+            command: Command::Fingerprint {
+                file_name: Some("vault".to_string()),
+                master: Some("master_password".to_string()),
             },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "bench-kdf", "--runs", "5", "--prompt-style", "{prompt}:"],
+        Args {
+            command: Command::BenchKdf { runs: 5 },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: Some("{prompt}:".to_string()),
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "self-check"],
+        Args {
+            command: Command::SelfTest,
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "man"],
+        Args {
+            command: Command::Man,
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
         }
     ),
     case(
         &["lockbox", "remove", "-s", "service"],
         Args {
             command: Command::Remove {
-                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
-                service: "service".to_string(),
+                file_name: None,
+                service: Some("service".to_string()),
+                username: None,
+                master: None,
+                index: None,
+                older_than: None,
+                yes: false,
+                quiet: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "remove", "--older-than", "2y", "--yes"],
+        Args {
+            command: Command::Remove {
+                file_name: None,
+                service: None,
+                username: None,
+                master: None,
+                index: None,
+                older_than: Some(Duration::from_secs(2 * 365 * 24 * 60 * 60)),
+                yes: true,
+                quiet: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "remove", "--index", "2"],
+        Args {
+            command: Command::Remove {
+                file_name: None,
+                service: None,
                 username: None,
                 master: None,
+                index: Some(2),
+                older_than: None,
+                yes: false,
+                quiet: false,
             },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
         }
     ),
     case(
-        &["lockbox", "show", "-s", "service"],
+        &["lockbox", "edit", "-s", "forum", "-u", "old-account", "--disable"],
+        Args {
+            command: Command::Edit {
+                file_name: None,
+                service: Some("forum".to_string()),
+                username: Some("old-account".to_string()),
+                master: None,
+                index: None,
+                disable: true,
+                enable: false,
+                quiet: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "show", "--index", "3", "--format", "type"],
         Args {
             command: Command::Show {
-                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
-                service: "service".to_string(),
+                file_name: None,
+                service: vec![],
+                username: None,
+                master: None,
+                alt_screen: false,
+                fuzzy: false,
+                index: Some(3),
+                format: ShowFormat::Type,
+                url: None,
+                quiet: false,
+            },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
+        }
+    ),
+    case(
+        &["lockbox", "show", "-s", "service", "-s", "other", "--alt-screen", "--fuzzy"],
+        Args {
+            command: Command::Show {
+                file_name: None,
+                service: vec!["service".to_string(), "other".to_string()],
                 username: None,
                 master: None,
+                alt_screen: true,
+                fuzzy: true,
+                index: None,
+                format: ShowFormat::Text,
+                url: None,
+                quiet: false,
             },
+            clipboard_backend: ClipboardBackend::Auto,
+            create: false,
+            cipher: Cipher::Aes256Gcm,
+            prompt_style: None,
+            master_fd: None,
+            append_only: false,
+            audit_log: None,
+            max_reveals: None,
+            reveal_window: None,
+            allow_weak_master: false,
+            strict_security: false,
+            no_master_warning: false,
+            no_clipboard_manager_warning: false,
         }
     )
     )]
@@ -454,6 +2207,91 @@ mod test {
         assert_eq!(args, expected);
     }
 
+    #[test]
+    fn test_args_definition() {
+        // checks every subcommand, e.g. that conflicts and requirements name existing arguments
+        Args::command().debug_assert();
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("key=value", Ok(("key".to_string(), "value".to_string()))),
+        case("url=https://a.b/?c=d", Ok(("url".to_string(), "https://a.b/?c=d".to_string()))),
+        case("url=github.com", Ok(("url".to_string(), "https://github.com".to_string()))),
+        case("url=http://[::1]:8080", Ok(("url".to_string(), "http://[::1]:8080".to_string()))),
+        case("url=", Err(())),
+        case("url=https://", Err(())),
+        case("url=https://[::1:8080", Err(())),
+        case("empty=", Ok(("empty".to_string(), "".to_string()))),
+        case("novalue", Err(())),
+        case("=value", Err(()))
+    )]
+    fn test_parse_metadata(input: &str, expected: Result<(String, String), ()>) {
+        assert_eq!(parse_metadata(input).map_err(|_| ()), expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case(":", Ok(":")),
+        case("\\t", Ok("\t")),
+        case(" \\n ", Ok(" \n ")),
+        case("\\\\t", Ok("\\t")),
+        case("\\x", Err(())),
+        case("\\", Err(()))
+    )]
+    fn test_parse_separator(input: &str, expected: Result<&str, ()>) {
+        assert_eq!(
+            parse_separator(input).map_err(|_| ()),
+            expected.map(str::to_string)
+        );
+    }
+
+    #[rstest(
+        file_path,
+        expected,
+        case(None, "master password for the store read from stdin"),
+        case(Some("/home/me/.lockbox/store"), "master password"),
+        case(
+            Some("/home/me/.lockbox/work"),
+            "master password for the store '/home/me/.lockbox/work'"
+        )
+    )]
+    fn test_master_password_prompt(file_path: Option<&str>, expected: &str) {
+        assert_eq!(master_password_prompt(file_path.map(Path::new)), expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("{prompt}:", Ok("{prompt}:".to_string())),
+        case("Unlock {prompt}", Ok("Unlock {prompt}".to_string())),
+        case("Password:", Err(()))
+    )]
+    fn test_parse_prompt_style(input: &str, expected: Result<String, ()>) {
+        assert_eq!(parse_prompt_style(input).map_err(|_| ()), expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("45s", Some(45)),
+        case("90m", Some(90 * 60)),
+        case("1h", Some(60 * 60)),
+        case("7d", Some(7 * 24 * 60 * 60)),
+        case("2w", Some(2 * 7 * 24 * 60 * 60)),
+        case("2y", Some(2 * 365 * 24 * 60 * 60)),
+        case("0h", None),
+        case("h", None),
+        case("12", None),
+        case("1.5h", None),
+        case("", None)
+    )]
+    fn test_parse_ttl(input: &str, expected: Option<u64>) {
+        assert_eq!(parse_ttl(input).ok().map(|ttl| ttl.as_secs()), expected);
+    }
+
     #[rstest(
         input,
         expected,
@@ -465,6 +2303,114 @@ mod test {
         assert_eq!(input.get_val(), expected)
     }
 
+    #[rstest(
+        input,
+        expected,
+        case("8", Ok(Length::Eight)),
+        case("16", Ok(Length::Sixteen)),
+        case(" 32 ", Ok(Length::ThirtyTwo)),
+        case("12", Err(())),
+        case("sixteen", Err(())),
+        case("", Err(()))
+    )]
+    fn test_length_from_str(input: &str, expected: Result<Length, ()>) {
+        let parsed = input.parse::<Length>();
+        if let Err(err) = &parsed {
+            assert!(err.contains("Accepted values are: 8, 16, 32"));
+        }
+        assert_eq!(parsed.map_err(|_| ()), expected);
+    }
+
+    #[rstest(
+        input,
+        case(&["lockbox", "generate", "--length", "12"]),
+        case(&["lockbox", "add", "-s", "service", "-l", "abc"])
+    )]
+    fn test_args_invalid_length(input: &[&str]) {
+        let err = Args::try_parse_from(input).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidValue);
+        let message = err.to_string();
+        assert!(message.contains("--length"));
+        assert!(message.contains("Accepted values are: 8, 16, 32"));
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case(&["lockbox", "list"], vec![]),
+        case(&["lockbox", "list", "-m", "master"], vec!["--master"]),
+        case(&["lockbox", "update-master", "-m", "master", "--new-master", "new"], vec!["--master", "--new-master"]),
+        case(&["lockbox", "recover", "--share", "1-ab", "-n", "new"], vec!["--new-master"]),
+        case(&["lockbox", "generate"], vec![])
+    )]
+    fn test_secret_options(input: &[&str], expected: Vec<&str>) {
+        let mut args = Args::parse_from(input);
+        assert_eq!(args.command.secret_options(), expected);
+    }
+
+    #[rstest(
+        input,
+        expected_kind,
+        case(&["lockbox", "show"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "remove"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "show", "--index", "1", "-s", "service"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "remove", "--index", "1", "-u", "user"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "list", "--show-index", "--services-only"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "list", "--table", "--format", "ndjson"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "list", "--master-fd", "2"], ErrorKind::ValueValidation),
+        case(&["lockbox", "show", "-s", "service", "--format", "lines", "--alt-screen"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "list", "--reveal-service", "github", "--services-only"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "update-master", "--generate", "--new-master", "new"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "update-master", "--length", "16"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "remove", "-s", "github", "--older-than", "1y"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "remove", "-s", "github", "--yes"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "remove", "--yes"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "show", "--url", "github.com", "-s", "github"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "show", "--url", "github.com", "--index", "1"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "generate", "--clipboard", "--phonetic"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "generate", "--bits", "80", "-l", "32"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "generate", "--seed", "42", "--clipboard"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "add", "-s", "github", "--upsert"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "add", "-s", "github", "-g", "--upsert", "--meta", "url=github.com"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "add", "-s", "github", "-g", "--upsert", "--favorite"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "add", "-s", "github", "-g", "--upsert", "--ttl", "1h"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "add", "-s", "github", "-g", "--upsert", "--clipboard-ttl", "30s"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "add", "-s", "github", "-g", "--upsert", "--folder", "work"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "generate", "--format", "hex", "--bits", "80", "--bytes", "16"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "edit", "-s", "github"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "edit", "--disable"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "edit", "-s", "github", "--disable", "--enable"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "edit", "--index", "2", "-s", "github", "--enable"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "add", "-s", "github", "--phonetic"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "rename-service", "--from", "twitter"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "list", "--strict-security", "--no-master-warning"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "list", "--tree", "--table"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "list", "--tree", "--services-only"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "list", "--reveal-window", "10m"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "list", "--max-reveals", "0"], ErrorKind::ValueValidation),
+        case(&["lockbox", "get", "-s", "github", "--field", "username", "--fields", "username,password"], ErrorKind::ArgumentConflict),
+        case(&["lockbox", "get", "-s", "github", "--separator", ":"], ErrorKind::MissingRequiredArgument),
+        case(&["lockbox", "get", "-s", "github", "--fields", "username,password", "--separator", "\\x"], ErrorKind::ValueValidation)
+    )]
+    fn test_args_index_conflicts(input: &[&str], expected_kind: ErrorKind) {
+        let err = Args::try_parse_from(input).unwrap_err();
+        assert_eq!(err.kind(), expected_kind);
+    }
+
+    #[test]
+    fn test_args_folder() {
+        let args = Args::parse_from(["lockbox", "add", "-s", "aws", "--folder", "work/aws"]);
+        assert!(matches!(
+            args.command,
+            Command::Add { folder: Some(folder), .. } if folder == "work/aws"
+        ));
+        let args = Args::parse_from(["lockbox", "list", "--folder", "work", "--tree"]);
+        assert!(matches!(
+            args.command,
+            Command::List { folder: Some(folder), tree: true, .. } if folder == "work"
+        ));
+    }
+
     #[test]
     fn test_get_password_store_path() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -475,8 +2421,89 @@ mod test {
             .to_str()
             .unwrap()
             .to_string();
-        let file_path = get_password_store_path(file_name.clone()).unwrap();
+        let file_path = get_password_store_path(Some(file_name.clone())).unwrap();
         assert_eq!(file_path.file_name().unwrap().to_str().unwrap(), file_name);
         assert!(file_path.to_string_lossy().contains(".lockbox"));
+
+        // a --file-name that names the default store is never replaced by a project store
+        let file_path =
+            get_password_store_path(Some(DEFAULT_PASSWORD_FILENAME.to_string())).unwrap();
+        assert_eq!(
+            file_path,
+            PathBuf::from(get_home_dir().unwrap())
+                .join(".lockbox")
+                .join(DEFAULT_PASSWORD_FILENAME)
+        );
+        let file_name = |args: &[&str]| match Args::parse_from(args).command {
+            Command::List { file_name, .. } => file_name,
+            command => panic!("unexpected command {command:?}"),
+        };
+        assert_eq!(file_name(&["lockbox", "list"]), None);
+        assert_eq!(
+            file_name(&["lockbox", "list", "-f", DEFAULT_PASSWORD_FILENAME]),
+            Some(DEFAULT_PASSWORD_FILENAME.to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_project_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_dir = temp_dir.path().join("project");
+        let nested_dir = project_dir.join("src").join("module");
+        std::fs::create_dir_all(&nested_dir).unwrap();
+        assert_eq!(find_project_store(&nested_dir), None);
+
+        let project_store = project_dir.join(PROJECT_STORE_FILENAME);
+        std::fs::write(&project_store, b"").unwrap();
+        assert_eq!(find_project_store(&nested_dir), Some(project_store.clone()));
+        assert_eq!(find_project_store(&project_dir), Some(project_store));
+        assert_eq!(find_project_store(temp_dir.path()), None);
+
+        // a directory with the store's name is not a store
+        std::fs::create_dir(nested_dir.join(PROJECT_STORE_FILENAME)).unwrap();
+        assert_eq!(
+            find_project_store(&nested_dir),
+            Some(project_dir.join(PROJECT_STORE_FILENAME))
+        );
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("store", "store"),
+        case("$LOCKBOX_TEST_VAULTDIR/work.store", "/vaults/work.store"),
+        case("${LOCKBOX_TEST_VAULTDIR}/work.store", "/vaults/work.store"),
+        case("${LOCKBOX_TEST_VAULTDIR}_old", "/vaults_old"),
+        case("cost$", "cost$"),
+        case("a$-b", "a$-b")
+    )]
+    fn test_expand_file_name(input: &str, expected: &str) {
+        env::set_var("LOCKBOX_TEST_VAULTDIR", "/vaults");
+        assert_eq!(expand_file_name(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_expand_file_name_home() {
+        let home_dir = get_home_dir().unwrap();
+        assert_eq!(expand_file_name("~").unwrap(), home_dir);
+        assert_eq!(
+            expand_file_name("~/work.store").unwrap(),
+            format!("{home_dir}/work.store")
+        );
+        assert_eq!(expand_file_name("a~b").unwrap(), "a~b");
+    }
+
+    #[rstest(
+        input,
+        expected_error,
+        case("$LOCKBOX_TEST_UNSET_VAR/store", "LOCKBOX_TEST_UNSET_VAR"),
+        case("${LOCKBOX_TEST_UNSET_VAR}", "LOCKBOX_TEST_UNSET_VAR"),
+        case("${LOCKBOX_TEST_VAULTDIR", "Unterminated"),
+        case("${}", "Empty")
+    )]
+    fn test_expand_file_name_errors(input: &str, expected_error: &str) {
+        env::remove_var("LOCKBOX_TEST_UNSET_VAR");
+        let err = expand_file_name(input).unwrap_err();
+        assert!(err.to_string().contains(expected_error));
     }
 }
@@ -1,14 +1,168 @@
 use crate::{
-    cli::{args::Length, io::read_hidden_input},
-    store::PasswordStore,
+    cli::{
+        args::{Args, ClipboardBackend, GenerateFormat, Length, ListFormat, ShowFormat, ABOUT},
+        io::read_hidden_input,
+    },
+    crypto::{get_random_bytes, get_random_salt, shamir, Cipher, KDF_ITERATIONS},
+    pass::{
+        normalized_host, phonetic_spelling, print_remaining, print_table, print_tree, unix_now,
+        EntryFilter, EntryView, PasswordEntry,
+    },
+    store::{time_key_derivation, PasswordStore, StoreError, StoreOptions},
 };
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use clap::{CommandFactory, ValueEnum};
+use clap_mangen::Man;
 use copypasta::{ClipboardContext, ClipboardProvider};
-use passwords::PasswordGenerator;
-use std::io::Write;
+use passwords::{analyzer, scorer, PasswordGenerator};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::OpenOptions;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::io::{
+    bold, colorize, print, read_terminal_input, wait_with_countdown, MessageType, PromptPassword,
+};
+use super::settings::Settings;
+
+/// Process names of clipboard managers that keep a history of what was copied.
+const CLIPBOARD_MANAGERS: &[&str] = &[
+    "cliphist",
+    "clipit",
+    "clipman",
+    "clipmenud",
+    "copyq",
+    "diodon",
+    "ditto",
+    "clipboardfusion",
+    "flycut",
+    "gpaste-daemon",
+    "greenclip",
+    "klipper",
+    "maccy",
+    "parcellite",
+    "pastebot",
+    "xfce4-clipman",
+];
+
+/// Caps how many distinct passwords can be revealed, so that someone with access to a running
+/// session can't dump the whole store. Revealing the same entry again doesn't count twice.
+/// Without a window the cap is for the whole run, e.g. a REPL session, with one it is for any
+/// such span of time.
+#[derive(Debug)]
+pub struct RevealLimit {
+    max: usize,
+    window: Option<Duration>,
+    revealed: Vec<(Instant, String, Option<String>)>,
+}
+
+impl RevealLimit {
+    pub fn new(max: usize, window: Option<Duration>) -> Self {
+        Self {
+            max,
+            window,
+            revealed: Vec::new(),
+        }
+    }
+
+    /// Records the reveal of `entries` at `now`, or fails without recording anything when that
+    /// would reveal more distinct passwords than allowed.
+    fn admit<'a>(
+        &mut self,
+        entries: impl IntoIterator<Item = &'a EntryView>,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        if let Some(window) = self.window {
+            self.revealed
+                .retain(|(revealed_at, ..)| now.duration_since(*revealed_at) < window);
+        }
+        let mut new = Vec::new();
+        for entry in entries.into_iter().filter(|entry| entry.password.is_some()) {
+            let key = (&entry.service, &entry.username);
+            let known = self
+                .revealed
+                .iter()
+                .any(|(_, service, username)| (service, username) == key)
+                || new.contains(&key);
+            if !known {
+                new.push(key);
+            }
+        }
+        if self.revealed.len() + new.len() > self.max {
+            let scope = match self.window {
+                Some(window) => format!("every {} seconds", window.as_secs()),
+                None => "per session".to_string(),
+            };
+            return Err(anyhow::anyhow!(
+                "Refused to reveal {} more password(s), at most {} distinct passwords can be revealed {scope} and {} already were",
+                new.len(),
+                self.max,
+                self.revealed.len()
+            ));
+        }
+        self.revealed.extend(
+            new.into_iter()
+                .map(|(service, username)| (now, service.clone(), username.clone())),
+        );
+        Ok(())
+    }
+}
+
+/// Checks every entry in `entries` whose password is revealed by `command` against the reveal
+/// limit, if one was set, and appends a record of it to the audit log, if one was set. A record
+/// is a line of JSON with the time, the command, the service and the username, never the
+/// password. Fails when the limit is reached or the log can't be written, so that nothing is
+/// revealed beyond the limit or without being recorded.
+pub fn audit_reveals<'a>(
+    command: &str,
+    entries: impl IntoIterator<Item = &'a EntryView> + Clone,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    if let Some(reveal_limit) = &settings.reveal_limit {
+        reveal_limit
+            .borrow_mut()
+            .admit(entries.clone(), Instant::now())?;
+    }
+    match settings.audit_log.as_deref() {
+        Some(path) => append_audit_records(path, command, entries),
+        None => Ok(()),
+    }
+}
 
-use super::io::{print, MessageType, PromptPassword};
+fn append_audit_records<'a>(
+    path: &Path,
+    command: &str,
+    entries: impl IntoIterator<Item = &'a EntryView>,
+) -> anyhow::Result<()> {
+    let time = unix_now();
+    let mut records = String::new();
+    for entry in entries.into_iter().filter(|entry| entry.password.is_some()) {
+        let record = serde_json::json!({
+            "time": time,
+            "command": command,
+            "service": entry.service,
+            "username": entry.username,
+        });
+        records.push_str(&format!("{record}\n"));
+    }
+    if records.is_empty() {
+        return Ok(());
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(records.as_bytes()))
+        .map_err(|err| anyhow::anyhow!("Failed to write the audit log {}: {err}", path.display()))
+}
 
-pub fn copy_to_clipboard(password: String) -> anyhow::Result<()> {
+fn copy_to_native_clipboard(password: String) -> anyhow::Result<()> {
     let mut ctx =
         ClipboardContext::new().map_err(|_| anyhow::anyhow!("Unable to initialize clipboard"))?;
     ctx.set_contents(password)
@@ -16,24 +170,234 @@ pub fn copy_to_clipboard(password: String) -> anyhow::Result<()> {
     Ok(())
 }
 
-// TODO: Refactor this code to pass fewer arguments
-#[allow(clippy::too_many_arguments)]
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+fn copy_to_x11_clipboard(password: String) -> anyhow::Result<()> {
+    use copypasta::x11_clipboard::{Clipboard, X11ClipboardContext};
+    let mut ctx = X11ClipboardContext::<Clipboard>::new()
+        .map_err(|_| anyhow::anyhow!("Unable to initialize the X11 clipboard"))?;
+    ctx.set_contents(password)
+        .map_err(|_| anyhow::anyhow!("Unable to set X11 clipboard contents"))?;
+    Ok(())
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+)))]
+fn copy_to_x11_clipboard(_password: String) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!(
+        "The X11 clipboard is not supported on this platform"
+    ))
+}
+
+fn copy_to_wayland_clipboard(password: String) -> anyhow::Result<()> {
+    let mut child = std::process::Command::new("wl-copy")
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|_| anyhow::anyhow!("Unable to run wl-copy, is wl-clipboard installed?"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(password.as_bytes())?;
+    }
+    if !child.wait()?.success() {
+        return Err(anyhow::anyhow!("Unable to set Wayland clipboard contents"));
+    }
+    Ok(())
+}
+
+/// The OSC 52 escape sequence asks the terminal itself to set the clipboard, which works over SSH.
+pub fn osc52_sequence(password: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(password))
+}
+
+fn copy_to_osc52_clipboard(password: String) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "{}", osc52_sequence(&password))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+pub fn copy_to_clipboard(password: String, settings: &Settings) -> anyhow::Result<()> {
+    #[cfg(test)]
+    if let Some(fake_clipboard) = &settings.fake_clipboard {
+        fake_clipboard.borrow_mut().push(password);
+        return Ok(());
+    }
+    let backend = settings.clipboard_backend;
+    let clearing = password.is_empty();
+    match backend {
+        ClipboardBackend::Auto => copy_to_native_clipboard(password),
+        ClipboardBackend::X11 => copy_to_x11_clipboard(password),
+        ClipboardBackend::Wayland => copy_to_wayland_clipboard(password),
+        ClipboardBackend::Osc52 => copy_to_osc52_clipboard(password),
+    }?;
+    // OSC 52 sets the clipboard of the machine the terminal runs on, whose processes lockbox
+    // can't see
+    if !clearing && backend != ClipboardBackend::Osc52 {
+        warn_about_clipboard_managers(&mut std::io::stderr(), settings);
+    }
+    Ok(())
+}
+
+/// Warns once per run when a known clipboard manager is running, because it may keep a copied
+/// password in its history after lockbox clears the clipboard. This is best effort: when the
+/// running processes can't be listed, nothing is printed.
+fn warn_about_clipboard_managers<W: Write>(writer: &mut W, settings: &Settings) {
+    if !settings.clipboard_manager_warning.replace(false) {
+        return;
+    }
+    let process_names = running_process_names();
+    let managers = find_clipboard_managers(process_names.iter().map(String::as_str));
+    if managers.is_empty() {
+        return;
+    }
+    let managers: Vec<&str> = managers.into_iter().collect();
+    print(
+        writer,
+        &format!(
+            "Warning: {} may keep the copied password in its clipboard history, even after the clipboard is cleared. Pass --no-clipboard-manager-warning to hide this warning",
+            managers.join(", ")
+        ),
+        Some(MessageType::Warning),
+    );
+}
+
+/// The known clipboard managers among `process_names`, which may be paths and end with `.exe`.
+fn find_clipboard_managers<'a>(
+    process_names: impl IntoIterator<Item = &'a str>,
+) -> BTreeSet<&'static str> {
+    process_names
+        .into_iter()
+        .filter_map(|process_name| {
+            let name = process_name
+                .rsplit(['/', '\\'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase();
+            let name = name.strip_suffix(".exe").unwrap_or(&name);
+            CLIPBOARD_MANAGERS
+                .iter()
+                .find(|manager| **manager == name)
+                .copied()
+        })
+        .collect()
+}
+
+/// The names of the running processes, or none when they can't be listed.
+fn running_process_names() -> Vec<String> {
+    #[cfg(windows)]
+    let output = std::process::Command::new("tasklist")
+        .args(["/FO", "CSV", "/NH"])
+        .output();
+    #[cfg(not(windows))]
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-o", "comm="])
+        .output();
+    let stdout = match output {
+        Ok(output) if output.status.success() => output.stdout,
+        _ => return Vec::new(),
+    };
+    String::from_utf8_lossy(&stdout)
+        .lines()
+        // tasklist prints quoted CSV fields, starting with the image name
+        .map(|line| {
+            let name = line.split("\",\"").next().unwrap_or_default();
+            name.trim().trim_matches('"').to_string()
+        })
+        .collect()
+}
+
+/// How `add_password` gets the password of a new entry and what it stores with it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AddOptions {
+    /// The password, prompted for when it is neither given nor generated.
+    pub password: Option<String>,
+    /// Generates the password with these settings, see `--generate`.
+    pub password_generator: Option<PasswordGenerator>,
+    pub metadata: HashMap<String, String>,
+    pub favorite: bool,
+    /// Refuses the entry when the service already has one for the username, comparing service
+    /// names ignoring case.
+    pub ignore_case: bool,
+    /// Makes the entry expire this long from now.
+    pub ttl: Option<Duration>,
+    /// Prints the phonetic spelling of a generated password.
+    pub phonetic: bool,
+    /// Clears the password from the clipboard this long after `show` copied it.
+    pub clipboard_ttl: Option<Duration>,
+    /// Replaces the password of an existing entry with the generated one.
+    pub upsert: bool,
+    pub folder: Option<String>,
+}
+
 pub fn add_password<W: Write>(
     writer: &mut W,
     prompt_password: &dyn PromptPassword,
     password_store: &mut PasswordStore,
     service: String,
     username: Option<String>,
-    password: Option<String>,
-    generate: bool,
-    password_generator: PasswordGenerator,
+    options: AddOptions,
+    settings: &Settings,
 ) -> anyhow::Result<()> {
+    let AddOptions {
+        password,
+        password_generator,
+        metadata,
+        favorite,
+        ignore_case,
+        ttl,
+        phonetic,
+        clipboard_ttl,
+        upsert,
+        folder,
+    } = options;
     password_store.load()?; // load to check if master password is correct before generating password
-    let password = if generate {
-        let password = password_generator
-            .generate_one()
-            .unwrap_or_else(|_| panic!("{}", "Failed to generate password"));
-        match copy_to_clipboard(password.clone()) {
+    if ignore_case {
+        if let Some(existing) = password_store.find_ignoring_case(&service, username.as_deref()) {
+            return Err(anyhow::anyhow!(
+                "{} already has an entry{}, service names are compared ignoring case",
+                existing.service,
+                username
+                    .as_deref()
+                    .map(|username| format!(" for {username}"))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+    // never shadow an existing password with a generated one by accident
+    let replace_existing = password_generator.is_some()
+        && password_store
+            .find(service.clone(), username.clone())
+            .is_some();
+    if replace_existing && !upsert {
+        return Err(anyhow::anyhow!(
+            "{service} already has an entry{}, pass --upsert to replace its password with a generated one",
+            username
+                .as_deref()
+                .map(|username| format!(" for {username}"))
+                .unwrap_or_default()
+        ));
+    }
+    let password = if let Some(password_generator) = password_generator {
+        let password = password_generator.generate_one().map_err(|err| {
+            anyhow::anyhow!(
+                "Error generating password: {}",
+                describe_generator_error(&password_generator, err)
+            )
+        })?;
+        match copy_to_clipboard(password.clone(), settings) {
             Ok(_) => writeln!(writer, "Random password generated and copied to clipboard")?,
             Err(err) => {
                 writeln!(writer, "Random password generated")?;
@@ -44,49 +408,212 @@ pub fn add_password<W: Write>(
                 )?;
             }
         }
+        if phonetic {
+            writeln!(writer, "{}", phonetic_spelling(&password))?;
+        }
         password
     } else {
-        password.unwrap_or_else(|| read_hidden_input("password", prompt_password))
+        password.unwrap_or_else(|| {
+            read_hidden_input(
+                &format!("password for {service}"),
+                prompt_password,
+                settings,
+            )
+        })
     };
-    password_store.push(service, username, password)?.dump()?;
+    // in an append-only store the new entry supersedes the old one instead
+    if replace_existing && !password_store.is_append_only() {
+        password_store.set_password(&service, username.as_deref(), password);
+        password_store.save()?;
+        return Ok(());
+    }
+    let new_password = PasswordEntry::new(service, username, password)
+        .with_metadata(metadata)
+        .with_favorite(favorite)
+        .with_ttl(ttl)
+        .with_clipboard_ttl(clipboard_ttl)
+        .with_folder(folder.as_deref().unwrap_or_default())
+        .with_updated_at(unix_now());
+    password_store.append(new_password)?.save()?;
     Ok(())
 }
 
-pub fn generate_password<W: Write>(
-    writer: &mut W,
-    length: Length,
+/// Explains why `password_generator` failed, pointing out `--no-strict` when strict generation
+/// needs more characters than the password length.
+pub fn describe_generator_error(password_generator: &PasswordGenerator, err: &str) -> String {
+    let classes = [
+        password_generator.numbers,
+        password_generator.lowercase_letters,
+        password_generator.uppercase_letters,
+        password_generator.symbols,
+        password_generator.spaces,
+    ]
+    .iter()
+    .filter(|enabled| **enabled)
+    .count();
+    if password_generator.strict && classes > 0 && password_generator.length < classes {
+        format!(
+            "A strict password of length {} cannot contain all {} enabled kinds of characters. Use a longer length or --no-strict.",
+            password_generator.length, classes
+        )
+    } else {
+        err.to_string()
+    }
+}
+
+/// The longest password `generate --bits` picks before giving up on the entropy target.
+pub const MAX_ENTROPY_LENGTH: usize = 128;
+
+/// Returns the shortest password length whose entropy, drawing uniformly from the enabled
+/// kinds of characters, reaches `bits`.
+pub fn length_for_entropy(
+    bits: u32,
     symbols: bool,
     uppercase: bool,
     lowercase: bool,
     numbers: bool,
-    count: usize,
+) -> anyhow::Result<usize> {
+    let alphabet: usize = [
+        (lowercase, 26),
+        (uppercase, 26),
+        (numbers, 10),
+        (symbols, 32),
+    ]
+    .iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, size)| size)
+    .sum();
+    if alphabet == 0 {
+        return Err(anyhow::anyhow!(
+            "Enable at least one kind of characters to reach {bits} bits of entropy"
+        ));
+    }
+    let length = (f64::from(bits) / (alphabet as f64).log2()).ceil() as usize;
+    if length > MAX_ENTROPY_LENGTH {
+        return Err(anyhow::anyhow!(
+            "{bits} bits of entropy need a password of {length} characters, longer than the maximum of {MAX_ENTROPY_LENGTH}"
+        ));
+    }
+    Ok(length.max(1))
+}
+
+/// The length of the random part of a password of `length` characters that starts with `prefix`
+/// and ends with `suffix`. The affixes have to leave room for at least one random character.
+pub fn affixed_core_length(length: usize, prefix: &str, suffix: &str) -> anyhow::Result<usize> {
+    let affixes = prefix.chars().count() + suffix.chars().count();
+    if affixes >= length {
+        return Err(anyhow::anyhow!(
+            "The prefix and suffix take {affixes} of the {length} characters, use a longer length or shorter affixes"
+        ));
+    }
+    Ok(length - affixes)
+}
+
+/// The passwords that `generate_password` and `generate_seeded_passwords` generate. The default
+/// is a single strict password of 16 letters and numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateOptions {
+    /// The length of each password, `prefix` and `suffix` included.
+    pub length: usize,
+    pub symbols: bool,
+    pub uppercase: bool,
+    pub lowercase: bool,
+    pub numbers: bool,
+    /// Every password contains at least one character of each enabled kind.
+    pub strict: bool,
+    pub count: usize,
+    pub phonetic: bool,
+    pub prefix: String,
+    pub suffix: String,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> GenerateOptions {
+        GenerateOptions {
+            length: Length::Sixteen.get_val(),
+            symbols: false,
+            uppercase: true,
+            lowercase: true,
+            numbers: true,
+            strict: true,
+            count: 1,
+            phonetic: false,
+            prefix: String::new(),
+            suffix: String::new(),
+        }
+    }
+}
+
+impl GenerateOptions {
+    /// The generator of the random part of the passwords, between the prefix and the suffix.
+    fn password_generator(&self) -> anyhow::Result<PasswordGenerator> {
+        Ok(PasswordGenerator::new()
+            .length(affixed_core_length(
+                self.length,
+                &self.prefix,
+                &self.suffix,
+            )?)
+            .lowercase_letters(self.lowercase)
+            .uppercase_letters(self.uppercase)
+            .numbers(self.numbers)
+            .symbols(self.symbols)
+            .strict(self.strict))
+    }
+}
+
+pub fn generate_password<W: Write>(
+    writer: &mut W,
+    options: &GenerateOptions,
+    clipboard: Option<Duration>,
+    settings: &Settings,
 ) -> anyhow::Result<()> {
-    let password_generator = PasswordGenerator::new()
-        .length(length.get_val())
-        .lowercase_letters(lowercase)
-        .uppercase_letters(uppercase)
-        .numbers(numbers)
-        .symbols(symbols)
-        .strict(true);
+    let GenerateOptions {
+        count,
+        phonetic,
+        ref prefix,
+        ref suffix,
+        ..
+    } = *options;
+    let password_generator = options.password_generator()?;
+    if let Some(clear_after) = clipboard {
+        check_single_clipboard_value(count)?;
+        let password = password_generator.generate_one().map_err(|err| {
+            anyhow::anyhow!(
+                "Error generating password: {}",
+                describe_generator_error(&password_generator, err)
+            )
+        })?;
+        let password = format!("{prefix}{password}{suffix}");
+        return copy_generated_to_clipboard(writer, password, clear_after, settings);
+    }
     writeln!(writer)?;
     if count > 1 {
-        match password_generator.generate(count) {
+        // write each password as it is generated instead of collecting them, so that a large
+        // count does not have to fit in memory
+        match password_generator.try_iter() {
             Ok(passwords) => {
-                for password in passwords {
+                for password in passwords.take(count) {
+                    let password = format!("{prefix}{password}{suffix}");
                     print(writer, &password, Some(MessageType::Success));
+                    print_phonetic(writer, &password, phonetic);
                 }
             }
             Err(err) => print(
                 writer,
-                &format!("Error generating password: {err}"),
+                &format!(
+                    "Error generating password: {}",
+                    describe_generator_error(&password_generator, err)
+                ),
                 Some(MessageType::Error),
             ),
         }
     } else {
         match password_generator.generate_one() {
             Ok(password) => {
+                let password = format!("{prefix}{password}{suffix}");
                 print(writer, &password, Some(MessageType::Success));
-                match copy_to_clipboard(password) {
+                print_phonetic(writer, &password, phonetic);
+                match copy_to_clipboard(password, settings) {
                   Ok(_) => print(
                         writer,
                         "(Random password generated. Copied to clipboard)",
@@ -99,7 +626,10 @@ pub fn generate_password<W: Write>(
             }
             Err(err) => print(
                 writer,
-                &format!("Error generating password: {}", err),
+                &format!(
+                    "Error generating password: {}",
+                    describe_generator_error(&password_generator, err)
+                ),
                 Some(MessageType::Error),
             ),
         }
@@ -107,152 +637,1507 @@ pub fn generate_password<W: Write>(
     Ok(())
 }
 
-pub fn show_password<W: Write>(
+/// Whether a character belongs to a kind of characters, e.g. `char::is_ascii_digit`.
+type CharacterClass = fn(&char) -> bool;
+
+/// The characters of each kind that `password_generator` draws from: the printable ASCII
+/// characters of every enabled class, the same that `PasswordGenerator` uses.
+fn character_kinds(password_generator: &PasswordGenerator) -> Vec<Vec<char>> {
+    let classes: [(bool, CharacterClass); 4] = [
+        (
+            password_generator.lowercase_letters,
+            char::is_ascii_lowercase,
+        ),
+        (
+            password_generator.uppercase_letters,
+            char::is_ascii_uppercase,
+        ),
+        (password_generator.numbers, char::is_ascii_digit),
+        (password_generator.symbols, char::is_ascii_punctuation),
+    ];
+    classes
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, class)| ('!'..='~').filter(class).collect())
+        .collect()
+}
+
+/// Generates passwords like `password_generator`, but from a ChaCha20 RNG seeded with `seed`, so
+/// that the same seed always yields the same passwords. Anyone who knows the seed can reproduce
+/// them: this is for tests and demos only, never for real secrets. Fails like
+/// `PasswordGenerator::try_iter` for settings it can't generate passwords with.
+fn seeded_passwords(
+    password_generator: &PasswordGenerator,
+    seed: u64,
+) -> Result<impl Iterator<Item = String>, &'static str> {
+    password_generator.try_iter()?;
+    let kinds = character_kinds(password_generator);
+    let pool: Vec<char> = kinds.concat();
+    let length = password_generator.length;
+    let strict = password_generator.strict;
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    Ok(std::iter::from_fn(move || loop {
+        let password: String = (0..length)
+            .map(|_| pool[rng.gen_range(0..pool.len())])
+            .collect();
+        // like `PasswordGenerator`, strict passwords are drawn again until they contain every
+        // enabled kind of characters
+        if !strict
+            || kinds
+                .iter()
+                .all(|kind| password.chars().any(|c| kind.contains(&c)))
+        {
+            return Some(password);
+        }
+    }))
+}
+
+/// Like `generate_password`, but the passwords are reproduced from `seed`, see `seeded_passwords`.
+/// They are never copied to the clipboard, and a warning that they are not secret comes first.
+pub fn generate_seeded_passwords<W: Write>(
     writer: &mut W,
-    password_store: &mut PasswordStore,
-    service: String,
-    username: Option<String>,
+    options: &GenerateOptions,
+    seed: u64,
+) -> anyhow::Result<()> {
+    let GenerateOptions {
+        count,
+        phonetic,
+        ref prefix,
+        ref suffix,
+        ..
+    } = *options;
+    let password_generator = options.password_generator()?;
+    let passwords = seeded_passwords(&password_generator, seed).map_err(|err| {
+        anyhow::anyhow!(
+            "Error generating password: {}",
+            describe_generator_error(&password_generator, err)
+        )
+    })?;
+    print(
+        writer,
+        "Warning: passwords generated with --seed can be reproduced by anyone who knows the seed. Use them for tests and demos only, never as real secrets",
+        Some(MessageType::Warning),
+    );
+    for password in passwords.take(count) {
+        let password = format!("{prefix}{password}{suffix}");
+        print(writer, &password, Some(MessageType::Success));
+        print_phonetic(writer, &password, phonetic);
+    }
+    Ok(())
+}
+
+/// Prints the phonetic spelling of a generated `value` under it when `phonetic` is set.
+fn print_phonetic<W: Write>(writer: &mut W, value: &str, phonetic: bool) {
+    if phonetic {
+        print(writer, &phonetic_spelling(value), Some(MessageType::Info));
+    }
+}
+
+/// Refuses a `count` above `max_count` before anything is generated.
+pub fn check_count(count: usize, max_count: usize) -> anyhow::Result<()> {
+    if count > max_count {
+        return Err(anyhow::anyhow!(
+            "--count {count} is more than the limit of {max_count}, pass --max-count to raise it"
+        ));
+    }
+    Ok(())
+}
+
+fn check_single_clipboard_value(count: usize) -> anyhow::Result<()> {
+    if count > 1 {
+        return Err(anyhow::anyhow!(
+            "--clipboard copies a single value and can't be combined with --count {count}"
+        ));
+    }
+    Ok(())
+}
+
+/// Copies a generated `value` to the clipboard without printing it, so that it never shows up in
+/// the terminal. Unless `clear_after` is zero, waits that long and then empties the clipboard.
+/// Waiting also keeps the value available on X11, where lockbox owns the clipboard contents. On a
+/// terminal the seconds left are counted down, and Ctrl-C clears the clipboard right away.
+pub fn copy_generated_to_clipboard<W: Write>(
+    writer: &mut W,
+    value: String,
+    clear_after: Duration,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    copy_to_clipboard(value, settings)
+        .map_err(|err| anyhow::anyhow!("Failed to copy to clipboard: {err}"))?;
+    if clear_after.is_zero() {
+        print(writer, "Copied to clipboard", Some(MessageType::Success));
+        return Ok(());
+    }
+    print(
+        writer,
+        &format!(
+            "Copied to clipboard. It will be cleared in {} seconds",
+            clear_after.as_secs()
+        ),
+        Some(MessageType::Success),
+    );
+    clear_clipboard_after(writer, clear_after, settings)
+}
+
+/// Waits `clear_after`, counting down on a terminal, and then empties the clipboard.
+fn clear_clipboard_after<W: Write>(
+    writer: &mut W,
+    clear_after: Duration,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    writer.flush()?;
+    wait_with_countdown(clear_after, |seconds| {
+        format!("Clipboard clears in {seconds}s... Ctrl-C to clear now")
+    });
+    copy_to_clipboard(String::new(), settings)
+        .map_err(|err| anyhow::anyhow!("Failed to clear the clipboard: {err}"))?;
+    print(writer, "Clipboard cleared", Some(MessageType::Info));
+    Ok(())
+}
+
+pub fn encode_token(bytes: &[u8], format: GenerateFormat) -> anyhow::Result<String> {
+    match format {
+        GenerateFormat::Hex => Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect()),
+        GenerateFormat::Base64 => Ok(STANDARD.encode(bytes)),
+        GenerateFormat::Base64Url => Ok(URL_SAFE_NO_PAD.encode(bytes)),
+        GenerateFormat::Password => Err(anyhow::anyhow!("{format} is not a token format")),
+    }
+}
+
+pub fn generate_token<W: Write>(
+    writer: &mut W,
+    format: GenerateFormat,
+    bytes: usize,
+    count: usize,
+    clipboard: Option<Duration>,
+    phonetic: bool,
+    settings: &Settings,
 ) -> anyhow::Result<()> {
-    let password = password_store.load()?.find(service, username);
-    if let Some(password) = password {
-        password.print_password(writer, Some(MessageType::Info));
-        match password.copy_password() {
-            Ok(_) => print(writer, "(Password copied to clipboard)", None),
+    if bytes == 0 {
+        return Err(anyhow::anyhow!(
+            "The number of bytes must be greater than 0"
+        ));
+    }
+    if let Some(clear_after) = clipboard {
+        check_single_clipboard_value(count)?;
+        let token = encode_token(&get_random_bytes(bytes), format)?;
+        return copy_generated_to_clipboard(writer, token, clear_after, settings);
+    }
+    writeln!(writer)?;
+    let mut last_token = None;
+    for _ in 0..count {
+        let token = encode_token(&get_random_bytes(bytes), format)?;
+        print(writer, &token, Some(MessageType::Success));
+        print_phonetic(writer, &token, phonetic);
+        last_token = Some(token);
+    }
+    if let (1, Some(token)) = (count, last_token) {
+        match copy_to_clipboard(token, settings) {
+            Ok(_) => print(
+                writer,
+                "(Random token generated. Copied to clipboard)",
+                None,
+            ),
             Err(err) => print(
                 writer,
-                &format!(
-                    "(Random password generated. Failed to copy password to clipboard: {err})"
-                ),
+                &format!("(Random token generated. Failed to copy token to clipboard: {err})"),
                 Some(MessageType::Warning),
             ),
         }
-    } else {
-        writeln!(writer, "Password not found")?;
     }
     Ok(())
 }
 
-pub fn list_passwords<W: Write>(
+/// Resolves `pattern` to a stored service with `PasswordStore::fuzzy_services`, asking which one
+/// is meant when several services match equally well.
+pub fn find_service_fuzzy<R: BufRead, W: Write>(
+    reader: &mut R,
     writer: &mut W,
+    password_store: &mut PasswordStore,
+    pattern: &str,
+) -> anyhow::Result<String> {
+    let mut services = password_store.load()?.fuzzy_services(pattern);
+    match services.len() {
+        0 => Err(anyhow::anyhow!(
+            "No service matches `{pattern}` closely enough"
+        )),
+        1 => Ok(services.remove(0)),
+        _ => {
+            let candidates: Vec<String> = services
+                .iter()
+                .enumerate()
+                .map(|(index, service)| {
+                    format!(
+                        "[{}] {}",
+                        colorize(&bold(&(index + 1).to_string()), MessageType::Success),
+                        service
+                    )
+                })
+                .collect();
+            writeln!(
+                writer,
+                "Several services match `{pattern}`: {}",
+                candidates.join(" ")
+            )?;
+            let input = read_terminal_input(reader, writer, None);
+            input
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| services.get(number.wrapping_sub(1)).cloned())
+                .or_else(|| services.iter().find(|service| **service == input).cloned())
+                .ok_or_else(|| anyhow::anyhow!("No service selected"))
+        }
+    }
+}
+
+/// Loads the store and returns the entries matching `filter` in listing order, numbered as by
+/// `list --show-index`. Passwords are only included with `show_passwords`, or for the entries of
+/// `reveal_services`.
+pub fn list_entries(
     password_store: &mut PasswordStore,
     show_passwords: bool,
-) -> anyhow::Result<()> {
-    password_store
+    reveal_services: &[String],
+    filter: &EntryFilter,
+) -> anyhow::Result<Vec<EntryView>> {
+    Ok(password_store
         .load()?
-        .print(writer, show_passwords, Some(MessageType::Info));
-    Ok(())
+        .indexed_entries(filter)
+        .into_iter()
+        .map(|(index, password)| {
+            let reveal = show_passwords || reveal_services.contains(&password.service);
+            password.view(reveal).with_index(Some(index))
+        })
+        .collect())
 }
 
-pub fn remove_password<W: Write>(
+/// Loads the store and returns the entry for `service` and `username` with its password.
+pub fn find_entry(
+    password_store: &mut PasswordStore,
+    service: &str,
+    username: Option<&str>,
+    settings: &Settings,
+) -> anyhow::Result<Option<EntryView>> {
+    password_store.load()?;
+    find_loaded_entry(password_store, service, username, settings)
+}
+
+/// Like [`find_entry`], for a store that is already loaded.
+fn find_loaded_entry(
+    password_store: &PasswordStore,
+    service: &str,
+    username: Option<&str>,
+    settings: &Settings,
+) -> anyhow::Result<Option<EntryView>> {
+    password_store.check_unambiguous(service, username)?;
+    let entry = password_store
+        .find(service.to_string(), username.map(String::from))
+        .map(|password| password.view(true));
+    audit_reveals("show", &entry, settings)?;
+    Ok(entry)
+}
+
+/// Loads the store and returns the entry at `index` of the listing printed by
+/// `list --show-index`, with its password.
+pub fn find_entry_at_index(
+    password_store: &mut PasswordStore,
+    index: usize,
+    settings: &Settings,
+) -> anyhow::Result<Option<EntryView>> {
+    let entry = password_store
+        .load()?
+        .find_by_index(index)
+        .map(|password| password.view(true).with_index(Some(index)));
+    audit_reveals("show", &entry, settings)?;
+    Ok(entry)
+}
+
+/// Shows an entry in `format`. Only the entry is printed to `writer`; notes about copying the
+/// password to the clipboard go to `messages`, so that `writer` only ever carries credentials.
+pub fn show_password<W: Write, M: Write>(
     writer: &mut W,
+    messages: &mut M,
     password_store: &mut PasswordStore,
     service: String,
     username: Option<String>,
+    format: ShowFormat,
+    settings: &Settings,
 ) -> anyhow::Result<()> {
-    password_store
-        .load()?
-        .pop(writer, service, username)
-        .dump()?;
-    Ok(())
+    show_passwords(
+        writer,
+        messages,
+        password_store,
+        &[service],
+        username,
+        format,
+        settings,
+    )
 }
 
-pub fn update_master_password<W: Write>(
+/// Shows the entry of every service in `services`, in order, loading the store only once. The
+/// same `username` is used for all of them, and the first failed lookup stops the rest.
+pub fn show_passwords<W: Write, M: Write>(
     writer: &mut W,
-    new_master_password: String,
+    messages: &mut M,
     password_store: &mut PasswordStore,
+    services: &[String],
+    username: Option<String>,
+    format: ShowFormat,
+    settings: &Settings,
 ) -> anyhow::Result<()> {
-    password_store
-        .load()?
-        .update_master(new_master_password)
-        .dump()?;
-    print(
-        writer,
-        "Master password updated successfully",
-        Some(MessageType::Success),
-    );
+    password_store.load()?;
+    // one clipboard can't hold several passwords, and their countdowns would run one after another
+    let several = services.len() > 1;
+    for (position, service) in services.iter().enumerate() {
+        match find_loaded_entry(password_store, service, username.as_deref(), settings)? {
+            Some(entry) if several && format == ShowFormat::Text => {
+                entry.print_details(writer, Some(MessageType::Info))
+            }
+            Some(entry) => {
+                // `type` prints no trailing newline, so the entries would run together
+                if position > 0 && format == ShowFormat::Type {
+                    writeln!(writer)?;
+                }
+                print_entry(writer, messages, entry, format, settings)?
+            }
+            None => {
+                let not_found = password_store.not_found_message(service, username.as_deref());
+                return Err(NotFound(not_found).into());
+            }
+        }
+    }
+    if several && format == ShowFormat::Text {
+        print(
+            messages,
+            "(Passwords are not copied to the clipboard when showing several entries)",
+            None,
+        );
+    }
     Ok(())
 }
 
-#[cfg(test)]
-mod test {
-    use crate::{cli::io::MockPromptPassword, pass::PasswordEntry};
+/// Loads the store and returns the entry whose `url` metadata has the host of `url`, with its
+/// password.
+pub fn find_entry_by_url(
+    password_store: &mut PasswordStore,
+    url: &str,
+    settings: &Settings,
+) -> anyhow::Result<Option<EntryView>> {
+    let entry = password_store
+        .load()?
+        .find_by_url(url)?
+        .map(|password| password.view(true));
+    audit_reveals("show", &entry, settings)?;
+    Ok(entry)
+}
 
-    use super::*;
-    use passwords::PasswordGenerator;
-    use rstest::rstest;
-    use tempfile::NamedTempFile;
+/// Shows the entry whose `url` metadata has the host of `url`, e.g. `github.com` for an entry
+/// added with `--meta url=https://www.github.com/login`.
+pub fn show_password_by_url<W: Write, M: Write>(
+    writer: &mut W,
+    messages: &mut M,
+    password_store: &mut PasswordStore,
+    url: &str,
+    format: ShowFormat,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    match find_entry_by_url(password_store, url, settings)? {
+        Some(entry) => print_entry(writer, messages, entry, format, settings)?,
+        None => {
+            return Err(NotFound(format!("No entry has a URL on {}", normalized_host(url))).into())
+        }
+    }
+    Ok(())
+}
 
-    #[rstest]
-    #[case("service1".to_string(), Some("username1".to_string()), Some("password1"), false)]
-    #[case("service2".to_string(), None, Some("password2"), false)]
-    #[case("service3".to_string(), Some("username3".to_string()), None, true)]
-    fn test_add_password(
-        #[case] service: String,
-        #[case] username: Option<String>,
-        #[case] password: Option<&str>,
-        #[case] generate: bool,
-    ) {
-        let password_generator = PasswordGenerator::new()
-            .length(10)
-            .lowercase_letters(true)
-            .uppercase_letters(true)
-            .numbers(true)
-            .symbols(true)
-            .strict(true);
-        let master = "master_password".to_string();
-        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let output = Vec::new();
-        let mut writer = std::io::Cursor::new(output);
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
-        let mock_prompt_password = &MockPromptPassword::new();
-        let result = add_password(
-            &mut writer,
-            mock_prompt_password,
-            &mut password_store,
-            service.clone(),
-            username.clone(),
-            password.map(|s| s.to_string()),
-            generate,
-            password_generator,
-        );
-        assert!(result.is_ok());
-        assert!(password_store.find(service, username).is_some());
+/// Shows the entry at `index` of the listing printed by `list --show-index`.
+pub fn show_password_at_index<W: Write, M: Write>(
+    writer: &mut W,
+    messages: &mut M,
+    password_store: &mut PasswordStore,
+    index: usize,
+    format: ShowFormat,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    match find_entry_at_index(password_store, index, settings)? {
+        Some(entry) => print_entry(writer, messages, entry, format, settings)?,
+        None => return Err(NotFound(format!("No entry at index {index}")).into()),
+    }
+    Ok(())
+}
+
+/// The error of a command when the entry it acts on does not exist. `show`, `remove` and `edit`
+/// report it as a warning and exit with `EXIT_NOT_FOUND`, so that scripts can tell it from other
+/// failures.
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// Prints `entry` in `format` to `writer`, and notes such as the clipboard copy to `messages`.
+pub fn print_entry<W: Write, M: Write>(
+    writer: &mut W,
+    messages: &mut M,
+    entry: EntryView,
+    format: ShowFormat,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    let username = entry.username.as_deref().unwrap_or_default();
+    let password = entry.password.as_deref().unwrap_or_default();
+    match format {
+        ShowFormat::Text => {
+            entry.print_details(writer, Some(MessageType::Info));
+            match copy_to_clipboard(password.to_string(), settings) {
+                // the entry's own clipboard lifetime, set with `add --clipboard-ttl`
+                Ok(_) => match entry.clipboard_ttl.filter(|ttl| !ttl.is_zero()) {
+                    Some(clear_after) => {
+                        print(
+                            messages,
+                            &format!(
+                                "(Password copied to clipboard. It will be cleared in {} seconds)",
+                                clear_after.as_secs()
+                            ),
+                            None,
+                        );
+                        writer.flush()?;
+                        clear_clipboard_after(messages, clear_after, settings)?;
+                    }
+                    None => print(messages, "(Password copied to clipboard)", None),
+                },
+                Err(err) => print(
+                    messages,
+                    &format!("(Failed to copy password to clipboard: {err})"),
+                    Some(MessageType::Warning),
+                ),
+            }
+        }
+        ShowFormat::Lines => writeln!(writer, "{username}\n{password}")?,
+        // without a username there is no field to tab out of
+        ShowFormat::Type if username.is_empty() => write!(writer, "{password}")?,
+        ShowFormat::Type => write!(writer, "{username}\t{password}")?,
+    }
+    Ok(())
+}
+
+/// Which fields `get_fields` prints and how they are joined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetOptions {
+    pub fields: Vec<String>,
+    pub separator: String,
+    /// Leaves out the newline after the values.
+    pub no_newline: bool,
+}
+
+impl Default for GetOptions {
+    fn default() -> GetOptions {
+        GetOptions {
+            fields: vec!["password".to_string()],
+            separator: "\t".to_string(),
+            no_newline: false,
+        }
+    }
+}
+
+/// Prints the raw values of the fields of a password entry joined by the separator, without
+/// colors or labels, for scripts, e.g. the username and the password separated by a tab for form
+/// filling.
+pub fn get_fields<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    service: String,
+    username: Option<String>,
+    options: &GetOptions,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    let GetOptions {
+        fields,
+        separator,
+        no_newline,
+    } = options;
+    let not_found = password_store
+        .load()?
+        .not_found_message(&service, username.as_deref());
+    password_store.check_unambiguous(&service, username.as_deref())?;
+    let password = password_store
+        .find(service, username)
+        .ok_or_else(|| anyhow::anyhow!("{not_found}"))?;
+    let values = fields
+        .iter()
+        .map(|field| {
+            password
+                .field(field)
+                .ok_or_else(|| anyhow::anyhow!("Field `{field}` not found"))
+        })
+        .collect::<anyhow::Result<Vec<&str>>>()?;
+    if fields.iter().any(|field| field == "password") {
+        audit_reveals("get", [&password.view(true)], settings)?;
+    }
+    let value = values.join(separator);
+    if *no_newline {
+        write!(writer, "{value}")?;
+    } else {
+        writeln!(writer, "{value}")?;
+    }
+    Ok(())
+}
+
+/// How `list_passwords` prints the entries that pass its `EntryFilter`. Passwords are only shown
+/// with `show_passwords`, or for the services in `reveal_services`, and at most `limit` entries
+/// are printed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOptions {
+    pub show_passwords: bool,
+    pub reveal_services: Vec<String>,
+    /// Prints each service once instead of the entries.
+    pub services_only: bool,
+    pub limit: Option<usize>,
+    pub show_index: bool,
+    pub table: bool,
+    pub tree: bool,
+    pub format: ListFormat,
+}
+
+impl Default for ListOptions {
+    fn default() -> ListOptions {
+        ListOptions {
+            show_passwords: false,
+            reveal_services: vec![],
+            services_only: false,
+            limit: None,
+            show_index: false,
+            table: false,
+            tree: false,
+            format: ListFormat::Text,
+        }
+    }
+}
+
+pub fn list_passwords<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    filter: &EntryFilter,
+    options: &ListOptions,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    let ListOptions {
+        show_passwords,
+        ref reveal_services,
+        services_only,
+        limit,
+        show_index,
+        table,
+        tree,
+        format,
+    } = *options;
+    let entries = list_entries(password_store, show_passwords, reveal_services, filter)?;
+    let take = limit.unwrap_or(usize::MAX);
+    if services_only {
+        let services: BTreeSet<&str> = entries.iter().map(|entry| entry.service.as_str()).collect();
+        if format == ListFormat::Ndjson {
+            for service in services.into_iter().take(take) {
+                writeln!(writer, "{}", serde_json::json!({ "service": service }))?;
+            }
+            return Ok(());
+        }
+        if services.is_empty() {
+            print(
+                writer,
+                password_store.empty_message(),
+                Some(MessageType::Info),
+            );
+        }
+        let total = services.len();
+        for service in services.into_iter().take(take) {
+            print(writer, service, Some(MessageType::Info));
+        }
+        print_remaining(writer, total, limit);
+        return Ok(());
+    }
+    let total = entries.len();
+    let entries: Vec<EntryView> = entries
+        .into_iter()
+        .take(take)
+        .map(|entry| {
+            let index = entry.index.filter(|_| show_index);
+            entry.with_index(index)
+        })
+        .collect();
+    audit_reveals("list", &entries, settings)?;
+    if table && !entries.is_empty() {
+        print_table(writer, &entries);
+    } else if tree {
+        print_tree(writer, &entries);
+    } else {
+        for entry in entries {
+            match format {
+                ListFormat::Ndjson => writeln!(writer, "{}", entry.to_json())?,
+                ListFormat::Text => entry.print(writer, Some(MessageType::Info)),
+            }
+        }
+    }
+    if format == ListFormat::Text {
+        if total == 0 {
+            print(
+                writer,
+                password_store.empty_message(),
+                Some(MessageType::Info),
+            );
+        }
+        print_remaining(writer, total, limit);
+    }
+    Ok(())
+}
+
+pub fn remove_password<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    service: String,
+    username: Option<String>,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    password_store.check_unambiguous(&service, username.as_deref())?;
+    let not_found = password_store.not_found_message(&service, username.as_deref());
+    if password_store.remove(service, username).is_none() {
+        return Err(NotFound(not_found).into());
+    }
+    password_store.save()?;
+    print(writer, "Password deleted", Some(MessageType::Success));
+    Ok(())
+}
+
+/// Removes every entry last updated more than `age` ago. Unless `yes` is set, the entries are
+/// listed first and only removed once the user confirms. Entries of unknown age are kept.
+pub fn remove_passwords_older_than<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    age: Duration,
+    yes: bool,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    let stale = password_store.older_than(age);
+    if stale.is_empty() {
+        print(writer, "No entries to remove", Some(MessageType::Info));
+        return Ok(());
+    }
+    if !yes {
+        for entry in &stale {
+            entry.view(false).print(writer, Some(MessageType::Info));
+        }
+        let prompt = format!("Remove these {} entries? Type yes to confirm", stale.len());
+        let input = read_terminal_input(reader, writer, Some(&prompt));
+        if !matches!(input.as_str(), "y" | "yes") {
+            print(writer, "Nothing was removed", Some(MessageType::Warning));
+            return Ok(());
+        }
+    }
+    let removed = password_store.remove_older_than(age);
+    password_store.save()?;
+    print(
+        writer,
+        &format!("Removed {removed} password(s)"),
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+/// Removes the entry at `index` of the listing printed by `list --show-index`.
+pub fn remove_password_at_index<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    index: usize,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    if password_store.remove_by_index(index).is_none() {
+        return Err(NotFound(format!("No entry at index {index}")).into());
+    }
+    password_store.save()?;
+    print(writer, "Password deleted", Some(MessageType::Success));
+    Ok(())
+}
+
+/// Disables or enables the entry for `service` and `username`, see `PasswordEntry::disabled`.
+pub fn set_password_disabled<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    service: String,
+    username: Option<String>,
+    disabled: bool,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    password_store.check_unambiguous(&service, username.as_deref())?;
+    if !password_store.set_disabled(&service, username.as_deref(), disabled) {
+        let not_found = password_store.not_found_message(&service, username.as_deref());
+        return Err(NotFound(not_found).into());
+    }
+    password_store.save()?;
+    print_disabled(writer, disabled);
+    Ok(())
+}
+
+/// Disables or enables the entry at `index` of the listing printed by `list --show-index`.
+pub fn set_password_disabled_at_index<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    index: usize,
+    disabled: bool,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    if !password_store.set_disabled_by_index(index, disabled) {
+        return Err(NotFound(format!("No entry at index {index}")).into());
+    }
+    password_store.save()?;
+    print_disabled(writer, disabled);
+    Ok(())
+}
+
+fn print_disabled<W: Write>(writer: &mut W, disabled: bool) {
+    let message = if disabled {
+        "Password disabled"
+    } else {
+        "Password enabled"
+    };
+    print(writer, message, Some(MessageType::Success));
+}
+
+/// Prints the fingerprint of the encryption key, to check that two copies of a store are opened
+/// with the same master password without revealing it.
+pub fn print_key_fingerprint<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+) -> anyhow::Result<()> {
+    let fingerprint = password_store.load()?.key_fingerprint()?;
+    print(
+        writer,
+        &format!("Key fingerprint: {fingerprint}"),
+        Some(MessageType::Info),
+    );
+    Ok(())
+}
+
+/// Derives a key `runs` times with the parameters stores are opened with and prints the average
+/// time taken.
+pub fn bench_kdf<W: Write>(writer: &mut W, runs: u32) {
+    let salt = get_random_salt();
+    let total: Duration = (0..runs)
+        .map(|_| time_key_derivation("lockbox benchmark", &salt))
+        .sum();
+    print(
+        writer,
+        &format!(
+            "Key derivation with PBKDF2-HMAC-SHA256 and {KDF_ITERATIONS} iterations took {}ms on average over {runs} run{}",
+            (total / runs).as_millis(),
+            if runs == 1 { "" } else { "s" }
+        ),
+        Some(MessageType::Info),
+    );
+}
+
+const SELF_TEST_MASTER_PASSWORD: &str = "lockbox self-test";
+const SELF_TEST_SERVICE: &str = "self-test";
+const SELF_TEST_PASSWORD: &str = "self-test password";
+
+/// Runs a throwaway store through every cipher: create it, add an entry, save it, reopen it and
+/// decrypt the entry. Each step is printed as passed or failed. Nothing touches the disk.
+pub fn self_test<W: Write>(writer: &mut W) -> anyhow::Result<()> {
+    let failed = Cipher::value_variants()
+        .iter()
+        .filter(|cipher| self_test_cipher(writer, **cipher).is_none())
+        .count();
+    if failed > 0 {
+        return Err(anyhow::anyhow!("Self-test failed"));
+    }
+    print(writer, "Self-test passed", Some(MessageType::Success));
+    Ok(())
+}
+
+fn self_test_cipher<W: Write>(writer: &mut W, cipher: Cipher) -> Option<()> {
+    let open = |contents: &[u8], master_password: &str| {
+        let options = StoreOptions {
+            cipher,
+            ..Default::default()
+        };
+        let mut password_store =
+            PasswordStore::from_reader(&mut &contents[..], master_password.to_string(), options)?;
+        password_store.load()?;
+        Ok::<_, StoreError>(password_store)
+    };
+    let mut password_store = report_step(
+        writer,
+        cipher,
+        "create a store",
+        open(b"", SELF_TEST_MASTER_PASSWORD),
+    )?;
+    let mut contents = Vec::new();
+    let saved = password_store
+        .push(
+            SELF_TEST_SERVICE.to_string(),
+            None,
+            SELF_TEST_PASSWORD.to_string(),
+            HashMap::new(),
+            false,
+        )
+        .and_then(|password_store| Ok(password_store.save()?.write_to(&mut contents)?));
+    report_step(writer, cipher, "add an entry and save the store", saved)?;
+    let reopened = report_step(
+        writer,
+        cipher,
+        "reopen the saved store",
+        open(&contents, SELF_TEST_MASTER_PASSWORD),
+    )?;
+    report_step(writer, cipher, "decrypt the entry", {
+        match reopened
+            .find(SELF_TEST_SERVICE.to_string(), None)
+            .and_then(|entry| entry.field("password"))
+        {
+            Some(SELF_TEST_PASSWORD) => Ok(()),
+            Some(_) => Err(anyhow::anyhow!("the decrypted password differs")),
+            None => Err(anyhow::anyhow!("the entry is missing")),
+        }
+    })?;
+    report_step(writer, cipher, "reject a wrong master password", {
+        match open(&contents, "wrong master password") {
+            Err(StoreError::WrongMasterPassword) => Ok(()),
+            Err(err) => Err(err.into()),
+            Ok(_) => Err(anyhow::anyhow!("the store opened")),
+        }
+    })
+}
+
+fn report_step<W: Write, T, E: Into<anyhow::Error>>(
+    writer: &mut W,
+    cipher: Cipher,
+    step: &str,
+    result: Result<T, E>,
+) -> Option<T> {
+    match result {
+        Ok(value) => {
+            print(
+                writer,
+                &format!("[PASS] {cipher}: {step}"),
+                Some(MessageType::Success),
+            );
+            Some(value)
+        }
+        Err(err) => {
+            let err: anyhow::Error = err.into();
+            print(
+                writer,
+                &format!("[FAIL] {cipher}: {step}: {err}"),
+                Some(MessageType::Error),
+            );
+            None
+        }
+    }
+}
+
+pub fn dedup_passwords<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    let report = password_store.dedup();
+    if report.pruned > 0 {
+        password_store.save()?;
+    }
+    print(
+        writer,
+        &format!("Removed {} duplicate password(s)", report.pruned),
+        Some(MessageType::Success),
+    );
+    for (service, username) in report.near_duplicates {
+        let entry = match username {
+            Some(username) => format!("{service} ({username})"),
+            None => service,
+        };
+        print(
+            writer,
+            &format!("{entry} has multiple different passwords, please review them manually"),
+            Some(MessageType::Warning),
+        );
+    }
+    Ok(())
+}
+
+/// Removes the entries whose TTL has passed, see `add --ttl`.
+pub fn gc_passwords<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    let removed = password_store.gc();
+    if removed > 0 {
+        password_store.save()?;
+    }
+    print(
+        writer,
+        &format!("Removed {removed} expired password(s)"),
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+/// Renames the service of every entry of `from` to `to`, e.g. when a provider rebrands. Nothing
+/// is renamed when `to` already has entries for some of the usernames of `from`.
+pub fn rename_service<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    from: &str,
+    to: &str,
+) -> anyhow::Result<()> {
+    password_store.load()?.check_mutable()?;
+    if from == to {
+        return Err(anyhow::anyhow!("The service is already named {to}"));
+    }
+    let collisions = password_store.rename_collisions(from, to);
+    if !collisions.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{to} already has entries for {}, remove or rename them first",
+            collisions.join(", ")
+        ));
+    }
+    let renamed = password_store.rename_service(from, to);
+    if renamed == 0 {
+        print(
+            writer,
+            &format!("No passwords stored for {from}"),
+            Some(MessageType::Warning),
+        );
+        return Ok(());
+    }
+    password_store.save()?;
+    print(
+        writer,
+        &format!("Renamed {renamed} password(s) from {from} to {to}"),
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+/// New master passwords that `passwords::scorer` rates below this, out of 100, are weak. It is
+/// where the scorer's "good" range starts.
+pub const MIN_MASTER_SCORE: f64 = 80.0;
+
+/// Checks that a new master password is not weak, unless `allow_weak` is set. When `reader` is
+/// given, because the password was typed at a prompt, a weak one is used after confirming.
+/// Without a reader, e.g. with --master, it is refused, so that automation has to accept weak
+/// master passwords explicitly with --allow-weak-master.
+pub fn check_master_strength<R: BufRead, W: Write>(
+    reader: Option<&mut R>,
+    writer: &mut W,
+    master: &str,
+    allow_weak: bool,
+) -> anyhow::Result<()> {
+    let score = scorer::score(&analyzer::analyze(master));
+    if allow_weak || score >= MIN_MASTER_SCORE {
+        return Ok(());
+    }
+    let message = format!("The master password is weak, it scores {score:.0} out of 100");
+    let Some(reader) = reader else {
+        return Err(anyhow::anyhow!(
+            "{message}. Pass --allow-weak-master to use it anyway"
+        ));
+    };
+    print(writer, &message, Some(MessageType::Warning));
+    let input = read_terminal_input(reader, writer, Some("Use it anyway? Type yes to confirm"));
+    if matches!(input.as_str(), "y" | "yes") {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Choose a stronger master password"))
+    }
+}
+
+/// A random master password of `length` characters using every kind of character.
+pub fn generate_master_password(length: Length) -> anyhow::Result<String> {
+    PasswordGenerator::new()
+        .length(length.get_val())
+        .lowercase_letters(true)
+        .uppercase_letters(true)
+        .numbers(true)
+        .symbols(true)
+        .strict(true)
+        .generate_one()
+        .map_err(|err| anyhow::anyhow!("{err}"))
+}
+
+pub fn update_master_password<W: Write>(
+    writer: &mut W,
+    new_master_password: String,
+    password_store: &mut PasswordStore,
+) -> anyhow::Result<()> {
+    password_store
+        .load()?
+        .update_master(new_master_password)
+        .save()?;
+    print(
+        writer,
+        "Master password updated successfully",
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+/// Splits the encryption key of the store into `shares` recovery shares, any `threshold` of
+/// which can recover the store, and prints one share per line.
+pub fn export_recovery_shares<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    shares: u8,
+    threshold: u8,
+) -> anyhow::Result<()> {
+    let encryption_key = password_store.load()?.encryption_key()?;
+    let recovery_shares = shamir::split(&encryption_key, shares, threshold)?;
+    print(
+        writer,
+        &format!(
+            "Keep these shares in separate places, any {threshold} of them can recover the password store"
+        ),
+        Some(MessageType::Info),
+    );
+    for share in recovery_shares {
+        print(writer, &share, Some(MessageType::Success));
+    }
+    Ok(())
+}
+
+/// Rebuilds the encryption key from recovery `shares` and saves the store with the master
+/// password `password_store` was created with.
+pub fn recover_password_store<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    shares: &[String],
+) -> anyhow::Result<()> {
+    let mismatch = || anyhow::anyhow!("The recovery shares do not match this password store");
+    let encryption_key: [u8; 32] = shamir::combine(shares)?
+        .try_into()
+        .map_err(|_| mismatch())?;
+    password_store
+        .recover(&encryption_key)
+        .map_err(|err| match err {
+            StoreError::WrongMasterPassword => mismatch(),
+            err => err.into(),
+        })?
+        .save()?;
+    print(
+        writer,
+        "Password store recovered and master password updated successfully",
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+/// Renders the man page of lockbox followed by one page per subcommand.
+pub fn print_man_page<W: Write>(writer: &mut W) -> anyhow::Result<()> {
+    let mut command = Args::command().about(ABOUT).long_about(None);
+    command.build();
+    Man::new(command.clone()).render(writer)?;
+    for subcommand in command.get_subcommands() {
+        let title = format!("{}-{}", command.get_name(), subcommand.get_name());
+        Man::new(subcommand.clone()).title(title).render(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::io::MockPromptPassword;
+
+    use super::*;
+    use passwords::PasswordGenerator;
+    use rstest::rstest;
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    #[rstest]
+    #[case("service1".to_string(), Some("username1".to_string()), Some("password1"), false)]
+    #[case("service2".to_string(), None, Some("password2"), false)]
+    #[case("service3".to_string(), Some("username3".to_string()), None, true)]
+    fn test_add_password(
+        #[case] service: String,
+        #[case] username: Option<String>,
+        #[case] password: Option<&str>,
+        #[case] generate: bool,
+    ) {
+        let password_generator = PasswordGenerator::new()
+            .length(10)
+            .lowercase_letters(true)
+            .uppercase_letters(true)
+            .numbers(true)
+            .symbols(true)
+            .strict(true);
+        let master = "master_password".to_string();
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let output = Vec::new();
+        let mut writer = std::io::Cursor::new(output);
+        let mut password_store = PasswordStore::create(temp_file, master).unwrap();
+        let mock_prompt_password = &MockPromptPassword::new();
+        let result = add_password(
+            &mut writer,
+            mock_prompt_password,
+            &mut password_store,
+            service.clone(),
+            username.clone(),
+            AddOptions {
+                password: password.map(|s| s.to_string()),
+                password_generator: generate.then_some(password_generator),
+                ..Default::default()
+            },
+            &Settings::default(),
+        );
+        assert!(result.is_ok());
+        assert!(password_store.find(service, username).is_some());
+    }
+
+    #[rstest]
+    #[case(false, Err("github already has an entry for octocat, pass --upsert to replace its password with a generated one"))]
+    #[case(true, Ok(()))]
+    fn test_add_generated_password_to_existing_entry(
+        #[case] upsert: bool,
+        #[case] expected: Result<(), &str>,
+    ) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "master".to_string()).unwrap();
+        let add = |password_store: &mut PasswordStore, password: Option<&str>, upsert: bool| {
+            add_password(
+                &mut Vec::new(),
+                &MockPromptPassword::new(),
+                password_store,
+                "github".to_string(),
+                Some("octocat".to_string()),
+                AddOptions {
+                    password: password.map(str::to_string),
+                    password_generator: password
+                        .is_none()
+                        .then_some(PasswordGenerator::new().length(16)),
+                    metadata: HashMap::from([(
+                        "url".to_string(),
+                        "https://github.com".to_string(),
+                    )]),
+                    favorite: true,
+                    clipboard_ttl: Some(Duration::from_secs(30)),
+                    upsert,
+                    folder: Some("work".to_string()),
+                    ..Default::default()
+                },
+                &Settings::default(),
+            )
+        };
+        add(&mut password_store, Some("strong password"), false).unwrap();
+        password_store.set_disabled("github", Some("octocat"), true);
+        password_store.save().unwrap();
+
+        let result = add(&mut password_store, None, upsert);
+        assert_eq!(
+            result.map_err(|err| err.to_string()),
+            expected.map_err(str::to_string)
+        );
+        let mut password_store = PasswordStore::open(temp_file, "master".to_string()).unwrap();
+        let entries = password_store.load().unwrap().entries(&EntryFilter::all());
+        assert_eq!(entries.len(), 1);
+        let kept = entries[0].field("password") == Some("strong password");
+        assert_eq!(kept, !upsert);
+        // only the password is replaced
+        assert_eq!(entries[0].field("url"), Some("https://github.com"));
+        assert!(entries[0].favorite);
+        assert!(entries[0].disabled);
+        assert_eq!(entries[0].clipboard_ttl, Some(30));
+        assert_eq!(entries[0].folder, "work");
+        assert!(entries[0].updated_at.is_some());
+    }
+
+    #[rstest]
+    #[case(Length::Eight, true, true, true, true, 2)]
+    #[case(Length::Sixteen, false, true, true, true, 2)]
+    #[case(Length::ThirtyTwo, true, false, true, true, 3)]
+    #[case(Length::Sixteen, true, false, false, false, 2)]
+    #[case(Length::ThirtyTwo, false, true, false, false, 3)]
+    #[case(Length::Eight, true, true, true, true, 1000)]
+    fn test_generate_password(
+        #[case] length: Length,
+        #[case] symbols: bool,
+        #[case] uppercase: bool,
+        #[case] lowercase: bool,
+        #[case] numbers: bool,
+        #[case] count: usize,
+    ) {
+        let mut output = Vec::new();
+        let mut writer = std::io::Cursor::new(output);
+        generate_password(
+            &mut writer,
+            &GenerateOptions {
+                length: length.get_val(),
+                symbols,
+                uppercase,
+                lowercase,
+                numbers,
+                count,
+                ..Default::default()
+            },
+            None,
+            &Settings::default(),
+        )
+        .unwrap();
+        output = writer.into_inner();
+        let output_str = String::from_utf8(output).unwrap();
+        println!("{}", output_str);
+        let lines: Vec<&str> = output_str.trim().lines().collect();
+        assert_eq!(lines.len(), count);
+        if count == 1 {
+            println!("{}", output_str);
+            assert!(output_str.contains("Random password generated"))
+        }
+    }
+
+    #[rstest]
+    #[case(true, true, true, true, true)]
+    #[case(false, true, false, false, true)]
+    #[case(true, false, true, false, false)]
+    fn test_seeded_passwords(
+        #[case] symbols: bool,
+        #[case] uppercase: bool,
+        #[case] lowercase: bool,
+        #[case] numbers: bool,
+        #[case] strict: bool,
+    ) {
+        let generator = PasswordGenerator::new()
+            .length(12)
+            .symbols(symbols)
+            .uppercase_letters(uppercase)
+            .lowercase_letters(lowercase)
+            .numbers(numbers)
+            .strict(strict);
+        let first: Vec<String> = seeded_passwords(&generator, 42).unwrap().take(5).collect();
+        let again: Vec<String> = seeded_passwords(&generator, 42).unwrap().take(5).collect();
+        let other: Vec<String> = seeded_passwords(&generator, 43).unwrap().take(5).collect();
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        let kinds: [(bool, CharacterClass); 4] = [
+            (symbols, char::is_ascii_punctuation),
+            (uppercase, char::is_ascii_uppercase),
+            (lowercase, char::is_ascii_lowercase),
+            (numbers, char::is_ascii_digit),
+        ];
+        for password in &first {
+            assert_eq!(password.chars().count(), 12);
+            for (enabled, class) in kinds {
+                // without strict an enabled kind may be missing by chance
+                if strict || !enabled {
+                    assert_eq!(password.chars().any(|c| class(&c)), enabled);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded_passwords_invalid_settings() {
+        let generator = PasswordGenerator::new()
+            .length(2)
+            .uppercase_letters(true)
+            .symbols(true)
+            .strict(true);
+        assert!(seeded_passwords(&generator, 42).is_err());
+        let generator = PasswordGenerator::new()
+            .lowercase_letters(false)
+            .uppercase_letters(false)
+            .numbers(false);
+        assert!(seeded_passwords(&generator, 42).is_err());
+    }
+
+    #[test]
+    fn test_character_kinds() {
+        // the same characters as `PasswordGenerator`, e.g. 32 symbols
+        let generator = PasswordGenerator::new()
+            .lowercase_letters(true)
+            .uppercase_letters(true)
+            .numbers(true)
+            .symbols(true);
+        let kinds = character_kinds(&generator);
+        let lengths: Vec<usize> = kinds.iter().map(Vec::len).collect();
+        assert_eq!(lengths, vec![26, 26, 10, 32]);
+        assert!(kinds[3].contains(&'"') && kinds[3].contains(&'~'));
+    }
+
+    #[test]
+    fn test_generate_seeded_passwords() {
+        let generate = |seed: u64| {
+            let mut output = Vec::new();
+            generate_seeded_passwords(
+                &mut output,
+                &GenerateOptions {
+                    symbols: true,
+                    count: 3,
+                    ..Default::default()
+                },
+                seed,
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let output = generate(7);
+        assert!(output.contains("Warning: passwords generated with --seed"));
+        assert_eq!(output.lines().count(), 4);
+        assert_eq!(output, generate(7));
+        assert_ne!(output, generate(8));
+
+        let err = generate_seeded_passwords(
+            &mut Vec::new(),
+            &GenerateOptions {
+                length: 2,
+                symbols: true,
+                ..Default::default()
+            },
+            7,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Use a longer length or --no-strict"));
     }
 
     #[rstest]
-    #[case(Length::Eight, true, true, true, true, 2)]
-    #[case(Length::Sixteen, false, true, true, true, 2)]
-    #[case(Length::ThirtyTwo, true, false, true, true, 3)]
-    #[case(Length::Sixteen, true, false, false, false, 2)]
-    #[case(Length::ThirtyTwo, false, true, false, false, 3)]
-    fn test_generate_password(
-        #[case] length: Length,
+    #[case(16, "X-", "!1", Ok(12))]
+    #[case(8, "", "", Ok(8))]
+    #[case(8, "é", "", Ok(7))]
+    #[case(8, "abcd", "efgh", Err("The prefix and suffix take 8 of the 8 characters, use a longer length or shorter affixes"))]
+    fn test_affixed_core_length(
+        #[case] length: usize,
+        #[case] prefix: &str,
+        #[case] suffix: &str,
+        #[case] expected: Result<usize, &str>,
+    ) {
+        assert_eq!(
+            affixed_core_length(length, prefix, suffix).map_err(|err| err.to_string()),
+            expected.map_err(str::to_string)
+        );
+    }
+
+    #[test]
+    fn test_generate_password_with_affixes() {
+        let mut output = Vec::new();
+        generate_password(
+            &mut output,
+            &GenerateOptions {
+                count: 3,
+                prefix: "X-".to_string(),
+                suffix: "!1".to_string(),
+                ..Default::default()
+            },
+            None,
+            &Settings::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let passwords: Vec<&str> = output
+            .lines()
+            .filter_map(|line| line.split("X-").nth(1))
+            .collect();
+        assert_eq!(passwords.len(), 3);
+        for password in passwords {
+            // the colors of the output follow the suffix
+            let core = password.split("!1").next().unwrap();
+            assert_eq!(core.chars().count(), 12);
+            assert!(core.chars().all(|c| c.is_ascii_alphanumeric()));
+        }
+    }
+
+    #[rstest]
+    #[case(80, false, true, true, true, 14)]
+    #[case(128, true, true, true, true, 20)]
+    #[case(64, false, false, false, true, 20)]
+    #[case(1, false, true, false, false, 1)]
+    fn test_length_for_entropy(
+        #[case] bits: u32,
         #[case] symbols: bool,
         #[case] uppercase: bool,
         #[case] lowercase: bool,
         #[case] numbers: bool,
-        #[case] count: usize,
+        #[case] expected: usize,
+    ) {
+        assert_eq!(
+            length_for_entropy(bits, symbols, uppercase, lowercase, numbers).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(
+        80,
+        false,
+        false,
+        false,
+        false,
+        "Enable at least one kind of characters"
+    )]
+    #[case(1000, false, false, false, true, "longer than the maximum of 128")]
+    fn test_length_for_entropy_error(
+        #[case] bits: u32,
+        #[case] symbols: bool,
+        #[case] uppercase: bool,
+        #[case] lowercase: bool,
+        #[case] numbers: bool,
+        #[case] expected: &str,
+    ) {
+        let err = length_for_entropy(bits, symbols, uppercase, lowercase, numbers).unwrap_err();
+        assert!(err.to_string().contains(expected), "{err}");
+    }
+
+    #[rstest]
+    #[case(2, true, "A strict password of length 2 cannot contain all 4 enabled kinds of characters. Use a longer length or --no-strict.")]
+    #[case(2, false, "The length of passwords is too short.")]
+    #[case(8, true, "The length of passwords is too short.")]
+    fn test_describe_generator_error(
+        #[case] length: usize,
+        #[case] strict: bool,
+        #[case] expected: &str,
     ) {
+        let password_generator = PasswordGenerator::new()
+            .length(length)
+            .numbers(true)
+            .lowercase_letters(true)
+            .uppercase_letters(true)
+            .symbols(true)
+            .strict(strict);
+        assert_eq!(
+            describe_generator_error(&password_generator, "The length of passwords is too short."),
+            expected
+        );
+        if !strict {
+            assert_eq!(password_generator.generate_one().unwrap().len(), length);
+        }
+    }
+
+    #[test]
+    fn test_generate_password_phonetic() {
         let mut output = Vec::new();
-        let mut writer = std::io::Cursor::new(output);
         generate_password(
-            &mut writer,
-            length,
-            symbols,
-            uppercase,
-            lowercase,
-            numbers,
-            count,
+            &mut output,
+            &GenerateOptions {
+                length: Length::Eight.get_val(),
+                lowercase: false,
+                numbers: false,
+                count: 3,
+                phonetic: true,
+                ..Default::default()
+            },
+            None,
+            &Settings::default(),
         )
         .unwrap();
-        output = writer.into_inner();
         let output_str = String::from_utf8(output).unwrap();
-        println!("{}", output_str);
         let lines: Vec<&str> = output_str.trim().lines().collect();
-        assert_eq!(lines.len(), count);
-        if count == 1 {
-            println!("{}", output_str);
-            assert!(output_str.contains("Random password generated"))
+        assert_eq!(lines.len(), 6);
+        for pair in lines.chunks(2) {
+            // skip the color codes around the password, which has uppercase letters only
+            let password: String = pair[0].chars().filter(char::is_ascii_uppercase).collect();
+            assert_eq!(password.len(), 8);
+            assert!(pair[1].contains(&phonetic_spelling(&password)));
+            assert!(pair[1].split(' ').all(|word| word.len() > 1));
         }
     }
 
@@ -260,7 +2145,19 @@ mod test {
     fn test_generate_password_all_false() {
         let mut output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
-        generate_password(&mut writer, Length::Eight, false, false, false, false, 1).unwrap();
+        generate_password(
+            &mut writer,
+            &GenerateOptions {
+                length: Length::Eight.get_val(),
+                uppercase: false,
+                lowercase: false,
+                numbers: false,
+                ..Default::default()
+            },
+            None,
+            &Settings::default(),
+        )
+        .unwrap();
         output = writer.into_inner();
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains(
@@ -268,6 +2165,126 @@ mod test {
         ))
     }
 
+    #[rstest(format, case(GenerateFormat::Password), case(GenerateFormat::Hex))]
+    fn test_generate_to_clipboard_never_prints(format: GenerateFormat) {
+        let settings = Settings {
+            fake_clipboard: Some(Default::default()),
+            ..Default::default()
+        };
+        let generate = |writer: &mut Vec<u8>, count: usize| match format {
+            GenerateFormat::Password => generate_password(
+                writer,
+                &GenerateOptions {
+                    count,
+                    ..Default::default()
+                },
+                Some(Duration::ZERO),
+                &settings,
+            ),
+            _ => generate_token(
+                writer,
+                format,
+                16,
+                count,
+                Some(Duration::ZERO),
+                false,
+                &settings,
+            ),
+        };
+        let mut output = Vec::new();
+        let err = generate(&mut output, 2).unwrap_err();
+        assert!(err.to_string().contains("--count 2"));
+        assert!(output.is_empty());
+
+        assert!(settings
+            .fake_clipboard
+            .as_ref()
+            .unwrap()
+            .borrow()
+            .is_empty());
+
+        // the value is only ever written to the clipboard
+        let mut output = Vec::new();
+        generate(&mut output, 1).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let copied = settings.fake_clipboard.unwrap().into_inner();
+        assert_eq!(copied.len(), 1);
+        assert!(!copied[0].is_empty());
+        assert!(!output.contains(&copied[0]));
+        assert_eq!(
+            output,
+            format!(
+                "{}\n",
+                colorize("Copied to clipboard", MessageType::Success)
+            )
+        );
+    }
+
+    #[rstest]
+    #[case(GenerateFormat::Hex, 32, 64, "0123456789abcdef")]
+    #[case(GenerateFormat::Hex, 1, 2, "0123456789abcdef")]
+    #[case(
+        GenerateFormat::Base64,
+        32,
+        44,
+        "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/="
+    )]
+    #[case(
+        GenerateFormat::Base64Url,
+        32,
+        43,
+        "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_"
+    )]
+    fn test_encode_token(
+        #[case] format: GenerateFormat,
+        #[case] bytes: usize,
+        #[case] expected_length: usize,
+        #[case] alphabet: &str,
+    ) {
+        let token = encode_token(&get_random_bytes(bytes), format).unwrap();
+        assert_eq!(token.len(), expected_length);
+        assert!(token.chars().all(|c| alphabet.contains(c)));
+    }
+
+    #[rstest]
+    #[case(GenerateFormat::Hex, 16, 3)]
+    #[case(GenerateFormat::Base64, 24, 1)]
+    #[case(GenerateFormat::Base64Url, 8, 1000)]
+    fn test_generate_token(
+        #[case] format: GenerateFormat,
+        #[case] bytes: usize,
+        #[case] count: usize,
+    ) {
+        let mut output = Vec::new();
+        generate_token(
+            &mut output,
+            format,
+            bytes,
+            count,
+            None,
+            false,
+            &Settings::default(),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        if count == 1 {
+            assert!(output_str.contains("Random token generated"));
+        } else {
+            assert_eq!(output_str.trim().lines().count(), count);
+        }
+        assert!(generate_token(
+            &mut Vec::new(),
+            format,
+            0,
+            count,
+            None,
+            false,
+            &Settings::default()
+        )
+        .is_err());
+        assert!(encode_token(&[0u8], GenerateFormat::Password).is_err());
+    }
+
     #[rstest(
         service, username, password, expect_password_found,
         case("service1".to_string(), Some("username1".to_string()), "password1".to_string(), true),
@@ -282,7 +2299,7 @@ mod test {
     ) {
         let master = "master_password".to_string();
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, master).unwrap();
         let output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let mock_prompt_password = &MockPromptPassword::new();
@@ -292,35 +2309,90 @@ mod test {
             &mut password_store,
             service.clone(),
             username.clone(),
-            Some(password.clone()),
-            false,
-            PasswordGenerator::default(),
+            AddOptions {
+                password: Some(password.clone()),
+                ..Default::default()
+            },
+            &Settings::default(),
         )
         .unwrap();
 
         let mut output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let result = if expect_password_found {
-            show_password(&mut writer, &mut password_store, service, username)
+            show_password(
+                &mut writer,
+                &mut Vec::new(),
+                &mut password_store,
+                service,
+                username,
+                ShowFormat::Text,
+                &Settings::default(),
+            )
         } else {
             show_password(
                 &mut writer,
+                &mut Vec::new(),
                 &mut password_store,
                 "not_found_service".to_string(),
                 Some("not_found_username".to_string()),
+                ShowFormat::Text,
+                &Settings::default(),
             )
         };
-        assert!(result.is_ok());
 
         output = writer.into_inner();
         let output_str = String::from_utf8(output).unwrap();
         if expect_password_found {
+            assert!(result.is_ok());
             assert!(output_str.contains(&password));
         } else {
-            assert!(output_str.contains("Password not found"));
+            let err = result.unwrap_err();
+            assert!(err.is::<NotFound>());
+            assert!(err.to_string().contains("Password not found"));
+            assert!(output_str.is_empty());
         }
     }
 
+    #[test]
+    fn test_show_password_metadata() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store = PasswordStore::create(temp_file, "master".to_string()).unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "service".to_string(),
+            None,
+            AddOptions {
+                password: Some("password".to_string()),
+                metadata: HashMap::from([("recovery-email".to_string(), "x@y.com".to_string())]),
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        let mut messages = Vec::new();
+        show_password(
+            &mut output,
+            &mut messages,
+            &mut password_store,
+            "service".to_string(),
+            None,
+            ShowFormat::Text,
+            &Settings::default(),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("recovery-email"));
+        assert!(output_str.contains("x@y.com"));
+        // whether or not copying worked, only the entry is on the output
+        assert!(!output_str.contains("clipboard"));
+        assert!(String::from_utf8(messages).unwrap().contains("clipboard"));
+    }
+
     #[rstest(
         show_passwords,
         passwords,
@@ -335,7 +2407,7 @@ mod test {
     fn test_list_passwords(show_passwords: bool, passwords: Vec<(&str, &str, &str)>) {
         let master = "master_password".to_string();
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, master).unwrap();
         let output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let mock_prompt_password = &MockPromptPassword::new();
@@ -347,16 +2419,27 @@ mod test {
                 &mut password_store,
                 service.to_string(),
                 Some(username.to_string()),
-                Some(password.to_string()),
-                false,
-                PasswordGenerator::default(),
+                AddOptions {
+                    password: Some(password.to_string()),
+                    ..Default::default()
+                },
+                &Settings::default(),
             )
             .unwrap();
         }
 
         let mut output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
-        let result = list_passwords(&mut writer, &mut password_store, show_passwords);
+        let result = list_passwords(
+            &mut writer,
+            &mut password_store,
+            &EntryFilter::default(),
+            &ListOptions {
+                show_passwords,
+                ..Default::default()
+            },
+            &Settings::default(),
+        );
         assert!(result.is_ok());
 
         output = writer.into_inner();
@@ -364,7 +2447,7 @@ mod test {
 
         if passwords.is_empty() {
             println!("OUTPUT STR: {output_str}");
-            assert!(output_str.contains("No passwords found!"));
+            assert!(output_str.contains("No passwords stored."));
         }
 
         for (service, username, password) in passwords.iter() {
@@ -381,6 +2464,418 @@ mod test {
         }
     }
 
+    #[rstest]
+    #[case(false, false)]
+    #[case(true, false)]
+    #[case(false, true)]
+    fn test_list_passwords_ndjson(#[case] show_passwords: bool, #[case] services_only: bool) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file, "master_password".to_string()).unwrap();
+        for (service, username) in [("service_a", Some("username1")), ("service_b", None)] {
+            add_password(
+                &mut Vec::new(),
+                &MockPromptPassword::new(),
+                &mut password_store,
+                service.to_string(),
+                username.map(String::from),
+                AddOptions {
+                    password: Some("password".to_string()),
+                    favorite: service == "service_b",
+                    ..Default::default()
+                },
+                &Settings::default(),
+            )
+            .unwrap();
+        }
+
+        let mut output = Vec::new();
+        list_passwords(
+            &mut output,
+            &mut password_store,
+            &EntryFilter::default(),
+            &ListOptions {
+                show_passwords,
+                services_only,
+                format: ListFormat::Ndjson,
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let expected = if services_only {
+            vec![
+                serde_json::json!({ "service": "service_a" }),
+                serde_json::json!({ "service": "service_b" }),
+            ]
+        } else {
+            let mut expected = vec![
+                serde_json::json!({ "service": "service_b", "username": null, "metadata": {}, "favorite": true }),
+                serde_json::json!({ "service": "service_a", "username": "username1", "metadata": {}, "favorite": false }),
+            ];
+            if show_passwords {
+                for entry in expected.iter_mut() {
+                    entry["password"] = serde_json::json!("password");
+                }
+            }
+            expected
+        };
+        assert_eq!(lines, expected);
+    }
+
+    #[rstest(
+        services_only,
+        format,
+        expected_remaining,
+        case(false, ListFormat::Text, Some("... and 1 more")),
+        case(true, ListFormat::Text, Some("... and 1 more")),
+        case(false, ListFormat::Ndjson, None),
+        case(true, ListFormat::Ndjson, None)
+    )]
+    fn test_list_passwords_limit(
+        services_only: bool,
+        format: ListFormat,
+        expected_remaining: Option<&str>,
+    ) {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        for service in ["service_a", "service_b", "service_c"] {
+            password_store
+                .load()
+                .unwrap()
+                .push(
+                    service.to_string(),
+                    None,
+                    "password".to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap()
+                .save()
+                .unwrap();
+        }
+        let mut output = Vec::new();
+        list_passwords(
+            &mut output,
+            &mut password_store,
+            &EntryFilter::default(),
+            &ListOptions {
+                services_only,
+                limit: Some(2),
+                format,
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output_str
+            .lines()
+            .filter(|line| line.contains("service_"))
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert!(!output_str.contains("service_c"));
+        match expected_remaining {
+            Some(remaining) => assert!(output_str.contains(remaining)),
+            None => assert!(!output_str.contains("more")),
+        }
+    }
+
+    #[rstest(runs, expected, case(1, "over 1 run"), case(2, "over 2 runs"))]
+    fn test_bench_kdf(runs: u32, expected: &str) {
+        let mut output = Vec::new();
+        bench_kdf(&mut output, runs);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("PBKDF2-HMAC-SHA256 and 100000 iterations took "));
+        assert!(output.contains(expected));
+    }
+
+    #[test]
+    fn test_list_and_find_entries() {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "github".to_string(),
+                Some("octocat".to_string()),
+                "hunter2".to_string(),
+                HashMap::from([("recovery".to_string(), "555".to_string())]),
+                false,
+            )
+            .unwrap()
+            .push(
+                "email".to_string(),
+                None,
+                "secret".to_string(),
+                HashMap::new(),
+                true,
+            )
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let entries =
+            list_entries(&mut password_store, false, &[], &EntryFilter::default()).unwrap();
+        let services: Vec<(Option<usize>, &str)> = entries
+            .iter()
+            .map(|entry| (entry.index, entry.service.as_str()))
+            .collect();
+        assert_eq!(services, vec![(Some(1), "email"), (Some(2), "github")]);
+        assert!(entries.iter().all(|entry| entry.password.is_none()));
+
+        let entries = list_entries(
+            &mut password_store,
+            false,
+            &["github".to_string()],
+            &EntryFilter::default(),
+        )
+        .unwrap();
+        let passwords: Vec<Option<&str>> = entries
+            .iter()
+            .map(|entry| entry.password.as_deref())
+            .collect();
+        assert_eq!(passwords, vec![None, Some("hunter2")]);
+
+        let entry = find_entry(
+            &mut password_store,
+            "github",
+            Some("octocat"),
+            &Settings::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            entry,
+            PasswordEntry::new(
+                "github".to_string(),
+                Some("octocat".to_string()),
+                "hunter2".to_string()
+            )
+            .with_metadata(HashMap::from([("recovery".to_string(), "555".to_string())]))
+            .view(true)
+        );
+        assert_eq!(entry.to_json()["password"], "hunter2");
+        assert!(entry.to_json().get("index").is_none());
+        assert!(
+            find_entry(&mut password_store, "github", None, &Settings::default())
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            find_entry_at_index(&mut password_store, 2, &Settings::default())
+                .unwrap()
+                .unwrap()
+                .index,
+            Some(2)
+        );
+    }
+
+    #[rstest(format, case(ListFormat::Text), case(ListFormat::Ndjson))]
+    fn test_list_show_and_remove_by_index(format: ListFormat) {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        for password in ["password_a", "password_b"] {
+            password_store
+                .load()
+                .unwrap()
+                .push(
+                    "service".to_string(),
+                    None,
+                    password.to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap()
+                .save()
+                .unwrap();
+        }
+        let mut output = Vec::new();
+        list_passwords(
+            &mut output,
+            &mut password_store,
+            &EntryFilter::default(),
+            &ListOptions {
+                show_index: true,
+                format,
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        match format {
+            ListFormat::Text => {
+                assert!(output_str.contains("[1] "));
+                assert!(output_str.contains("[2] "));
+            }
+            ListFormat::Ndjson => assert!(output_str.contains(r#""index":2"#)),
+        }
+
+        let mut output = Vec::new();
+        show_password_at_index(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            2,
+            ShowFormat::Text,
+            &Settings::default(),
+        )
+        .unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("password_b"));
+
+        let mut output = Vec::new();
+        remove_password_at_index(&mut output, &mut password_store, 1).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Password deleted"));
+        let err = show_password_at_index(
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut password_store,
+            2,
+            ShowFormat::Text,
+            &Settings::default(),
+        )
+        .unwrap_err();
+        assert!(err.is::<NotFound>());
+        assert_eq!(err.to_string(), "No entry at index 2");
+        let remaining: Vec<&str> = password_store
+            .entries(&EntryFilter::default())
+            .into_iter()
+            .filter_map(|password| password.field("password"))
+            .collect();
+        assert_eq!(remaining, vec!["password_b"]);
+    }
+
+    #[rstest(
+        folder,
+        tree,
+        expected,
+        case(None, false, vec!["forum", "console", "jira"]),
+        case(Some("work/aws"), false, vec!["console"]),
+        case(Some("work"), true, vec!["work/", "aws/", "console", "jira"])
+    )]
+    fn test_list_passwords_folder(folder: Option<&str>, tree: bool, expected: Vec<&str>) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store = PasswordStore::create(temp_file, "master".to_string()).unwrap();
+        for (service, folder) in [
+            ("forum", None),
+            ("console", Some("work/aws")),
+            ("jira", Some("/work/")),
+        ] {
+            add_password(
+                &mut Vec::new(),
+                &MockPromptPassword::new(),
+                &mut password_store,
+                service.to_string(),
+                None,
+                AddOptions {
+                    password: Some("password".to_string()),
+                    folder: folder.map(str::to_string),
+                    ..Default::default()
+                },
+                &Settings::default(),
+            )
+            .unwrap();
+        }
+
+        let mut output = Vec::new();
+        list_passwords(
+            &mut output,
+            &mut password_store,
+            &EntryFilter {
+                folder: folder.map(str::to_string),
+                ..Default::default()
+            },
+            &ListOptions {
+                tree,
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output_str.lines().collect();
+        assert_eq!(lines.len(), expected.len());
+        for (line, expected) in lines.iter().zip(expected) {
+            assert!(line.contains(expected));
+        }
+    }
+
+    #[test]
+    fn test_list_passwords_services_only() {
+        let master = "master_password".to_string();
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store = PasswordStore::create(temp_file, master).unwrap();
+        let mut writer = std::io::Cursor::new(Vec::new());
+        let mock_prompt_password = &MockPromptPassword::new();
+        let passwords = [
+            ("service_b", "username1", "password1"),
+            ("service_a", "username2", "password2"),
+            ("service_b", "username3", "password3"),
+        ];
+        for (service, username, password) in passwords.iter() {
+            add_password(
+                &mut writer,
+                mock_prompt_password,
+                &mut password_store,
+                service.to_string(),
+                Some(username.to_string()),
+                AddOptions {
+                    password: Some(password.to_string()),
+                    ..Default::default()
+                },
+                &Settings::default(),
+            )
+            .unwrap();
+        }
+
+        let mut output = Vec::new();
+        list_passwords(
+            &mut output,
+            &mut password_store,
+            &EntryFilter::default(),
+            &ListOptions {
+                services_only: true,
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output_str.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("service_a"));
+        assert!(lines[1].contains("service_b"));
+        for (_, username, password) in passwords.iter() {
+            assert!(!output_str.contains(username));
+            assert!(!output_str.contains(password));
+        }
+
+        let mut output = Vec::new();
+        list_passwords(
+            &mut output,
+            &mut password_store,
+            &EntryFilter {
+                exclude: vec!["service".to_string()],
+                ..Default::default()
+            },
+            &ListOptions {
+                services_only: true,
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("No entries match."));
+    }
+
     #[rstest(
     passwords_to_add,
     password_to_remove,
@@ -413,7 +2908,7 @@ mod test {
     ) {
         let master = "master_password".to_string();
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, master).unwrap();
         let output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let mock_prompt_password = &MockPromptPassword::new();
@@ -425,9 +2920,11 @@ mod test {
                 &mut password_store,
                 service.to_string(),
                 Some(username.to_string()),
-                Some(password.to_string()),
-                false,
-                PasswordGenerator::default(),
+                AddOptions {
+                    password: Some(password.to_string()),
+                    ..Default::default()
+                },
+                &Settings::default(),
             )
             .unwrap();
         }
@@ -440,25 +2937,779 @@ mod test {
             service.to_string(),
             Some(username.to_string()),
         );
-        assert!(result.is_ok());
+        let found = passwords_to_add.len() != expected_passwords.len();
+        assert_eq!(result.is_ok(), found);
+        if !found {
+            assert!(result.unwrap_err().is::<NotFound>());
+        }
 
         for (service, username, password) in expected_passwords.iter() {
             assert_eq!(
-                password_store.find(service.to_string(), Some(username.to_string())),
-                Some(&PasswordEntry::new(
+                password_store
+                    .find(service.to_string(), Some(username.to_string()))
+                    .and_then(|entry| entry.field("password")),
+                Some(*password)
+            );
+        }
+    }
+
+    #[test]
+    fn test_dedup_passwords() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "master".to_string()).unwrap();
+        let mock_prompt_password = &MockPromptPassword::new();
+        for (service, password) in [
+            ("service1", "password1"),
+            ("service1", "password1"),
+            ("service2", "password2"),
+            ("service2", "password3"),
+        ] {
+            add_password(
+                &mut Vec::new(),
+                mock_prompt_password,
+                &mut password_store,
+                service.to_string(),
+                Some("username".to_string()),
+                AddOptions {
+                    password: Some(password.to_string()),
+                    ..Default::default()
+                },
+                &Settings::default(),
+            )
+            .unwrap();
+        }
+
+        let mut output = Vec::new();
+        dedup_passwords(&mut output, &mut password_store).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Removed 1 duplicate password(s)"));
+        assert!(output_str.contains("service2 (username) has multiple different passwords"));
+
+        let mut password_store = PasswordStore::open(temp_file, "master".to_string()).unwrap();
+        let report = password_store.load().unwrap().dedup();
+        assert_eq!(report.pruned, 0);
+    }
+
+    #[rstest(
+        username,
+        format,
+        expected,
+        case(Some("octocat"), ShowFormat::Lines, "octocat\nhunter2\n"),
+        case(None, ShowFormat::Lines, "\nhunter2\n"),
+        case(Some("octocat"), ShowFormat::Type, "octocat\thunter2"),
+        case(None, ShowFormat::Type, "hunter2")
+    )]
+    fn test_show_password_formats(username: Option<&str>, format: ShowFormat, expected: &str) {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "github".to_string(),
+                username.map(str::to_string),
+                "hunter2".to_string(),
+                HashMap::new(),
+                false,
+            )
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let mut output = Vec::new();
+        show_password(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            "github".to_string(),
+            username.map(str::to_string),
+            format,
+            &Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        // a missing entry is an error instead of output that could be typed into a form
+        let mut output = Vec::new();
+        let err = show_password(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            "gitlab".to_string(),
+            None,
+            format,
+            &Settings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Password not found");
+        assert!(output.is_empty());
+        assert!(show_password_at_index(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            5,
+            format,
+            &Settings::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_set_password_disabled() {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        for service in ["github", "gitlab"] {
+            password_store
+                .load()
+                .unwrap()
+                .push(
+                    service.to_string(),
+                    None,
+                    "hunter2".to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap()
+                .save()
+                .unwrap();
+        }
+        let listed = |password_store: &mut PasswordStore, filter: &EntryFilter| -> Vec<String> {
+            list_entries(password_store, false, &[], filter)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.service)
+                .collect()
+        };
+
+        let mut output = Vec::new();
+        set_password_disabled(
+            &mut output,
+            &mut password_store,
+            "github".to_string(),
+            None,
+            true,
+        )
+        .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Password disabled"));
+        assert_eq!(
+            listed(&mut password_store, &EntryFilter::default()),
+            vec!["gitlab"]
+        );
+        assert_eq!(
+            listed(&mut password_store, &EntryFilter::all()),
+            vec!["github", "gitlab"]
+        );
+        // a disabled entry is kept for reference and can still be shown
+        let mut output = Vec::new();
+        show_password(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            "github".to_string(),
+            None,
+            ShowFormat::Lines,
+            &Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\nhunter2\n");
+
+        let mut output = Vec::new();
+        set_password_disabled_at_index(&mut output, &mut password_store, 1, false).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Password enabled"));
+        assert_eq!(
+            listed(&mut password_store, &EntryFilter::default()),
+            vec!["github", "gitlab"]
+        );
+
+        let err = set_password_disabled_at_index(&mut Vec::new(), &mut password_store, 5, true)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No entry at index 5");
+        let err = set_password_disabled(
+            &mut Vec::new(),
+            &mut password_store,
+            "forum".to_string(),
+            None,
+            true,
+        )
+        .unwrap_err();
+        assert!(err.is::<NotFound>());
+        assert!(err.to_string().contains("Password not found"));
+    }
+
+    #[rstest(
+        services,
+        expected_output,
+        expected_error,
+        case(&["github", "gitlab"], "octocat\nhunter2\noctocat\ntanuki\n", None),
+        case(&["gitlab", "github"], "octocat\ntanuki\noctocat\nhunter2\n", None),
+        case(&["github", "bitbucket", "gitlab"], "octocat\nhunter2\n", Some("Password not found"))
+    )]
+    fn test_show_passwords(services: &[&str], expected_output: &str, expected_error: Option<&str>) {
+        let mut password_store = show_passwords_store();
+        let mut output = Vec::new();
+        let services: Vec<String> = services.iter().map(|service| service.to_string()).collect();
+        let result = show_passwords(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            &services,
+            Some("octocat".to_string()),
+            ShowFormat::Lines,
+            &Settings::default(),
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected_output);
+        assert_eq!(
+            result.err().map(|err| err.to_string()).as_deref(),
+            expected_error
+        );
+    }
+
+    #[test]
+    fn test_show_several_passwords_as_type() {
+        let mut output = Vec::new();
+        show_passwords(
+            &mut output,
+            &mut Vec::new(),
+            &mut show_passwords_store(),
+            &["github".to_string(), "gitlab".to_string()],
+            Some("octocat".to_string()),
+            ShowFormat::Type,
+            &Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "octocat\thunter2\noctocat\ttanuki"
+        );
+    }
+
+    #[rstest(services, expected_copies, case(&["github"], 1), case(&["github", "gitlab"], 0))]
+    fn test_show_several_passwords_skips_clipboard(services: &[&str], expected_copies: usize) {
+        let settings = Settings {
+            fake_clipboard: Some(Default::default()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let mut messages = Vec::new();
+        let services: Vec<String> = services.iter().map(|service| service.to_string()).collect();
+        show_passwords(
+            &mut output,
+            &mut messages,
+            &mut show_passwords_store(),
+            &services,
+            Some("octocat".to_string()),
+            ShowFormat::Text,
+            &settings,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("hunter2"));
+        assert_eq!(services.len() > 1, output.contains("tanuki"));
+        let copied = settings.fake_clipboard.unwrap().into_inner();
+        assert_eq!(copied.len(), expected_copies);
+        assert_eq!(
+            services.len() > 1,
+            String::from_utf8(messages)
+                .unwrap()
+                .contains("not copied to the clipboard")
+        );
+    }
+
+    fn show_passwords_store() -> PasswordStore {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        for (service, password) in [("github", "hunter2"), ("gitlab", "tanuki")] {
+            password_store
+                .load()
+                .unwrap()
+                .push(
                     service.to_string(),
-                    Some(username.to_string()),
-                    password.to_string()
-                ))
+                    Some("octocat".to_string()),
+                    password.to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap()
+                .save()
+                .unwrap();
+        }
+        password_store
+    }
+
+    #[test]
+    fn test_ambiguous_entries() {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        for (username, password) in [
+            (None, "password0"),
+            (Some("octocat"), "password1"),
+            (Some("octocat"), "password2"),
+        ] {
+            password_store
+                .load()
+                .unwrap()
+                .push(
+                    "github".to_string(),
+                    username.map(str::to_string),
+                    password.to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap()
+                .save()
+                .unwrap();
+        }
+        let expected = "github (octocat) matches 2 entries, pick one with --index: 2, 3";
+        let octocat = || Some("octocat".to_string());
+
+        let mut output = Vec::new();
+        let err = show_password(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            "github".to_string(),
+            octocat(),
+            ShowFormat::Text,
+            &Settings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), expected);
+        assert!(output.is_empty());
+        let err = get_fields(
+            &mut output,
+            &mut password_store,
+            "github".to_string(),
+            octocat(),
+            &GetOptions::default(),
+            &Settings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), expected);
+        let err = remove_password(
+            &mut output,
+            &mut password_store,
+            "github".to_string(),
+            octocat(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), expected);
+        assert!(output.is_empty());
+        assert_eq!(
+            list_entries(&mut password_store, false, &[], &EntryFilter::default())
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // the entry without a username and the indexed entries are unambiguous
+        let entry = find_entry(&mut password_store, "github", None, &Settings::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.password.as_deref(), Some("password0"));
+        let entry = find_entry_at_index(&mut password_store, 3, &Settings::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.password.as_deref(), Some("password2"));
+    }
+
+    #[test]
+    fn test_self_test() {
+        let mut output = Vec::new();
+        self_test(&mut output).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        for cipher in ["aes-256-gcm", "chacha20-poly1305"] {
+            assert!(output_str.contains(&format!("[PASS] {cipher}: decrypt the entry")));
+            assert!(
+                output_str.contains(&format!("[PASS] {cipher}: reject a wrong master password"))
             );
         }
+        assert!(!output_str.contains("[FAIL]"));
+        assert!(output_str.contains("Self-test passed"));
+
+        let mut output = Vec::new();
+        let step = report_step(
+            &mut output,
+            Cipher::Aes256Gcm,
+            "reopen the saved store",
+            Err::<(), _>(StoreError::WrongMasterPassword),
+        );
+        assert!(step.is_none());
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("[FAIL] aes-256-gcm: reopen the saved store: "));
+    }
+
+    #[test]
+    fn test_gc_passwords() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "master".to_string()).unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "temp".to_string(),
+            None,
+            AddOptions {
+                password: Some("password".to_string()),
+                ttl: Some(Duration::from_secs(3600)),
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        password_store
+            .append({
+                let mut entry = PasswordEntry::new("old".to_string(), None, "password".to_string());
+                entry.expires_at = Some(1);
+                entry
+            })
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let mut output = Vec::new();
+        gc_passwords(&mut output, &mut password_store).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Removed 1 expired password(s)"));
+
+        let mut password_store = PasswordStore::open(temp_file, "master".to_string()).unwrap();
+        let services: Vec<&str> = password_store
+            .load()
+            .unwrap()
+            .entries(&EntryFilter::default())
+            .into_iter()
+            .map(|entry| entry.service.as_str())
+            .collect();
+        assert_eq!(services, vec!["temp"]);
+        assert_eq!(password_store.gc(), 0);
+    }
+
+    #[rstest]
+    #[case("twitter", "x", Ok("Renamed 2 password(s) from twitter to x"), vec!["github", "x", "x"])]
+    #[case("twitter", "github", Err("github already has entries for (no username), remove or rename them first"), vec!["github", "twitter", "twitter"])]
+    #[case("twitter", "twitter", Err("The service is already named twitter"), vec!["github", "twitter", "twitter"])]
+    #[case("mastodon", "x", Ok("No passwords stored for mastodon"), vec!["github", "twitter", "twitter"])]
+    fn test_rename_service(
+        #[case] from: &str,
+        #[case] to: &str,
+        #[case] expected: Result<&str, &str>,
+        #[case] expected_services: Vec<&str>,
+    ) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "master".to_string()).unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .append(PasswordEntry::new(
+                "twitter".to_string(),
+                Some("alice".to_string()),
+                "password1".to_string(),
+            ))
+            .unwrap()
+            .append(PasswordEntry::new(
+                "twitter".to_string(),
+                None,
+                "password2".to_string(),
+            ))
+            .unwrap()
+            .append(PasswordEntry::new(
+                "github".to_string(),
+                None,
+                "password3".to_string(),
+            ))
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let mut output = Vec::new();
+        match (
+            rename_service(&mut output, &mut password_store, from, to),
+            expected,
+        ) {
+            (Ok(()), Ok(message)) => {
+                assert!(String::from_utf8(output).unwrap().contains(message))
+            }
+            (Err(err), Err(message)) => assert_eq!(err.to_string(), message),
+            (result, _) => panic!("unexpected result {result:?}"),
+        }
+
+        let mut password_store = PasswordStore::open(temp_file, "master".to_string()).unwrap();
+        let mut services: Vec<String> = password_store
+            .load()
+            .unwrap()
+            .entries(&EntryFilter::all())
+            .into_iter()
+            .map(|entry| entry.service.clone())
+            .collect();
+        services.sort();
+        assert_eq!(services, expected_services);
+    }
+
+    #[rstest]
+    #[case(true, b"", vec!["Removed 1 password(s)"], vec!["recent", "unknown"])]
+    #[case(false, b"yes\n", vec!["stale", "Remove these 1 entries?", "Removed 1 password(s)"], vec!["recent", "unknown"])]
+    #[case(false, b"\n", vec!["stale", "Nothing was removed"], vec!["stale", "recent", "unknown"])]
+    fn test_remove_passwords_older_than(
+        #[case] yes: bool,
+        #[case] input: &[u8],
+        #[case] expected_output: Vec<&str>,
+        #[case] expected_services: Vec<&str>,
+    ) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "master".to_string()).unwrap();
+        let year = 365 * 24 * 60 * 60;
+        let entry =
+            |service: &str| PasswordEntry::new(service.to_string(), None, "password".to_string());
+        password_store
+            .load()
+            .unwrap()
+            .append(entry("stale").with_updated_at(unix_now() - 3 * year))
+            .unwrap()
+            .append(entry("recent").with_updated_at(unix_now() - year))
+            .unwrap()
+            .append(entry("unknown"))
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let mut output = Vec::new();
+        remove_passwords_older_than(
+            &mut std::io::Cursor::new(input),
+            &mut output,
+            &mut password_store,
+            Duration::from_secs(2 * year),
+            yes,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        for expected in expected_output {
+            assert!(output.contains(expected));
+        }
+
+        let mut password_store = PasswordStore::open(temp_file, "master".to_string()).unwrap();
+        let services: Vec<&str> = password_store
+            .load()
+            .unwrap()
+            .entries(&EntryFilter::default())
+            .into_iter()
+            .map(|entry| entry.service.as_str())
+            .collect();
+        assert_eq!(services, expected_services);
+    }
+
+    #[rstest]
+    #[case("github.com", Ok("octocat\nhunter2\n"))]
+    #[case("https://www.github.com/settings", Ok("octocat\nhunter2\n"))]
+    #[case("gitlab.com", Err("No entry has a URL on gitlab.com"))]
+    #[case(
+        "example.com",
+        Err("example.com matches 2 entries, pick one with --index: 2, 3")
+    )]
+    fn test_show_password_by_url(#[case] url: &str, #[case] expected: Result<&str, &str>) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store = PasswordStore::create(temp_file, "master".to_string()).unwrap();
+        let entry = |service: &str, username: &str, password: &str, url: &str| {
+            PasswordEntry::new(
+                service.to_string(),
+                Some(username.to_string()),
+                password.to_string(),
+            )
+            .with_metadata(HashMap::from([("url".to_string(), url.to_string())]))
+        };
+        password_store
+            .load()
+            .unwrap()
+            .append(entry(
+                "github",
+                "octocat",
+                "hunter2",
+                "https://github.com/login",
+            ))
+            .unwrap()
+            .append(entry(
+                "example",
+                "alice",
+                "password1",
+                "https://example.com",
+            ))
+            .unwrap()
+            .append(entry("example", "bob", "password2", "www.example.com"))
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let mut output = Vec::new();
+        let result = show_password_by_url(
+            &mut output,
+            &mut Vec::new(),
+            &mut password_store,
+            url,
+            ShowFormat::Lines,
+            &Settings::default(),
+        );
+        match expected {
+            Ok(expected) => {
+                result.unwrap();
+                assert_eq!(String::from_utf8(output).unwrap(), expected);
+            }
+            Err(expected) => {
+                assert_eq!(result.unwrap_err().to_string(), expected);
+                assert!(output.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_reveal_limit() {
+        let entry = |service: &str, reveal: bool| {
+            PasswordEntry::new(service.to_string(), None, "password".to_string()).view(reveal)
+        };
+        let (github, gitlab, forum) = (
+            entry("github", true),
+            entry("gitlab", true),
+            entry("forum", true),
+        );
+        let start = Instant::now();
+        let mut reveal_limit = RevealLimit::new(2, None);
+        reveal_limit.admit([&github], start).unwrap();
+        // revealing the same entry again and listing without passwords don't count
+        reveal_limit
+            .admit([&github, &entry("bank", false)], start)
+            .unwrap();
+        reveal_limit.admit([&gitlab], start).unwrap();
+        let err = reveal_limit.admit([&github, &forum], start).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Refused to reveal 1 more password(s), at most 2 distinct passwords can be revealed per session and 2 already were"
+        );
+        // nothing is revealed when the limit would be exceeded
+        let mut reveal_limit = RevealLimit::new(2, None);
+        assert!(reveal_limit
+            .admit([&github, &gitlab, &forum], start)
+            .is_err());
+        reveal_limit.admit([&forum], start).unwrap();
+
+        let window = Duration::from_secs(60);
+        let mut reveal_limit = RevealLimit::new(1, Some(window));
+        reveal_limit.admit([&github], start).unwrap();
+        let err = reveal_limit
+            .admit([&gitlab], start + Duration::from_secs(59))
+            .unwrap_err();
+        assert!(err.to_string().contains("every 60 seconds"));
+        reveal_limit.admit([&gitlab], start + window).unwrap();
+    }
+
+    #[test]
+    fn test_append_audit_records() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let revealed = PasswordEntry::new(
+            "github".to_string(),
+            Some("octocat".to_string()),
+            "hunter2".to_string(),
+        );
+        let hidden = PasswordEntry::new("email".to_string(), None, "secret".to_string());
+        let entries = [revealed.view(true), hidden.view(false)];
+        append_audit_records(&temp_file, "list", &entries).unwrap();
+        append_audit_records(&temp_file, "show", &entries[..1]).unwrap();
+
+        let log = fs::read_to_string(&temp_file).unwrap();
+        let records: Vec<serde_json::Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["command"], "list");
+        assert_eq!(records[0]["service"], "github");
+        assert_eq!(records[0]["username"], "octocat");
+        assert!(records[0]["time"].as_u64().unwrap() > 0);
+        assert_eq!(records[1]["command"], "show");
+        assert!(!log.contains("hunter2"));
+        assert!(!log.contains("email"));
+    }
+
+    #[test]
+    fn test_show_password_audit_log() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let audit_log = temp_dir.path().join("audit.log");
+        let mut password_store =
+            PasswordStore::create(temp_dir.path().join("store"), "master".to_string()).unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .append(PasswordEntry::new(
+                "audited".to_string(),
+                None,
+                "hunter2".to_string(),
+            ))
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let settings = Settings {
+            audit_log: Some(audit_log.clone()),
+            ..Default::default()
+        };
+        show_password(
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut password_store,
+            "audited".to_string(),
+            None,
+            ShowFormat::Lines,
+            &settings,
+        )
+        .unwrap();
+        let log = fs::read_to_string(audit_log).unwrap();
+        assert!(log.contains(r#""service":"audited""#));
+        assert!(!log.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_remove_from_append_only_store() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store = PasswordStore::create_with_options(
+            temp_file,
+            "master".to_string(),
+            StoreOptions {
+                append_only: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .append(PasswordEntry::new(
+                "github".to_string(),
+                None,
+                "password".to_string(),
+            ))
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let mut output = Vec::new();
+        let err = remove_password(&mut output, &mut password_store, "github".to_string(), None)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("append-only"));
+        assert!(remove_password_at_index(&mut output, &mut password_store, 1).is_err());
+        assert!(dedup_passwords(&mut output, &mut password_store).is_err());
+        assert!(gc_passwords(&mut output, &mut password_store).is_err());
+        assert!(output.is_empty());
+        assert!(password_store.find("github".to_string(), None).is_some());
     }
 
     #[test]
     fn test_update_master_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let mut output = Vec::new();
-        let mut password_store = PasswordStore::new(temp_file, "master".to_string()).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, "master".to_string()).unwrap();
         update_master_password(
             &mut output,
             "new_master_password".to_string(),
@@ -468,4 +3719,155 @@ mod test {
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Master password updated successfully"));
     }
+
+    #[test]
+    fn test_print_man_page() {
+        let mut output = Vec::new();
+        print_man_page(&mut output).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.starts_with(".ie"));
+        assert!(output_str.contains(".TH lockbox 1"));
+        for subcommand in Args::command().get_subcommands() {
+            assert!(output_str.contains(&format!(".TH lockbox-{} 1", subcommand.get_name())));
+        }
+        assert!(output_str.contains("services\\-only"));
+    }
+
+    #[rstest(
+        pattern,
+        input,
+        expected,
+        case("gh", b"2\n" as &[u8], Some("github-enterprise")),
+        case("gh", b"github\n" as &[u8], Some("github")),
+        case("gh", b"3\n" as &[u8], None),
+        case("gl", b"" as &[u8], Some("gitlab")),
+        case("zz", b"" as &[u8], None)
+    )]
+    fn test_find_service_fuzzy(pattern: &str, input: &[u8], expected: Option<&str>) {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        password_store.load().unwrap();
+        for service in ["github", "github-enterprise", "gitlab"] {
+            password_store
+                .push(
+                    service.to_string(),
+                    None,
+                    "password".to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap();
+        }
+        password_store.save().unwrap();
+        let mut output = Vec::new();
+        let result = find_service_fuzzy(&mut { input }, &mut output, &mut password_store, pattern);
+        assert_eq!(result.ok().as_deref(), expected);
+    }
+
+    #[test]
+    fn test_add_password_keeps_whitespace() {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(|_| Ok("  secret  \n".to_string()));
+        add_password(
+            &mut Vec::new(),
+            &mock_prompt_password,
+            &mut password_store,
+            "service".to_string(),
+            None,
+            AddOptions::default(),
+            &Settings::default(),
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        get_fields(
+            &mut output,
+            &mut password_store,
+            "service".to_string(),
+            None,
+            &GetOptions::default(),
+            &Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "  secret  \n");
+    }
+
+    #[rstest(
+        service,
+        username,
+        ignore_case,
+        expected_error,
+        case(
+            "GitHub",
+            Some("octocat"),
+            true,
+            Some("github already has an entry for octocat")
+        ),
+        case(
+            "GITHUB",
+            Some("octocat"),
+            true,
+            Some("github already has an entry for octocat")
+        ),
+        case("GitHub", Some("octocat"), false, None),
+        case("GitHub", Some("hubot"), true, None),
+        case("GitHub", None, true, None),
+        case("gitlab", Some("octocat"), true, None)
+    )]
+    fn test_add_password_ignore_case(
+        service: &str,
+        username: Option<&str>,
+        ignore_case: bool,
+        expected_error: Option<&str>,
+    ) {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        let add = |password_store: &mut PasswordStore, service: &str, username: Option<&str>| {
+            add_password(
+                &mut Vec::new(),
+                &MockPromptPassword::new(),
+                password_store,
+                service.to_string(),
+                username.map(String::from),
+                AddOptions {
+                    password: Some("password".to_string()),
+                    ignore_case,
+                    ..Default::default()
+                },
+                &Settings::default(),
+            )
+        };
+        add(&mut password_store, "github", Some("octocat")).unwrap();
+        let result = add(&mut password_store, service, username);
+        match expected_error {
+            Some(expected_error) => {
+                assert!(result.unwrap_err().to_string().contains(expected_error));
+                assert_eq!(password_store.entries(&EntryFilter::default()).len(), 1);
+            }
+            None => {
+                result.unwrap();
+                assert_eq!(password_store.entries(&EntryFilter::default()).len(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("password"), "\x1b]52;c;cGFzc3dvcmQ=\x07");
+    }
+
+    #[rstest(
+        process_names,
+        expected,
+        case(vec!["bash", "sshd", "Xorg"], vec![]),
+        case(vec!["bash", "copyq", "klipper"], vec!["copyq", "klipper"]),
+        case(vec!["/Applications/Maccy.app/Contents/MacOS/Maccy"], vec!["maccy"]),
+        case(vec!["Ditto.exe", "ditto"], vec!["ditto"]),
+        case(vec!["copyq-helper", "klipperd"], vec![])
+    )]
+    fn test_find_clipboard_managers(process_names: Vec<&str>, expected: Vec<&str>) {
+        let managers: Vec<&str> = find_clipboard_managers(process_names).into_iter().collect();
+        assert_eq!(managers, expected);
+    }
 }
@@ -1,5 +1,19 @@
-use crossterm::style::{style, Attribute, Color, Stylize};
-use std::io::{stdout, BufRead, Error, Write};
+use super::settings::Settings;
+use crossterm::{
+    cursor::MoveTo,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    style::{style, Attribute, Color, Stylize},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+use std::io::{stdin, stdout, BufRead, Error, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// The text of password prompts, where `{prompt}` is replaced by what is asked for.
+pub const DEFAULT_PROMPT_STYLE: &str = "Please enter the {prompt}";
 
 #[cfg(test)]
 use mockall::automock;
@@ -7,6 +21,9 @@ use mockall::automock;
 #[cfg_attr(test, automock)]
 pub trait PromptPassword {
     fn prompt_password(&self, prompt: String) -> Result<String, std::io::Error>;
+    /// Whether someone at a terminal answers the prompts, so that a mistyped password can be asked
+    /// for again.
+    fn is_interactive(&self) -> bool;
 }
 
 pub struct RpasswordPromptPassword;
@@ -15,33 +32,71 @@ impl PromptPassword for RpasswordPromptPassword {
     fn prompt_password(&self, prompt: String) -> Result<String, Error> {
         rpassword::prompt_password(prompt)
     }
+
+    fn is_interactive(&self) -> bool {
+        stdin().is_terminal()
+    }
 }
 
-pub fn read_hidden_input(prompt: &str, prompt_password: &dyn PromptPassword) -> String {
+/// Reads a password without echoing it, asking with the prompt style of `settings`. Only the line
+/// ending is stripped, since leading and trailing spaces can be part of the password.
+pub fn read_hidden_input(
+    prompt: &str,
+    prompt_password: &dyn PromptPassword,
+    settings: &Settings,
+) -> String {
     let input = prompt_password
-        .prompt_password(format!(
-            "Please enter the {prompt}\n{}",
-            colorize(">> ", MessageType::DarkYellow)
-        ))
+        .prompt_password(format_prompt(&settings.prompt_style, prompt))
         .unwrap_or_else(|_| panic!("Failed to read {}", prompt));
-    input.trim().to_string()
+    strip_line_ending(&input).to_string()
+}
+
+fn format_prompt(style: &str, prompt: &str) -> String {
+    format!(
+        "{}\n{}",
+        style.replace("{prompt}", prompt),
+        colorize(">> ", MessageType::DarkYellow)
+    )
+}
+
+/// Reads a password from the first line of the open file descriptor `fd`, e.g. the read end of a
+/// pipe set up by a parent process. Like `read_hidden_input`, only the line ending is stripped.
+#[cfg(unix)]
+pub fn read_from_fd(fd: u32) -> anyhow::Result<String> {
+    // /dev/fd/N opens the file or pipe that the descriptor refers to anew, so that no unsafe code
+    // has to take the descriptor over, and it stays open for the parent process
+    let file = std::fs::File::open(format!("/dev/fd/{fd}"))?;
+    let mut input = String::new();
+    std::io::BufReader::new(file).read_line(&mut input)?;
+    Ok(strip_line_ending(&input).to_string())
+}
+
+#[cfg(not(unix))]
+pub fn read_from_fd(_fd: u32) -> anyhow::Result<String> {
+    anyhow::bail!("--master-fd is only supported on Unix")
+}
+
+fn strip_line_ending(input: &str) -> &str {
+    let input = input.strip_suffix('\n').unwrap_or(input);
+    input.strip_suffix('\r').unwrap_or(input)
 }
 
 pub fn read_hidden_input_with_confirmation<W: Write>(
     writer: &mut W,
     prompt: &str,
     prompt_password: &dyn PromptPassword,
+    settings: &Settings,
 ) -> String {
     let mut second_prompt = prompt.to_string();
     second_prompt.push_str(" again");
     let second_prompt = second_prompt.as_str();
     loop {
-        let first_input = read_hidden_input(prompt, prompt_password);
-        let second_input = read_hidden_input(second_prompt, prompt_password);
+        let first_input = read_hidden_input(prompt, prompt_password, settings);
+        let second_input = read_hidden_input(second_prompt, prompt_password, settings);
         if first_input != second_input {
             print(
                 writer,
-                format!("The {prompt}s don't match").as_str(),
+                format!("The {prompt} entries don't match").as_str(),
                 Some(MessageType::Warning),
             );
             continue;
@@ -105,6 +160,108 @@ pub fn print<W: Write>(writer: &mut W, message: &str, message_type: Option<Messa
     writeln!(writer, "{message}").unwrap_or_else(|_| println!("{message}"));
 }
 
+/// Prints `text` `page_size` lines at a time, waiting for Enter before each following page.
+/// Entering `q` stops early.
+pub fn print_paged<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    text: &str,
+    page_size: usize,
+) {
+    let lines: Vec<&str> = text.lines().collect();
+    for (index, page) in lines.chunks(page_size).enumerate() {
+        if index > 0 {
+            let input =
+                read_terminal_input(reader, writer, Some("Press Enter for more, or q to quit"));
+            if input.eq_ignore_ascii_case("q") {
+                return;
+            }
+        }
+        for line in page {
+            writeln!(writer, "{line}").unwrap_or_else(|_| println!("{line}"));
+        }
+    }
+}
+
+/// Shows `text` on the alternate screen of the terminal until a key is pressed. Leaving the
+/// alternate screen restores the previous screen, so the text does not stay in the scrollback.
+pub fn print_on_alternate_screen(text: &str) -> anyhow::Result<()> {
+    let mut stdout = stdout();
+    if !stdout.is_terminal() {
+        anyhow::bail!("--alt-screen needs the output to be a terminal");
+    }
+    let result = execute!(stdout, EnterAlternateScreen, MoveTo(0, 0))
+        .and_then(|_| enable_raw_mode())
+        // raw mode does not turn \n into \r\n
+        .and_then(|_| {
+            write!(
+                stdout,
+                "{}\r\n{}",
+                text.replace('\n', "\r\n"),
+                colorize("Press any key to continue", MessageType::DarkYellow)
+            )
+        })
+        .and_then(|_| stdout.flush())
+        .and_then(|_| loop {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    break Ok(());
+                }
+            }
+        });
+    // restore the terminal whatever failed, so that the text never stays on screen and the shell
+    // is not left in raw mode
+    let _ = disable_raw_mode();
+    let _ = execute!(stdout, LeaveAlternateScreen);
+    Ok(result?)
+}
+
+/// Waits `duration` while showing `describe(seconds left)` on one line of the terminal, updated
+/// every second. Ctrl-C ends the wait early instead of killing the process, which is read as a key
+/// press in raw mode. Without a terminal it just sleeps. Returns whether the wait was cut short.
+pub fn wait_with_countdown(duration: Duration, describe: impl Fn(u64) -> String) -> bool {
+    let deadline = Instant::now() + duration;
+    let mut stdout = stdout();
+    if !stdout.is_terminal() || !stdin().is_terminal() || enable_raw_mode().is_err() {
+        std::thread::sleep(duration);
+        return false;
+    }
+    let result = (|| -> anyhow::Result<bool> {
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(false);
+            }
+            let seconds = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+            write!(
+                stdout,
+                "\r{}",
+                colorize(&describe(seconds), MessageType::DarkYellow)
+            )?;
+            execute!(stdout, Clear(ClearType::UntilNewLine))?;
+            // wake up when the number of seconds shown changes, or on a key press
+            if event::poll(left.saturating_sub(Duration::from_secs(seconds - 1)))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press
+                        && key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL)
+                    {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+    })();
+    let _ = disable_raw_mode();
+    let _ = execute!(stdout, Clear(ClearType::CurrentLine));
+    let _ = write!(stdout, "\r");
+    let _ = stdout.flush();
+    result.unwrap_or_else(|_| {
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        false
+    })
+}
+
 pub fn print_key_value_with_color<W: Write>(
     writer: &mut W,
     key: &str,
@@ -130,6 +287,33 @@ pub fn print_key_value_with_color<W: Write>(
 mod tests {
     use super::*;
     use mockall::predicate::eq;
+    use rstest::rstest;
+
+    #[test]
+    fn test_wait_with_countdown() {
+        // with or without a terminal, the wait lasts the whole duration unless Ctrl-C is pressed
+        let start = Instant::now();
+        let interrupted =
+            wait_with_countdown(Duration::from_millis(50), |seconds| format!("{seconds}s"));
+        assert!(!interrupted);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[cfg(unix)]
+    #[rstest(
+        contents,
+        case(b"master\n" as &[u8]),
+        case(b"master\r\n" as &[u8]),
+        case(b"master" as &[u8]),
+        case(b"master\nsecond line\n" as &[u8])
+    )]
+    fn test_read_from_fd(contents: &[u8]) {
+        use std::os::unix::io::AsRawFd;
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(contents).unwrap();
+        let fd = file.as_raw_fd() as u32;
+        assert_eq!(read_from_fd(fd).unwrap(), "master");
+    }
 
     #[test]
     fn test_read_terminal_input() {
@@ -156,10 +340,51 @@ mod tests {
             .times(1)
             .returning(|_| Ok("secret".to_string()));
 
-        let input = read_hidden_input("password", &mock_prompt_password);
+        let input = read_hidden_input("password", &mock_prompt_password, &Settings::default());
         assert_eq!(input, "secret");
     }
 
+    #[rstest(
+        style,
+        expected,
+        case(
+            DEFAULT_PROMPT_STYLE,
+            "Please enter the master password for the store 'work'"
+        ),
+        case("{prompt}:", "master password for the store 'work':"),
+        case(
+            "Vault > {prompt} ({prompt})",
+            "Vault > master password for the store 'work' (master password for the store 'work')"
+        )
+    )]
+    fn test_format_prompt(style: &str, expected: &str) {
+        assert_eq!(
+            format_prompt(style, "master password for the store 'work'"),
+            format!("{expected}\n{}", colorize(">> ", MessageType::DarkYellow))
+        );
+    }
+
+    #[rstest(
+        raw_input,
+        expected,
+        case("  secret  ", "  secret  "),
+        case("  secret  \n", "  secret  "),
+        case("secret\r\n", "secret"),
+        case("secret\n\n", "secret\n"),
+        case("\tsecret", "\tsecret")
+    )]
+    fn test_read_hidden_input_keeps_whitespace(raw_input: &'static str, expected: &str) {
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(move |_| Ok(raw_input.to_string()));
+        assert_eq!(
+            read_hidden_input("password", &mock_prompt_password, &Settings::default()),
+            expected
+        );
+    }
+
     use std::io::Cursor;
 
     #[test]
@@ -177,10 +402,23 @@ mod tests {
             &mut Cursor::new(Vec::new()),
             "password",
             &mock_prompt_password,
+            &Settings::default(),
         );
         assert_eq!(input, "secret")
     }
 
+    #[test]
+    fn test_print_paged() {
+        let mut input = b"\nq\n" as &[u8];
+        let mut output = Vec::new();
+        print_paged(&mut input, &mut output, "1\n2\n3\n4\n5\n", 2);
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.starts_with("1\n2\nPress Enter for more"));
+        assert!(output_str.contains("3\n4\n"));
+        assert_eq!(output_str.matches("Press Enter for more").count(), 2);
+        assert!(!output_str.contains("5\n"));
+    }
+
     #[test]
     fn test_colorize() {
         let test_message = "test_message";
@@ -1,29 +1,385 @@
 pub mod args;
 pub mod commands;
 pub mod io;
+pub mod settings;
 
 use self::{
-    args::{get_password_store_path, Args, Command, DEFAULT_PASSWORD_FILENAME},
+    args::{
+        get_password_store_path, master_password_prompt, Args, Command, GenerateFormat,
+        PROJECT_STORE_FILENAME, STDIO_FILE_NAME,
+    },
     commands::{
-        add_password, generate_password, list_passwords, remove_password, show_password,
-        update_master_password,
+        add_password, bench_kdf, check_count, check_master_strength, dedup_passwords,
+        export_recovery_shares, find_service_fuzzy, gc_passwords, generate_master_password,
+        generate_password, generate_seeded_passwords, generate_token, get_fields,
+        length_for_entropy, list_passwords, print_key_fingerprint, print_man_page,
+        recover_password_store, remove_password, remove_password_at_index,
+        remove_passwords_older_than, rename_service, self_test, set_password_disabled,
+        set_password_disabled_at_index, show_password_at_index, show_password_by_url,
+        show_passwords, update_master_password, AddOptions, GenerateOptions, GetOptions,
+        ListOptions, NotFound,
+    },
+    io::{
+        print, print_on_alternate_screen, print_paged, read_from_fd, read_hidden_input,
+        MessageType, PromptPassword,
     },
-    io::{print, read_hidden_input, MessageType, PromptPassword},
+    settings::Settings,
 };
-use crate::{repl::repl, store::PasswordStore};
-use passwords::PasswordGenerator;
-use std::{
-    io::{BufRead, Write},
-    path::PathBuf,
+use crate::{
+    pass::EntryFilter,
+    repl::repl,
+    store::{PasswordStore, StoreError, StoreOptions},
 };
+use passwords::PasswordGenerator;
+use std::io::{stdout, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub(crate) const MASTER_PASSWORD_RETRIES: usize = 3;
+
+/// Checks the master password by loading the store. When `retry` is set (the master password was
+/// prompted for rather than passed as an argument) and the prompts are answered at a terminal, a
+/// wrong master password is re-prompted up to `MASTER_PASSWORD_RETRIES` times before giving up.
+/// Piped input gets no second chance, so that a script fails on the first wrong password.
+fn open_password_store<W: Write>(
+    writer: &mut W,
+    prompt_password: &dyn PromptPassword,
+    mut password_store: PasswordStore,
+    retry: bool,
+    settings: &Settings,
+) -> Result<PasswordStore, StoreError> {
+    let mut retries_left = if retry { MASTER_PASSWORD_RETRIES } else { 0 };
+    let prompt = master_password_prompt(password_store.file_path().map(PathBuf::as_path));
+    loop {
+        match password_store.load() {
+            Ok(_) => return Ok(password_store),
+            Err(err @ StoreError::WrongMasterPassword)
+                if retries_left > 0 && prompt_password.is_interactive() =>
+            {
+                print(writer, &err.to_string(), Some(MessageType::Warning));
+                retries_left -= 1;
+                let master = read_hidden_input(&prompt, prompt_password, settings);
+                password_store.update_master(master);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Reads the master password from `master` or a prompt. For a new store it is asked for again and
+/// checked with `check_master_strength`, where `reader` is used to confirm a weak master password
+/// that was prompted for.
+fn read_master_password<R: BufRead, W: Write>(
+    reader: Option<&mut R>,
+    writer: &mut W,
+    prompt_password: &dyn PromptPassword,
+    prompt: &str,
+    master: Option<String>,
+    is_new_store: bool,
+    settings: &Settings,
+) -> Option<String> {
+    let reader = reader.filter(|_| master.is_none());
+    let master = master.unwrap_or_else(|| read_hidden_input(prompt, prompt_password, settings));
+    if is_new_store {
+        let second_input = read_hidden_input(&format!("{prompt} again"), prompt_password, settings);
+        if master != second_input {
+            print(
+                writer,
+                "Error: The inserted master passwords don't match",
+                Some(MessageType::Error),
+            );
+            return None;
+        }
+        if let Err(err) = check_master_strength(reader, writer, &master, settings.allow_weak_master)
+        {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            return None;
+        }
+    }
+    Some(master)
+}
+
+/// Resolves `file_name` with `get_password_store_path`, printing any error to `writer`, and which
+/// project store is used when one was found in place of the default store.
+fn resolve_password_store_path<W: Write>(
+    writer: &mut W,
+    file_name: Option<String>,
+) -> Option<PathBuf> {
+    let is_default = file_name.is_none();
+    match get_password_store_path(file_name) {
+        Ok(file_path) => {
+            if is_default && file_path.ends_with(PROJECT_STORE_FILENAME) {
+                print(
+                    writer,
+                    &format!("Using the password store {}", file_path.display()),
+                    Some(MessageType::Info),
+                );
+            }
+            Some(file_path)
+        }
+        Err(err) => {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            None
+        }
+    }
+}
+
+/// Opens and loads the password store named by `file_name`, printing any error to `writer`.
+/// The file name `-` reads the encrypted store from `reader` instead of the `.lockbox` directory.
+/// A missing store is only initialized when `create` holds the options to create it with.
+fn get_password_store<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prompt_password: &dyn PromptPassword,
+    file_name: Option<String>,
+    master: Option<String>,
+    create: Option<StoreOptions>,
+    settings: &Settings,
+) -> Option<PasswordStore> {
+    let retry = master.is_none();
+    let password_store = if file_name.as_deref() == Some(STDIO_FILE_NAME) {
+        let mut contents = Vec::new();
+        if let Err(err) = reader.read_to_end(&mut contents) {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            return None;
+        }
+        if contents.is_empty() && create.is_none() {
+            print(
+                writer,
+                "Error: No password store was read from stdin; pass --create to initialize one",
+                Some(MessageType::Error),
+            );
+            return None;
+        }
+        // the store was read from `reader`, so a weak master password can't be confirmed
+        let master = read_master_password(
+            None::<&mut R>,
+            writer,
+            prompt_password,
+            &master_password_prompt(None),
+            master,
+            contents.is_empty(),
+            settings,
+        )?;
+        PasswordStore::from_reader(&mut contents.as_slice(), master, create.unwrap_or_default())
+    } else {
+        let file_path = resolve_password_store_path(writer, file_name)?;
+        let is_new_store = !PasswordStore::exists(&file_path);
+        if is_new_store && create.is_none() {
+            let err = PasswordStore::missing_store_error(file_path);
+            let hint = match err {
+                StoreError::NotFound(_) => "; pass --create to initialize it",
+                _ => "",
+            };
+            print(
+                writer,
+                &format!("Error: {err}{hint}"),
+                Some(MessageType::Error),
+            );
+            return None;
+        }
+        let master = read_master_password(
+            Some(reader),
+            writer,
+            prompt_password,
+            &master_password_prompt(Some(&file_path)),
+            master,
+            is_new_store,
+            settings,
+        )?;
+        match create.filter(|_| is_new_store) {
+            Some(options) => PasswordStore::create_with_options(file_path, master, options),
+            None => PasswordStore::open(file_path, master),
+        }
+    };
+    match password_store.and_then(|password_store| {
+        open_password_store(writer, prompt_password, password_store, retry, settings)
+    }) {
+        Ok(password_store) => Some(password_store),
+        Err(err) => {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            None
+        }
+    }
+}
+
+/// Opens the existing store named by `file_name` without loading it, so that it can be recovered
+/// and saved with `new_master`.
+fn get_password_store_to_recover<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    file_name: Option<String>,
+    new_master: String,
+) -> Option<PasswordStore> {
+    let password_store = if file_name.as_deref() == Some(STDIO_FILE_NAME) {
+        let mut contents = Vec::new();
+        if let Err(err) = reader.read_to_end(&mut contents) {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            return None;
+        }
+        if contents.is_empty() {
+            print(
+                writer,
+                "Error: No password store was read from stdin",
+                Some(MessageType::Error),
+            );
+            return None;
+        }
+        PasswordStore::from_reader(
+            &mut contents.as_slice(),
+            new_master,
+            StoreOptions::default(),
+        )
+    } else {
+        let file_path = resolve_password_store_path(writer, file_name)?;
+        PasswordStore::open(file_path, new_master)
+    };
+    password_store
+        .map_err(|err| print(writer, &format!("Error: {err}"), Some(MessageType::Error)))
+        .ok()
+}
+
+/// Writes a changed stream store to `writer` once a command is done with it.
+fn write_stream_store<W: Write>(writer: &mut W, password_store: &PasswordStore) {
+    if let Err(err) = password_store.write_to(writer) {
+        eprintln!("Error: Failed to write the password store: {err}");
+    }
+}
+
+/// Warns that the passwords given to `options` on the command line leak into the shell history
+/// and the process list, or refuses them with `strict_security`.
+fn check_secret_options<W: Write>(
+    writer: &mut W,
+    options: &[&str],
+    strict_security: bool,
+    no_master_warning: bool,
+) -> anyhow::Result<()> {
+    if options.is_empty() {
+        return Ok(());
+    }
+    let options = options.join(" and ");
+    if strict_security {
+        return Err(anyhow::anyhow!(
+            "{options} can't be used with --strict-security, the password would leak into the shell history and the process list. Leave it out to be prompted, or use --master-fd"
+        ));
+    }
+    if !no_master_warning {
+        print(
+            writer,
+            &format!("Warning: {options} leaks the password into the shell history and the process list. Leave it out to be prompted, or use --master-fd. Pass --no-master-warning to hide this warning"),
+            Some(MessageType::Warning),
+        );
+    }
+    Ok(())
+}
+
+/// The exit code of `show`, `remove` and `edit` when they fail for any other reason than a missing
+/// entry, e.g. a wrong master password.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// The exit code of `show`, `remove` and `edit` when the entry does not exist.
+pub const EXIT_NOT_FOUND: i32 = 3;
+
+/// Reports the error of a command acting on one entry and returns the exit code. A missing entry
+/// is a warning, left out with `quiet`, and exits with `EXIT_NOT_FOUND`. Other errors are printed
+/// and exit with `EXIT_FAILURE`.
+pub(crate) fn report_entry_error<W: Write>(writer: &mut W, err: anyhow::Error, quiet: bool) -> i32 {
+    match err.downcast_ref::<NotFound>() {
+        Some(_) if quiet => EXIT_NOT_FOUND,
+        Some(not_found) => {
+            print(writer, &not_found.to_string(), Some(MessageType::Warning));
+            EXIT_NOT_FOUND
+        }
+        None => {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            EXIT_FAILURE
+        }
+    }
+}
 
-pub fn run_cli<R: BufRead, W: Write>(
+/// Whether generated passwords must contain every enabled kind of characters. `--strict` and
+/// `--no-strict` override each other, so at most one of them is set, and strict is the default.
+fn is_strict(strict: bool, no_strict: bool) -> bool {
+    strict || !no_strict
+}
+
+/// Runs the command of `args` and returns the exit code of the process, see `EXIT_FAILURE` and
+/// `EXIT_NOT_FOUND`. Output that must not mix with the data on `writer`, like warnings and the
+/// errors of `show`, goes to `error_writer`, which is stderr outside of tests.
+pub fn run_cli<R: BufRead, W: Write, E: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    error_writer: &mut E,
+    prompt_password: &dyn PromptPassword,
+    args: Args,
+) -> i32 {
+    let settings = Settings::from_args(&args);
+    let mut exit_code = 0;
+    run_command(
+        reader,
+        writer,
+        error_writer,
+        prompt_password,
+        args,
+        &settings,
+        &mut exit_code,
+    );
+    exit_code
+}
+
+fn run_command<R: BufRead, W: Write, E: Write>(
     reader: &mut R,
     writer: &mut W,
+    error_writer: &mut E,
     prompt_password: &dyn PromptPassword,
     args: Args,
+    settings: &Settings,
+    exit_code: &mut i32,
 ) {
-    match args.command {
+    // Commands that change a store read from stdin print their messages to `error_writer`, i.e.
+    // stderr, so that stdout only carries the re-encrypted store.
+    let mut command = args.command;
+    // warnings go to stderr, so that they never end up in output piped to another program
+    if let Err(err) = check_secret_options(
+        error_writer,
+        &command.secret_options(),
+        args.strict_security,
+        args.no_master_warning,
+    ) {
+        print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+        return;
+    }
+    if let Some(fd) = args.master_fd {
+        match command.master_mut() {
+            Some(master) if master.is_none() => match read_from_fd(fd) {
+                Ok(password) => *master = Some(password),
+                Err(err) => {
+                    let message =
+                        format!("Failed to read the master password from descriptor {fd}: {err}");
+                    print(
+                        writer,
+                        &format!("Error: {message}"),
+                        Some(MessageType::Error),
+                    );
+                    return;
+                }
+            },
+            Some(_) => (),
+            None => {
+                print(
+                    writer,
+                    "Error: --master-fd can't be used with this command",
+                    Some(MessageType::Error),
+                );
+                return;
+            }
+        }
+    }
+    let create = args.create.then_some(StoreOptions {
+        cipher: args.cipher,
+        append_only: args.append_only,
+    });
+    match command {
         Command::Add {
             file_name,
             service,
@@ -36,6 +392,16 @@ pub fn run_cli<R: BufRead, W: Write>(
             uppercase,
             lowercase,
             numbers,
+            strict,
+            no_strict,
+            meta,
+            favorite,
+            ignore_case,
+            ttl,
+            phonetic,
+            clipboard_ttl,
+            upsert,
+            folder,
         } => {
             let password_generator = PasswordGenerator::new()
                 .length(length.get_val())
@@ -43,89 +409,186 @@ pub fn run_cli<R: BufRead, W: Write>(
                 .uppercase_letters(uppercase)
                 .numbers(numbers)
                 .symbols(symbols)
-                .strict(true);
-            let master =
-                master.unwrap_or_else(|| read_hidden_input("master password", prompt_password));
-            let file_path = get_password_store_path(file_name)
-                .unwrap_or(PathBuf::from(DEFAULT_PASSWORD_FILENAME));
-            if !file_path.exists() {
-                let second_input = read_hidden_input("master password again", prompt_password);
-                if master != second_input {
-                    print(
-                        writer,
-                        "Error: The inserted master passwords don't match",
-                        Some(MessageType::Error),
-                    );
-                    return;
-                }
-            }
-            let mut password_store = match PasswordStore::new(file_path, master) {
-                Ok(password_store) => password_store,
-                Err(err) => {
-                    writeln!(writer, "{}", err).unwrap_or_else(|_| println!("{}", err));
-                    return;
-                }
+                .strict(is_strict(strict, no_strict));
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
             };
             match add_password(
-                writer,
+                &mut output,
                 prompt_password,
                 &mut password_store,
                 service,
                 username,
-                password,
-                generate,
-                password_generator,
+                AddOptions {
+                    password,
+                    password_generator: generate.then_some(password_generator),
+                    metadata: meta.into_iter().collect(),
+                    favorite,
+                    ignore_case,
+                    ttl,
+                    phonetic,
+                    clipboard_ttl,
+                    upsert,
+                    folder,
+                },
+                settings,
             ) {
                 Ok(_) => print(
-                    writer,
+                    &mut output,
                     "Password added successfully",
                     Some(MessageType::Success),
                 ),
-                Err(err) => print(writer, &format!("Error: {}", err), Some(MessageType::Error)),
+                Err(err) => print(
+                    &mut output,
+                    &format!("Error: {}", err),
+                    Some(MessageType::Error),
+                ),
             }
+            write_stream_store(writer, &password_store);
         }
         Command::Generate {
             length,
+            bits,
             symbols,
             uppercase,
             lowercase,
             numbers,
+            strict,
+            no_strict,
             count,
-        } => match generate_password(
-            writer, length, symbols, uppercase, lowercase, numbers, count,
-        ) {
-            Ok(_) => (),
-            Err(err) => print(writer, &format!("Error: {}", err), Some(MessageType::Error)),
-        },
+            max_count,
+            format,
+            bytes,
+            clipboard,
+            clear_after,
+            phonetic,
+            seed,
+            prefix,
+            suffix,
+        } => {
+            let strict = is_strict(strict, no_strict);
+            let clipboard = clipboard.then(|| Duration::from_secs(clear_after));
+            let result = check_count(count, max_count).and_then(|_| match format {
+                GenerateFormat::Password => {
+                    let length = match bits {
+                        // the entropy comes from the random part alone, the affixes are added
+                        Some(bits) => {
+                            length_for_entropy(bits, symbols, uppercase, lowercase, numbers)?
+                                + prefix.chars().count()
+                                + suffix.chars().count()
+                        }
+                        None => length.get_val(),
+                    };
+                    let options = GenerateOptions {
+                        length,
+                        symbols,
+                        uppercase,
+                        lowercase,
+                        numbers,
+                        strict,
+                        count,
+                        phonetic,
+                        prefix,
+                        suffix,
+                    };
+                    match seed {
+                        Some(seed) => generate_seeded_passwords(writer, &options, seed),
+                        None => generate_password(writer, &options, clipboard, settings),
+                    }
+                }
+                _ if seed.is_some() => Err(anyhow::anyhow!(
+                    "--seed only applies to passwords, tokens are always random"
+                )),
+                _ if !prefix.is_empty() || !suffix.is_empty() => Err(anyhow::anyhow!(
+                    "--prefix and --suffix only apply to passwords"
+                )),
+                _ => {
+                    let bytes = bits.map_or(bytes, |bits| (bits as usize).div_ceil(8));
+                    generate_token(writer, format, bytes, count, clipboard, phonetic, settings)
+                }
+            });
+            match result {
+                Ok(_) => (),
+                Err(err) => print(writer, &format!("Error: {}", err), Some(MessageType::Error)),
+            }
+        }
         Command::List {
             file_name,
             master,
             show_passwords,
+            reveal_service,
+            services_only,
+            favorites,
+            exclude,
+            include_disabled,
+            page_size,
+            limit,
+            show_index,
+            table,
+            format,
+            folder,
+            tree,
         } => {
-            let master =
-                master.unwrap_or_else(|| read_hidden_input("master password", prompt_password));
-            let file_path = get_password_store_path(file_name)
-                .unwrap_or(PathBuf::from(DEFAULT_PASSWORD_FILENAME));
-            if !file_path.exists() {
-                let second_input = read_hidden_input("master password again", prompt_password);
-                if master != second_input {
-                    print(
-                        writer,
-                        "Error: The inserted master passwords don't match",
-                        Some(MessageType::Error),
-                    );
-                    return;
-                }
-            }
-            let mut password_store = match PasswordStore::new(file_path, master) {
-                Ok(password_store) => password_store,
-                Err(err) => {
-                    print(writer, &err.to_string(), Some(MessageType::Error));
-                    return;
-                }
+            // only paginate when a person is reading the output and can press Enter
+            let page_size = page_size.filter(|_| {
+                file_name.as_deref() != Some(STDIO_FILE_NAME) && stdout().is_terminal()
+            });
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
             };
-            match list_passwords(writer, &mut password_store, show_passwords) {
-                Ok(_) => (),
+            let mut output = Vec::new();
+            match list_passwords(
+                &mut output,
+                &mut password_store,
+                &EntryFilter {
+                    favorites_only: favorites,
+                    exclude,
+                    include_disabled,
+                    folder,
+                },
+                &ListOptions {
+                    show_passwords,
+                    reveal_services: reveal_service,
+                    services_only,
+                    limit,
+                    show_index,
+                    table,
+                    tree,
+                    format,
+                },
+                settings,
+            ) {
+                Ok(_) => match page_size {
+                    Some(page_size) => {
+                        print_paged(reader, writer, &String::from_utf8_lossy(&output), page_size)
+                    }
+                    None => writer.write_all(&output).unwrap_or_else(|err| {
+                        eprintln!("Error: Failed to write the password list: {err}")
+                    }),
+                },
                 Err(err) => print(writer, &format!("Error: {}", err), Some(MessageType::Error)),
             }
         }
@@ -134,124 +597,557 @@ pub fn run_cli<R: BufRead, W: Write>(
             service,
             username,
             master,
+            index,
+            older_than,
+            yes,
+            quiet,
         } => {
-            let master =
-                master.unwrap_or_else(|| read_hidden_input("master password", prompt_password));
-            let file_path = get_password_store_path(file_name)
-                .unwrap_or(PathBuf::from(DEFAULT_PASSWORD_FILENAME));
-            if !file_path.exists() {
-                let second_input = read_hidden_input("master password again", prompt_password);
-                if master != second_input {
-                    print(
-                        writer,
-                        "Error: The inserted master passwords don't match",
-                        Some(MessageType::Error),
-                    );
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => {
+                    *exit_code = EXIT_FAILURE;
                     return;
                 }
+            };
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
+            };
+            let result = match (index, older_than) {
+                (Some(index), _) => {
+                    remove_password_at_index(&mut output, &mut password_store, index)
+                }
+                (None, Some(age)) => {
+                    remove_passwords_older_than(reader, &mut output, &mut password_store, age, yes)
+                }
+                (None, None) => remove_password(
+                    &mut output,
+                    &mut password_store,
+                    service.unwrap_or_default(),
+                    username,
+                ),
+            };
+            if let Err(err) = result {
+                *exit_code = report_entry_error(&mut output, err, quiet);
             }
-            let mut password_store = match PasswordStore::new(file_path, master) {
-                Ok(password_store) => password_store,
-                Err(err) => {
-                    print(writer, &err.to_string(), None);
+            write_stream_store(writer, &password_store);
+        }
+        Command::Edit {
+            file_name,
+            service,
+            username,
+            master,
+            index,
+            disable,
+            enable: _,
+            quiet,
+        } => {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => {
+                    *exit_code = EXIT_FAILURE;
                     return;
                 }
             };
-            match remove_password(writer, &mut password_store, service, username) {
-                Ok(_) => (),
-                Err(err) => print(writer, &format!("Error: {}", err), None),
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
+            };
+            let result = match index {
+                Some(index) => {
+                    set_password_disabled_at_index(&mut output, &mut password_store, index, disable)
+                }
+                None => set_password_disabled(
+                    &mut output,
+                    &mut password_store,
+                    service.unwrap_or_default(),
+                    username,
+                    disable,
+                ),
+            };
+            if let Err(err) = result {
+                *exit_code = report_entry_error(&mut output, err, quiet);
             }
+            write_stream_store(writer, &password_store);
         }
         Command::Show {
             file_name,
             service,
             username,
             master,
+            alt_screen,
+            fuzzy,
+            index,
+            format,
+            url,
+            quiet,
         } => {
-            let master =
-                master.unwrap_or_else(|| read_hidden_input("master password", prompt_password));
-            let file_path = get_password_store_path(file_name)
-                .unwrap_or(PathBuf::from(DEFAULT_PASSWORD_FILENAME));
-            if !file_path.exists() {
-                let second_input = read_hidden_input("master password again", prompt_password);
-                if master != second_input {
-                    print(
-                        writer,
-                        "Error: The inserted master passwords don't match",
-                        Some(MessageType::Error),
-                    );
+            // like `get`, stdout only carries the entries, so that scripts can capture them, and
+            // everything else goes to stderr
+            let mut password_store = match get_password_store(
+                reader,
+                error_writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => {
+                    *exit_code = EXIT_FAILURE;
                     return;
                 }
-            }
-            let mut password_store = match PasswordStore::new(file_path, master) {
-                Ok(password_store) => password_store,
-                Err(err) => {
-                    print(writer, &format!("Error: {}", err), None);
-                    return;
+            };
+            let services = if fuzzy {
+                match service
+                    .iter()
+                    .map(|service| {
+                        find_service_fuzzy(reader, error_writer, &mut password_store, service)
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()
+                {
+                    Ok(services) => services,
+                    Err(err) => {
+                        *exit_code = report_entry_error(error_writer, err, quiet);
+                        return;
+                    }
                 }
+            } else {
+                service
+            };
+            let show =
+                |mut output: &mut dyn Write,
+                 messages: &mut E,
+                 password_store: &mut PasswordStore| match (index, url) {
+                    (Some(index), _) => show_password_at_index(
+                        &mut output,
+                        messages,
+                        password_store,
+                        index,
+                        format,
+                        settings,
+                    ),
+                    (None, Some(url)) => show_password_by_url(
+                        &mut output,
+                        messages,
+                        password_store,
+                        &url,
+                        format,
+                        settings,
+                    ),
+                    (None, None) => show_passwords(
+                        &mut output,
+                        messages,
+                        password_store,
+                        &services,
+                        username,
+                        format,
+                        settings,
+                    ),
+                };
+            let result = if alt_screen {
+                let mut output = Vec::new();
+                show(&mut output, error_writer, &mut password_store)
+                    .and_then(|_| print_on_alternate_screen(&String::from_utf8_lossy(&output)))
+            } else {
+                show(writer, error_writer, &mut password_store)
+            };
+            if let Err(err) = result {
+                *exit_code = report_entry_error(error_writer, err, quiet);
+            }
+        }
+        Command::Get {
+            file_name,
+            service,
+            username,
+            master,
+            field,
+            fields,
+            separator,
+            no_newline,
+        } => {
+            // keep stdout to the bare value so that it can be captured by scripts
+            let mut password_store = match get_password_store(
+                reader,
+                error_writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
             };
-            match show_password(writer, &mut password_store, service, username) {
+            let fields = if fields.is_empty() {
+                vec![field]
+            } else {
+                fields
+            };
+            if let Err(err) = get_fields(
+                writer,
+                &mut password_store,
+                service,
+                username,
+                &GetOptions {
+                    fields,
+                    separator,
+                    no_newline,
+                },
+                settings,
+            ) {
+                print(
+                    error_writer,
+                    &format!("Error: {}", err),
+                    Some(MessageType::Error),
+                );
+            }
+        }
+        Command::Dedup { file_name, master } => {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
+            };
+            match dedup_passwords(&mut output, &mut password_store) {
                 Ok(_) => (),
-                Err(err) => print(writer, &format!("Error: {}", err), Some(MessageType::Error)),
+                Err(err) => print(
+                    &mut output,
+                    &format!("Error: {}", err),
+                    Some(MessageType::Error),
+                ),
+            }
+            write_stream_store(writer, &password_store);
+        }
+        Command::RenameService {
+            file_name,
+            from,
+            to,
+            master,
+        } => {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
+            };
+            if let Err(err) = rename_service(&mut output, &mut password_store, &from, &to) {
+                print(
+                    &mut output,
+                    &format!("Error: {}", err),
+                    Some(MessageType::Error),
+                );
+            }
+            write_stream_store(writer, &password_store);
+        }
+        Command::Gc { file_name, master } => {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
+            };
+            if let Err(err) = gc_passwords(&mut output, &mut password_store) {
+                print(
+                    &mut output,
+                    &format!("Error: {}", err),
+                    Some(MessageType::Error),
+                );
+            }
+            write_stream_store(writer, &password_store);
+        }
+        Command::Fingerprint { file_name, master } => {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            if let Err(err) = print_key_fingerprint(writer, &mut password_store) {
+                print(writer, &format!("Error: {}", err), Some(MessageType::Error));
+            }
+        }
+        Command::BenchKdf { runs } => bench_kdf(writer, runs),
+        Command::SelfTest => {
+            if let Err(err) = self_test(writer) {
+                print(writer, &format!("Error: {}", err), Some(MessageType::Error));
             }
         }
         Command::UpdateMaster {
             file_name,
             master,
             new_master,
+            generate,
+            length,
         } => {
-            let master =
-                master.unwrap_or_else(|| read_hidden_input("master password", prompt_password));
-            let file_path = get_password_store_path(file_name)
-                .unwrap_or(PathBuf::from(DEFAULT_PASSWORD_FILENAME));
-            if !file_path.exists() {
-                let second_input = read_hidden_input("master password again", prompt_password);
-                if master != second_input {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
+            };
+            let new_master = if generate {
+                match generate_master_password(length) {
+                    Ok(new_master) => new_master,
+                    Err(err) => {
+                        print(
+                            &mut output,
+                            &format!("Error: {err}"),
+                            Some(MessageType::Error),
+                        );
+                        return;
+                    }
+                }
+            } else {
+                // a weak new master password can only be confirmed when it was typed at a prompt
+                // and the store was not read from `reader`
+                let confirm = new_master.is_none() && !password_store.is_stream();
+                let new_master = new_master.unwrap_or_else(|| {
+                    read_hidden_input("new password", prompt_password, settings)
+                });
+                let second_input =
+                    read_hidden_input("new password again", prompt_password, settings);
+                if new_master != second_input {
                     print(
-                        writer,
-                        "Error: The inserted master passwords don't match",
+                        &mut output,
+                        "Error: The inserted new passwords don't match",
                         Some(MessageType::Error),
                     );
                     return;
                 }
-            }
-            let new_master =
-                new_master.unwrap_or_else(|| read_hidden_input("new password", prompt_password));
-            let second_input = read_hidden_input("new password again", prompt_password);
-            if new_master != second_input {
-                print(
-                    writer,
-                    "Error: The inserted new passwords don't match",
+                if let Err(err) = check_master_strength(
+                    Some(&mut *reader).filter(|_| confirm),
+                    &mut output,
+                    &new_master,
+                    settings.allow_weak_master,
+                ) {
+                    print(
+                        &mut output,
+                        &format!("Error: {err}"),
+                        Some(MessageType::Error),
+                    );
+                    return;
+                }
+                new_master
+            };
+            match update_master_password(&mut output, new_master.clone(), &mut password_store) {
+                // the generated master is only shown once the store is encrypted with it
+                Ok(()) if generate => {
+                    print(
+                        &mut output,
+                        &format!("New master password: {new_master}"),
+                        None,
+                    );
+                    print(
+                        &mut output,
+                        "Save the new master password now, e.g. in another password manager. It is not shown again.",
+                        Some(MessageType::Warning),
+                    );
+                }
+                Ok(()) => (),
+                Err(err) => print(
+                    &mut output,
+                    &format!("Failed to update master password: {err}"),
                     Some(MessageType::Error),
-                );
-                return;
+                ),
             }
-            let mut password_store = match PasswordStore::new(file_path, master) {
-                Ok(password_store) => password_store,
-                Err(err) => {
-                    print(writer, &format!("Error: {}", err), None);
-                    return;
+            write_stream_store(writer, &password_store);
+        }
+        Command::ExportRecovery {
+            file_name,
+            master,
+            shares,
+            threshold,
+        } => {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            export_recovery_shares(writer, &mut password_store, shares, threshold).unwrap_or_else(
+                |err| print(writer, &format!("Error: {}", err), Some(MessageType::Error)),
+            );
+        }
+        Command::Recover {
+            file_name,
+            share,
+            new_master,
+        } => {
+            // like in `update-master`, a weak new master password can only be confirmed when it
+            // was typed at a prompt and the store is not read from `reader`
+            let confirm = new_master.is_none() && file_name.as_deref() != Some(STDIO_FILE_NAME);
+            let new_master = match new_master {
+                Some(new_master) => new_master,
+                None => {
+                    let new_master = read_hidden_input("new password", prompt_password, settings);
+                    if new_master
+                        != read_hidden_input("new password again", prompt_password, settings)
+                    {
+                        print(
+                            error_writer,
+                            "Error: The inserted new passwords don't match",
+                            Some(MessageType::Error),
+                        );
+                        return;
+                    }
+                    new_master
                 }
             };
-            update_master_password(writer, new_master, &mut password_store).unwrap_or_else(|err| {
+            if let Err(err) = check_master_strength(
+                Some(&mut *reader).filter(|_| confirm),
+                error_writer,
+                &new_master,
+                settings.allow_weak_master,
+            ) {
                 print(
-                    writer,
-                    &format!("Failed to update master password: {err}"),
+                    error_writer,
+                    &format!("Error: {err}"),
                     Some(MessageType::Error),
                 );
-            });
+                return;
+            }
+            let mut password_store =
+                match get_password_store_to_recover(reader, writer, file_name, new_master) {
+                    Some(password_store) => password_store,
+                    None => return,
+                };
+            let mut output: &mut dyn Write = if password_store.is_stream() {
+                error_writer
+            } else {
+                writer
+            };
+            recover_password_store(&mut output, &mut password_store, &share).unwrap_or_else(
+                |err| {
+                    print(
+                        &mut output,
+                        &format!("Error: {}", err),
+                        Some(MessageType::Error),
+                    )
+                },
+            );
+            write_stream_store(writer, &password_store);
+        }
+        Command::Man => print_man_page(writer).unwrap_or_else(|err| {
+            print(writer, &format!("Error: {}", err), Some(MessageType::Error))
+        }),
+        #[cfg(feature = "serve")]
+        Command::Serve {
+            file_name,
+            master,
+            port,
+        } => {
+            let mut password_store = match get_password_store(
+                reader,
+                writer,
+                prompt_password,
+                file_name,
+                master,
+                create,
+                settings,
+            ) {
+                Some(password_store) => password_store,
+                None => return,
+            };
+            if let Err(err) = crate::serve::serve(writer, &mut password_store, port, settings) {
+                print(writer, &format!("Error: {}", err), Some(MessageType::Error));
+            }
         }
-        Command::Repl { file_name } => repl(reader, writer, prompt_password, file_name),
+        Command::Repl { file_name, format } => repl(
+            reader,
+            writer,
+            prompt_password,
+            file_name,
+            create,
+            format,
+            settings,
+        ),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cli::io::MockPromptPassword;
+    use crate::cli::io::{colorize, MockPromptPassword};
     use clap::Parser;
     use rstest::rstest;
-    use std::io::Cursor;
+    use std::{collections::HashMap, io::Cursor};
 
     use tempfile::NamedTempFile;
 
@@ -272,6 +1168,36 @@ mod tests {
             vec!["Random password generated."],
             false
         ),
+        case(
+            vec!["lockbox", "generate", "--format", "hex", "--bytes", "8"],
+            b"",
+            vec!["Random token generated."],
+            false
+        ),
+        case(
+            vec!["lockbox", "generate", "--length", "8", "--prefix", "abcdef", "--no-strict", "--strict"],
+            b"",
+            vec!["A strict password of length 2 cannot contain all 3 enabled kinds of characters"],
+            false
+        ),
+        case(
+            vec!["lockbox", "generate", "--length", "8", "--prefix", "abcdef", "--strict", "--no-strict"],
+            b"",
+            vec!["Random password generated."],
+            false
+        ),
+        case(
+            vec!["lockbox", "generate", "--count", "100001"],
+            b"",
+            vec!["Error: --count 100001 is more than the limit of 100000, pass --max-count to raise it"],
+            false
+        ),
+        case(
+            vec!["lockbox", "generate", "--format", "hex", "--count", "3", "--max-count", "2"],
+            b"",
+            vec!["Error: --count 3 is more than the limit of 2"],
+            false
+        ),
         case(
             vec!["lockbox", "list", "--master", "test_master_password", "--reveal"],
             b"",
@@ -289,6 +1215,12 @@ mod tests {
             b"",
             vec!["Password:", "password"],
             true
+        ),
+        case(
+            vec!["lockbox", "dedup", "--master", "test_master_password"],
+            b"",
+            vec!["Removed 0 duplicate password(s)"],
+            true
         )
     )]
     fn test_run_cli(
@@ -302,7 +1234,7 @@ mod tests {
         let mut temp_writer = std::io::Cursor::new(Vec::new());
 
         let mut password_store =
-            PasswordStore::new(temp_file.clone(), "test_master_password".to_string()).unwrap();
+            PasswordStore::create(temp_file.clone(), "test_master_password".to_string()).unwrap();
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
             &mut temp_writer,
@@ -310,9 +1242,11 @@ mod tests {
             &mut password_store,
             "service".to_string(),
             Some("username".to_string()),
-            Some("password".to_string()),
-            false,
-            PasswordGenerator::default(),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
         )
         .unwrap();
 
@@ -327,7 +1261,13 @@ mod tests {
         let mut output = Vec::new();
         let mock_prompt_password = &MockPromptPassword::new();
 
-        run_cli(&mut input, &mut output, mock_prompt_password, args);
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut std::io::sink(),
+            mock_prompt_password,
+            args,
+        );
 
         let output_str = String::from_utf8(output).unwrap();
         for item in expected_output {
@@ -342,12 +1282,19 @@ mod tests {
         expected_output,
         use_temp_file,
         case(
-            vec!["lockbox", "update-master", "--master", "test_master_password", "--new-master", "new_master_password"],
+            vec!["lockbox", "update-master", "--master", "test_master_password", "--new-master", "new_master_password", "--allow-weak-master"],
             b"",
             "new_master_password",
             vec!["Master password updated successfully"],
             true
         ),
+        case(
+            vec!["lockbox", "update-master", "--master", "test_master_password", "--new-master", "master"],
+            b"yes\n",
+            "master",
+            vec!["Error: The master password is weak, it scores 30 out of 100. Pass --allow-weak-master to use it anyway"],
+            true
+        ),
         case(
             vec!["lockbox", "update-master", "--master", "test_master_password", "--new-master", "new_master_password"],
             b"",
@@ -368,7 +1315,7 @@ mod tests {
         let mut temp_writer = std::io::Cursor::new(Vec::new());
 
         let mut password_store =
-            PasswordStore::new(temp_file.clone(), "test_master_password".to_string()).unwrap();
+            PasswordStore::create(temp_file.clone(), "test_master_password".to_string()).unwrap();
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
             &mut temp_writer,
@@ -376,9 +1323,11 @@ mod tests {
             &mut password_store,
             "service".to_string(),
             Some("username".to_string()),
-            Some("password".to_string()),
-            false,
-            PasswordGenerator::default(),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
         )
         .unwrap();
 
@@ -397,7 +1346,13 @@ mod tests {
             .times(1)
             .returning(|_| Ok(prompt_input.to_string()));
 
-        run_cli(&mut input, &mut output, &mock_prompt_password, args);
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut std::io::sink(),
+            &mock_prompt_password,
+            args,
+        );
 
         let output_str = String::from_utf8(output).unwrap();
         for item in expected_output {
@@ -405,6 +1360,111 @@ mod tests {
         }
     }
 
+    #[rstest(
+        master,
+        allow_weak_master,
+        input,
+        expected_output,
+        case(
+            Some("master"),
+            false,
+            b"yes\n",
+            "Pass --allow-weak-master to use it anyway"
+        ),
+        case(Some("master"), true, b"", "Password added successfully"),
+        case(None, false, b"yes\n", "Password added successfully"),
+        case(None, false, b"no\n", "Error: Choose a stronger master password"),
+        case(
+            Some("correct horse battery staple"),
+            false,
+            b"",
+            "Password added successfully"
+        )
+    )]
+    fn test_run_cli_new_store_weak_master(
+        master: Option<&'static str>,
+        allow_weak_master: bool,
+        input: &[u8],
+        expected_output: &str,
+    ) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let file_name = file_path.to_str().unwrap();
+        let mut args = vec![
+            "lockbox",
+            "add",
+            "--file-name",
+            file_name,
+            "--service",
+            "service",
+            "--password",
+            "password",
+            "--create",
+        ];
+        if let Some(master) = master {
+            args.extend(["--master", master]);
+        }
+        if allow_weak_master {
+            args.push("--allow-weak-master");
+        }
+        let prompted = master.unwrap_or("master");
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(if master.is_some() { 1 } else { 2 })
+            .returning(move |_| Ok(prompted.to_string()));
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(input),
+            &mut output,
+            &mut std::io::sink(),
+            &mock_prompt_password,
+            Args::parse_from(args),
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains(expected_output));
+        let added = expected_output == "Password added successfully";
+        assert_eq!(PasswordStore::exists(&file_path), added);
+    }
+
+    #[test]
+    fn test_run_cli_update_master_generate() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        PasswordStore::create(temp_file.clone(), "test_master_password".to_string()).unwrap();
+        let temp_file_str = temp_file.to_string_lossy().to_string();
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "update-master",
+            "--master",
+            "test_master_password",
+            "--generate",
+            "--length",
+            "16",
+            "--file-name",
+            &temp_file_str,
+        ]);
+        let mut output = Vec::new();
+        // no prompts for the new master password
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Master password updated successfully"));
+        assert!(output_str.contains("It is not shown again"));
+        let new_master = output_str
+            .lines()
+            .find_map(|line| line.strip_prefix("New master password: "))
+            .unwrap();
+        assert_eq!(new_master.chars().count(), 16);
+        let mut password_store = PasswordStore::open(temp_file, new_master.to_string()).unwrap();
+        assert!(password_store.load().is_ok());
+    }
+
     #[rstest(
         args,
         input,
@@ -435,6 +1495,7 @@ mod tests {
         let mut args = args;
         args.push("--file-name");
         args.push(&temp_file_str);
+        args.push("--create");
         let args = Args::parse_from(args);
         let mut input = Cursor::new(input);
         let mut output = Vec::new();
@@ -443,23 +1504,737 @@ mod tests {
             .expect_prompt_password()
             .times(1)
             .returning(|_| Ok("wrong_test_master_password".to_string()));
-        run_cli(&mut input, &mut output, &mock_prompt_password, args);
+        let show = matches!(args.command, Command::Show { .. });
+        let mut errors = Vec::new();
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut errors,
+            &mock_prompt_password,
+            args,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        let errors_str = String::from_utf8(errors).unwrap();
+        // `show` reports on the error writer
+        let (messages, other) = if show {
+            (errors_str, output_str)
+        } else {
+            (output_str, errors_str)
+        };
+        assert!(messages.contains("Error: The inserted master passwords don't match"));
+        assert!(!other.contains("Error"));
+    }
+
+    #[rstest(
+        prompt_inputs,
+        passed_master,
+        interactive,
+        expected_output,
+        expected_errors,
+        case(vec!["wrong_master_password", "test_master_password"], false, true, vec!["Password:", "password"], vec!["Master password incorrect"]),
+        case(vec!["wrong_master_password"; 4], false, true, vec![], vec!["Error: Master password incorrect"]),
+        case(vec!["wrong_master_password"], false, false, vec![], vec!["Error: Master password incorrect"]),
+        case(vec![], true, true, vec![], vec!["Error: Master password incorrect"])
+    )]
+    fn test_run_cli_retry_wrong_master(
+        prompt_inputs: Vec<&'static str>,
+        passed_master: bool,
+        interactive: bool,
+        expected_output: Vec<&str>,
+        expected_errors: Vec<&str>,
+    ) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "test_master_password".to_string()).unwrap();
+        add_password(
+            &mut std::io::Cursor::new(Vec::new()),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "service".to_string(),
+            Some("username".to_string()),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+
+        let temp_file_str = temp_file.to_string_lossy().to_string();
+        let mut args = vec![
+            "lockbox",
+            "show",
+            "--service",
+            "service",
+            "--username",
+            "username",
+            "--file-name",
+            &temp_file_str,
+        ];
+        if passed_master {
+            args.push("--master");
+            args.push("wrong_master_password");
+        }
+        let args = Args::parse_from(args);
+
+        let mut mock_prompt_password = MockPromptPassword::new();
+        for prompt_input in prompt_inputs {
+            mock_prompt_password
+                .expect_prompt_password()
+                .times(1)
+                .returning(move |_| Ok(prompt_input.to_string()));
+        }
+        mock_prompt_password
+            .expect_is_interactive()
+            .return_const(interactive);
+        let mut input = Cursor::new(b"");
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut errors,
+            &mock_prompt_password,
+            args,
+        );
+
+        let output_str = String::from_utf8(output).unwrap();
+        let errors_str = String::from_utf8(errors).unwrap();
+        if expected_output.is_empty() {
+            assert!(output_str.is_empty());
+        }
+        for item in expected_output {
+            assert!(output_str.contains(item));
+        }
+        for item in expected_errors {
+            assert!(errors_str.contains(item));
+        }
+    }
+
+    #[test]
+    fn test_run_cli_stdio_store() {
+        let mut password_store = PasswordStore::from_reader(
+            &mut (b"" as &[u8]),
+            "test_master_password".to_string(),
+            StoreOptions::default(),
+        )
+        .unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "service".to_string(),
+            Some("username".to_string()),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let mut encrypted_store = Vec::new();
+        password_store.write_to(&mut encrypted_store).unwrap();
+
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "add",
+            "--file-name",
+            "-",
+            "--service",
+            "service2",
+            "--password",
+            "password2",
+            "--master",
+            "test_master_password",
+        ]);
+        let mut input = Cursor::new(encrypted_store);
+        let mut output = Vec::new();
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "list",
+            "--file-name",
+            "-",
+            "--reveal",
+            "--master",
+            "test_master_password",
+        ]);
+        let mut input = Cursor::new(output);
+        let mut output = Vec::new();
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        for item in ["service", "username", "password", "service2", "password2"] {
+            assert!(output_str.contains(item));
+        }
+    }
+
+    #[rstest(
+        field_args,
+        no_newline,
+        expected,
+        case(&["--field", "password"], false, b"password\n"),
+        case(&["--field", "password"], true, b"password"),
+        case(&["--field", "username"], false, b"username\n"),
+        case(&["--field", "recovery-email"], false, b"x@y.com\n"),
+        case(&["--field", "recovery-email"], true, b"x@y.com"),
+        case(&["--field", "phone"], false, b""),
+        case(&["--field", "phone"], true, b""),
+        case(&["--fields", "username,password"], false, b"username\tpassword\n"),
+        case(&["--fields", "password,recovery-email", "--separator", "\\n"], true, b"password\nx@y.com"),
+        case(&["--fields", "username,phone"], false, b"")
+    )]
+    fn test_run_cli_get_field(field_args: &[&str], no_newline: bool, expected: &[u8]) {
+        let mut password_store = PasswordStore::from_reader(
+            &mut (b"" as &[u8]),
+            "test_master_password".to_string(),
+            StoreOptions::default(),
+        )
+        .unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "service".to_string(),
+            Some("username".to_string()),
+            AddOptions {
+                password: Some("password".to_string()),
+                metadata: HashMap::from([("recovery-email".to_string(), "x@y.com".to_string())]),
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let mut encrypted_store = Vec::new();
+        password_store.write_to(&mut encrypted_store).unwrap();
+
+        let mut args = vec![
+            "lockbox",
+            "get",
+            "--file-name",
+            "-",
+            "--service",
+            "service",
+            "--username",
+            "username",
+            "--master",
+            "test_master_password",
+        ];
+        args.extend(field_args);
+        if no_newline {
+            args.push("--no-newline");
+        }
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(encrypted_store),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            Args::parse_from(args),
+        );
+        assert_eq!(output, expected);
+    }
+
+    #[rstest(
+        command,
+        quiet,
+        expected_output,
+        case(&["show"], false, "Password not found"),
+        case(&["remove"], false, "Password not found"),
+        case(&["edit", "--disable"], false, "Password not found"),
+        case(&["edit", "--disable", "--index", "7"], false, "No entry at index 7"),
+        case(&["show"], true, ""),
+        case(&["remove"], true, "")
+    )]
+    fn test_run_cli_not_found(command: &[&str], quiet: bool, expected_output: &str) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "test_master_password".to_string()).unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "service".to_string(),
+            None,
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+
+        let temp_file_str = temp_file.to_string_lossy().to_string();
+        let mut args = vec!["lockbox"];
+        args.extend(command);
+        args.extend([
+            "--file-name",
+            &temp_file_str,
+            "--master",
+            "test_master_password",
+        ]);
+        if !command.contains(&"--index") {
+            args.extend(["--service", "missing"]);
+        }
+        if quiet {
+            args.push("--quiet");
+        }
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let exit_code = run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut errors,
+            &MockPromptPassword::new(),
+            Args::parse_from(args),
+        );
+        assert_eq!(exit_code, EXIT_NOT_FOUND);
+        let output_str = String::from_utf8(output).unwrap();
+        let errors_str = String::from_utf8(errors).unwrap();
+        // `show` reports on the error writer
+        let (messages, other) = if command[0] == "show" {
+            (errors_str, output_str)
+        } else {
+            (output_str, errors_str)
+        };
+        assert!(messages.contains(expected_output));
+        assert!(!other.contains("Password not found"));
+        if quiet {
+            assert!(!messages.contains("Password not found"));
+        }
+    }
+
+    #[rstest]
+    #[case(&["show"])]
+    #[case(&["remove"])]
+    #[case(&["edit", "--disable"])]
+    fn test_run_cli_wrong_master_exit_code(#[case] command: &[&str]) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        PasswordStore::create(temp_file.clone(), "test_master_password".to_string()).unwrap();
+        let temp_file_str = temp_file.to_string_lossy().to_string();
+        let mut args = vec!["lockbox"];
+        args.extend(command);
+        args.extend([
+            "--file-name",
+            &temp_file_str,
+            "--master",
+            "wrong_master_password",
+            "--service",
+            "service",
+        ]);
+        let exit_code = run_cli(
+            &mut Cursor::new(b""),
+            &mut Vec::new(),
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            Args::parse_from(args),
+        );
+        assert_eq!(exit_code, EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_report_entry_error() {
+        let mut output = Vec::new();
+        let exit_code = report_entry_error(&mut output, anyhow::anyhow!("Failed to save"), true);
+        assert_eq!(exit_code, EXIT_FAILURE);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Error: Failed to save"));
+
+        let mut output = Vec::new();
+        let exit_code = report_entry_error(&mut output, NotFound("gone".to_string()).into(), false);
+        assert_eq!(exit_code, EXIT_NOT_FOUND);
+        assert!(String::from_utf8(output).unwrap().contains("gone"));
+    }
+
+    #[test]
+    fn test_run_cli_export_recovery_and_recover() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_file_str = temp_file.path().to_str().unwrap().to_string();
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "add",
+            "--file-name",
+            &temp_file_str,
+            "--service",
+            "service",
+            "--password",
+            "s3cret",
+            "--master",
+            "forgotten_master",
+            "--create",
+            "--allow-weak-master",
+        ]);
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(|_| Ok("forgotten_master".to_string()));
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut Vec::new(),
+            &mut std::io::sink(),
+            &mock_prompt_password,
+            args,
+        );
+
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "export-recovery",
+            "--file-name",
+            &temp_file_str,
+            "--master",
+            "forgotten_master",
+            "--shares",
+            "3",
+            "--threshold",
+            "2",
+        ]);
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        let shares: Vec<&str> = output_str
+            .split(|c: char| !(c.is_ascii_hexdigit() || c == '-'))
+            .filter(|token| token.len() > 64)
+            .collect();
+        assert_eq!(shares.len(), 3);
+
+        let recover = |shares: &[&str]| {
+            let mut args = vec!["lockbox", "recover", "--file-name", &temp_file_str];
+            for share in shares {
+                args.extend(["--share", share]);
+            }
+            args.extend(["--new-master", "new_master", "--allow-weak-master"]);
+            let mut output = Vec::new();
+            run_cli(
+                &mut Cursor::new(b""),
+                &mut output,
+                &mut std::io::sink(),
+                &MockPromptPassword::new(),
+                Args::parse_from(args),
+            );
+            String::from_utf8(output).unwrap()
+        };
+        assert!(recover(&shares[..1]).contains("do not match this password store"));
+
+        let mut args = vec!["lockbox", "recover", "--file-name", &temp_file_str];
+        for share in &shares[1..] {
+            args.extend(["--share", share]);
+        }
+        args.extend(["--new-master", "new_master"]);
+        let mut error_output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut Vec::new(),
+            &mut error_output,
+            &MockPromptPassword::new(),
+            Args::parse_from(args),
+        );
+        assert!(String::from_utf8(error_output)
+            .unwrap()
+            .contains("Error: The master password is weak"));
+
+        assert!(recover(&shares[1..]).contains("Password store recovered"));
+
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "show",
+            "--file-name",
+            &temp_file_str,
+            "--service",
+            "service",
+            "--master",
+            "new_master",
+        ]);
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+        assert!(String::from_utf8(output).unwrap().contains("s3cret"));
+    }
+
+    #[test]
+    fn test_check_secret_options() {
+        let mut output = Vec::new();
+        check_secret_options(&mut output, &[], true, false).unwrap();
+        assert!(output.is_empty());
+
+        check_secret_options(&mut output, &["--master"], false, false).unwrap();
+        let warning = String::from_utf8(output).unwrap();
+        assert!(warning.contains("Warning: --master leaks the password"));
+        assert!(warning.contains("--no-master-warning"));
+
+        let mut output = Vec::new();
+        check_secret_options(&mut output, &["--master"], false, true).unwrap();
+        assert!(output.is_empty());
+
+        let err = check_secret_options(&mut output, &["--master", "--new-master"], true, false)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("--master and --new-master can't be used with --strict-security"));
+    }
+
+    #[test]
+    fn test_run_cli_strict_security() {
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            Args::parse_from(["lockbox", "list", "--master", "master", "--strict-security"]),
+        );
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Error: --master can't be used with --strict-security"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_cli_master_fd() {
+        use std::io::Write as _;
+        use std::os::fd::AsRawFd;
+
+        let store_file = NamedTempFile::new().unwrap();
+        let mut password_store = PasswordStore::create(
+            store_file.path().to_path_buf(),
+            "test_master_password".to_string(),
+        )
+        .unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "service".to_string(),
+                None,
+                "password".to_string(),
+                HashMap::new(),
+                false,
+            )
+            .unwrap()
+            .save()
+            .unwrap();
+        let mut master_file = NamedTempFile::new().unwrap();
+        writeln!(master_file, "test_master_password").unwrap();
+        let fd = master_file.as_file().as_raw_fd().to_string();
+        let file_name = store_file.path().to_str().unwrap();
+
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            Args::parse_from([
+                "lockbox",
+                "list",
+                "--file-name",
+                file_name,
+                "--master-fd",
+                &fd,
+            ]),
+        );
+        assert!(String::from_utf8(output).unwrap().contains("service"));
+
+        // --master takes precedence
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            Args::parse_from([
+                "lockbox",
+                "list",
+                "--file-name",
+                file_name,
+                "--master-fd",
+                &fd,
+                "--master",
+                "wrong",
+            ]),
+        );
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Master password incorrect"));
+
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            Args::parse_from(["lockbox", "man", "--master-fd", &fd]),
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap().trim_end(),
+            colorize(
+                "Error: --master-fd can't be used with this command",
+                MessageType::Error
+            )
+        );
+    }
+
+    #[test]
+    fn test_run_cli_fingerprint() {
+        let mut password_store = PasswordStore::from_reader(
+            &mut (b"" as &[u8]),
+            "test_master_password".to_string(),
+            StoreOptions::default(),
+        )
+        .unwrap();
+        password_store.load().unwrap().save().unwrap();
+        let mut encrypted_store = Vec::new();
+        password_store.write_to(&mut encrypted_store).unwrap();
+
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "fingerprint",
+            "--file-name",
+            "-",
+            "--master",
+            "test_master_password",
+        ]);
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(encrypted_store),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Key fingerprint: "));
+        assert!(output_str.contains(&password_store.key_fingerprint().unwrap()));
+        assert!(!output_str.contains("test_master_password"));
+    }
+
+    #[test]
+    fn test_run_cli_stdio_store_wrong_password() {
+        let args = Args::parse_from(vec!["lockbox", "list", "--file-name", "-", "--create"]);
+        let mut input = Cursor::new(b"");
+        let mut output = Vec::new();
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(|_| Ok("test_master_password".to_string()));
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(|_| Ok("wrong_test_master_password".to_string()));
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut std::io::sink(),
+            &mock_prompt_password,
+            args,
+        );
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Error: The inserted master passwords don't match"));
     }
 
+    #[rstest(file_name, case(None), case(Some("-")))]
+    fn test_run_cli_missing_store_without_create(file_name: Option<&str>) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let file_name = file_name.map_or(file_path.to_str().unwrap().to_string(), String::from);
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "list",
+            "--file-name",
+            &file_name,
+            "--master",
+            "test_master_password",
+        ]);
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("pass --create to initialize"));
+        assert!(!file_path.exists());
+    }
+
+    #[test]
+    fn test_run_cli_empty_store_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        std::fs::write(&file_path, b"").unwrap();
+        std::fs::write(temp_dir.path().join("store.v3.bak"), b"backup").unwrap();
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "list",
+            "--file-name",
+            file_path.to_str().unwrap(),
+            "--master",
+            "test_master_password",
+        ]);
+        let mut output = Vec::new();
+        run_cli(
+            &mut Cursor::new(b""),
+            &mut output,
+            &mut std::io::sink(),
+            &MockPromptPassword::new(),
+            args,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("is empty or truncated, restore it from the backup"));
+        assert!(output_str.contains("store.v3.bak"));
+        assert!(!output_str.contains("--create"));
+    }
+
     #[test]
     fn test_run_cli_repl() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_file_name = temp_file.path().to_str().unwrap();
-        let args = Args::parse_from(vec!["lockbox", "repl", "--file-name", temp_file_name]);
-        let mut input = b"exit\n" as &[u8];
+        let args = Args::parse_from(vec![
+            "lockbox",
+            "repl",
+            "--file-name",
+            temp_file_name,
+            "--create",
+        ]);
+        let mut input = b"yes\nexit\n" as &[u8];
         let mut output = Vec::new();
         let mut mock_prompt_password = MockPromptPassword::new();
         mock_prompt_password
             .expect_prompt_password()
             .returning(|_| Ok("password\n".to_string()));
-        run_cli(&mut input, &mut output, &mock_prompt_password, args);
+        run_cli(
+            &mut input,
+            &mut output,
+            &mut std::io::sink(),
+            &mock_prompt_password,
+            args,
+        );
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Welcome to L🦀CKBOX!"));
         let operations = [
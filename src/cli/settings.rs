@@ -0,0 +1,65 @@
+//! The global options that change how commands behave, e.g. which clipboard passwords are copied
+//! to. `run_cli` builds them once from the arguments and passes them to the commands that need
+//! them.
+
+use super::{
+    args::{Args, ClipboardBackend},
+    commands::RevealLimit,
+    io::DEFAULT_PROMPT_STYLE,
+};
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct Settings {
+    pub clipboard_backend: ClipboardBackend,
+    /// Whether to warn about running clipboard managers. It is cleared once they were looked for,
+    /// so that the warning is printed at most once.
+    pub clipboard_manager_warning: Cell<bool>,
+    /// The text of password prompts, see `DEFAULT_PROMPT_STYLE`.
+    pub prompt_style: String,
+    /// The file that a record of every revealed password is appended to.
+    pub audit_log: Option<PathBuf>,
+    /// Counts the reveals of the whole run, e.g. a REPL session or a `serve` process.
+    pub reveal_limit: Option<RefCell<RevealLimit>>,
+    /// Whether a weak new master password is accepted without asking.
+    pub allow_weak_master: bool,
+    /// Records what is copied in place of the clipboard, so that tests don't touch the real one.
+    #[cfg(test)]
+    pub fake_clipboard: Option<RefCell<Vec<String>>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            clipboard_backend: ClipboardBackend::Auto,
+            clipboard_manager_warning: Cell::new(true),
+            prompt_style: DEFAULT_PROMPT_STYLE.to_string(),
+            audit_log: None,
+            reveal_limit: None,
+            allow_weak_master: false,
+            #[cfg(test)]
+            fake_clipboard: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            clipboard_backend: args.clipboard_backend,
+            clipboard_manager_warning: Cell::new(!args.no_clipboard_manager_warning),
+            prompt_style: args
+                .prompt_style
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PROMPT_STYLE.to_string()),
+            audit_log: args.audit_log.clone(),
+            reveal_limit: args
+                .max_reveals
+                .map(|max| RefCell::new(RevealLimit::new(max, args.reveal_window))),
+            allow_weak_master: args.allow_weak_master,
+            #[cfg(test)]
+            fake_clipboard: None,
+        }
+    }
+}
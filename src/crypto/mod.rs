@@ -1,12 +1,16 @@
 use aes_gcm::{
-    aead::{generic_array::GenericArray, Aead, OsRng},
-    AeadCore, Aes256Gcm, KeyInit,
+    aead::{generic_array::GenericArray, Aead, Payload},
+    Aes256Gcm, KeyInit,
 };
+use clap::ValueEnum;
 use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
     pbkdf2,
     rand::{SecureRandom, SystemRandom},
 };
-use std::num::NonZeroU32;
+use std::{fmt::Display, num::NonZeroU32};
+
+pub mod shamir;
 
 pub fn get_random_salt() -> [u8; 16] {
     let mut salt = [0u8; 16];
@@ -15,11 +19,37 @@ pub fn get_random_salt() -> [u8; 16] {
     salt
 }
 
+pub fn get_random_nonce() -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    let r = SystemRandom::new();
+    r.fill(&mut nonce).unwrap();
+    nonce
+}
+
+pub fn get_random_bytes(length: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; length];
+    let r = SystemRandom::new();
+    r.fill(&mut bytes).unwrap();
+    bytes
+}
+
+/// Overwrites `secret` with zeros and leaves it empty, so that it does not linger in memory once
+/// it is freed.
+pub fn scrub(secret: &mut String) {
+    let mut bytes = std::mem::take(secret).into_bytes();
+    bytes.fill(0);
+    // keep the compiler from skipping the writes to a buffer that is about to be freed
+    std::hint::black_box(&bytes);
+}
+
+/// The PBKDF2-HMAC-SHA256 iterations used to derive the store key from the master password.
+pub const KDF_ITERATIONS: u32 = 100_000;
+
 pub fn derive_encryption_key(master_password: &str, salt: &[u8]) -> [u8; 32] {
     let mut enc_key: [u8; 32] = [0u8; 32];
     pbkdf2::derive(
         pbkdf2::PBKDF2_HMAC_SHA256,
-        NonZeroU32::new(100_000).unwrap(),
+        NonZeroU32::new(KDF_ITERATIONS).unwrap(),
         salt,
         master_password.as_bytes(),
         &mut enc_key,
@@ -27,15 +57,165 @@ pub fn derive_encryption_key(master_password: &str, salt: &[u8]) -> [u8; 32] {
     enc_key
 }
 
-pub fn get_cipher(master_password: &str, salt: &[u8]) -> Aes256Gcm {
-    let enc_key = derive_encryption_key(master_password, salt);
-    let cipher = Aes256Gcm::new(GenericArray::from_slice(&enc_key));
-    cipher
+/// The authenticated cipher a store is encrypted with. Both take the same 32 byte key and a 12
+/// byte nonce. ChaCha20-Poly1305 is faster on CPUs without AES instructions.
+#[derive(Debug, Copy, Clone, Default, PartialEq, ValueEnum)]
+pub enum Cipher {
+    #[default]
+    #[value(name = "aes-256-gcm")]
+    Aes256Gcm,
+    #[value(name = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+}
+
+impl Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cipher = self
+            .to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .to_string();
+        write!(f, "{cipher}")
+    }
 }
 
-pub fn encrypt_contents(contents: &str, master_password: &str, salt: &[u8]) -> (Vec<u8>, Vec<u8>) {
-    let cipher = get_cipher(master_password, salt);
-    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-    let encrypted_text = cipher.encrypt(&nonce, contents.as_ref());
-    (encrypted_text.unwrap(), nonce.to_vec())
+impl Cipher {
+    /// The byte that identifies the cipher in the store header.
+    pub fn id(self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 0,
+            Cipher::ChaCha20Poly1305 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|cipher| cipher.id() == id)
+    }
+
+    /// Encrypts `contents` with `nonce`, which must never be reused with the same key, and
+    /// authenticates `aad` along with it without encrypting it.
+    pub fn encrypt(
+        self,
+        enc_key: &[u8; 32],
+        nonce: &[u8; NONCE_LEN],
+        contents: &[u8],
+        aad: &[u8],
+    ) -> Vec<u8> {
+        match self {
+            Cipher::Aes256Gcm => Aes256Gcm::new(GenericArray::from_slice(enc_key))
+                .encrypt(
+                    GenericArray::from_slice(nonce),
+                    Payload { msg: contents, aad },
+                )
+                .unwrap(),
+            Cipher::ChaCha20Poly1305 => {
+                let mut encrypted_text = contents.to_vec();
+                chacha20_poly1305_key(enc_key)
+                    .seal_in_place_append_tag(
+                        Nonce::assume_unique_for_key(*nonce),
+                        Aad::from(aad),
+                        &mut encrypted_text,
+                    )
+                    .unwrap();
+                encrypted_text
+            }
+        }
+    }
+
+    /// Decrypts `ciphertext`, or returns `None` when the key is wrong or the ciphertext or `aad`
+    /// was tampered with.
+    pub fn decrypt(
+        self,
+        enc_key: &[u8; 32],
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Option<Vec<u8>> {
+        if nonce.len() != NONCE_LEN {
+            return None;
+        }
+        match self {
+            Cipher::Aes256Gcm => Aes256Gcm::new(GenericArray::from_slice(enc_key))
+                .decrypt(
+                    GenericArray::from_slice(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad,
+                    },
+                )
+                .ok(),
+            Cipher::ChaCha20Poly1305 => {
+                let mut plain_text = ciphertext.to_vec();
+                let length = chacha20_poly1305_key(enc_key)
+                    .open_in_place(
+                        Nonce::try_assume_unique_for_key(nonce).ok()?,
+                        Aad::from(aad),
+                        &mut plain_text,
+                    )
+                    .ok()?
+                    .len();
+                plain_text.truncate(length);
+                Some(plain_text)
+            }
+        }
+    }
+}
+
+fn chacha20_poly1305_key(enc_key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, enc_key).expect("the key is 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_scrub() {
+        let mut secret = "master password".to_string();
+        scrub(&mut secret);
+        assert!(secret.is_empty());
+    }
+
+    #[rstest(cipher, case(Cipher::Aes256Gcm), case(Cipher::ChaCha20Poly1305))]
+    fn test_cipher_round_trip(cipher: Cipher) {
+        let enc_key = derive_encryption_key("master", &get_random_salt());
+        let nonce = get_random_nonce();
+        let ciphertext = cipher.encrypt(&enc_key, &nonce, b"secret contents", b"header");
+        assert_ne!(ciphertext.as_slice(), b"secret contents");
+        assert_eq!(
+            cipher.decrypt(&enc_key, &nonce, &ciphertext, b"header"),
+            Some(b"secret contents".to_vec())
+        );
+        assert_eq!(Cipher::from_id(cipher.id()), Some(cipher));
+
+        let wrong_key = derive_encryption_key("wrong", &get_random_salt());
+        assert_eq!(
+            cipher.decrypt(&wrong_key, &nonce, &ciphertext, b"header"),
+            None
+        );
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert_eq!(cipher.decrypt(&enc_key, &nonce, &tampered, b"header"), None);
+        assert_eq!(
+            cipher.decrypt(&enc_key, &nonce, &ciphertext, b"headex"),
+            None
+        );
+        assert_eq!(cipher.decrypt(&enc_key, &nonce, &ciphertext, b""), None);
+    }
+
+    #[test]
+    fn test_ciphers_are_not_interchangeable() {
+        let enc_key = derive_encryption_key("master", &get_random_salt());
+        let nonce = get_random_nonce();
+        let ciphertext = Cipher::Aes256Gcm.encrypt(&enc_key, &nonce, b"secret contents", b"");
+        assert_eq!(
+            Cipher::ChaCha20Poly1305.decrypt(&enc_key, &nonce, &ciphertext, b""),
+            None
+        );
+        assert_eq!(Cipher::from_id(2), None);
+    }
 }
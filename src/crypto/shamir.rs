@@ -0,0 +1,157 @@
+//! Shamir's Secret Sharing over GF(256), used to split the store encryption key into
+//! recovery shares. A share is written as `<index>-<hex bytes>`.
+
+use super::get_random_bytes;
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_inv(a: u8) -> u8 {
+    // a^254 is the multiplicative inverse of a in GF(256)
+    let mut result = 1u8;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+fn eval_polynomial(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0u8, |acc, &coefficient| gf_mul(acc, x) ^ coefficient)
+}
+
+/// Splits `secret` into `shares` shares, any `threshold` of which can rebuild it.
+pub fn split(secret: &[u8], shares: u8, threshold: u8) -> anyhow::Result<Vec<String>> {
+    if threshold == 0 || threshold > shares {
+        anyhow::bail!("The threshold must be between 1 and the number of shares ({shares})");
+    }
+    let mut points: Vec<Vec<u8>> = vec![Vec::with_capacity(secret.len()); shares as usize];
+    for &byte in secret {
+        let mut coefficients = vec![byte];
+        coefficients.extend(get_random_bytes(threshold as usize - 1));
+        for (index, point) in points.iter_mut().enumerate() {
+            point.push(eval_polynomial(&coefficients, index as u8 + 1));
+        }
+    }
+    Ok(points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| {
+            let hex: String = point.iter().map(|byte| format!("{byte:02x}")).collect();
+            format!("{}-{}", index + 1, hex)
+        })
+        .collect())
+}
+
+fn parse_share(share: &str) -> anyhow::Result<(u8, Vec<u8>)> {
+    let invalid = || anyhow::anyhow!("Invalid recovery share `{share}`");
+    let (index, hex) = share.trim().split_once('-').ok_or_else(invalid)?;
+    let index: u8 = index.parse().map_err(|_| invalid())?;
+    if index == 0 || hex.is_empty() || hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+    Ok((index, bytes))
+}
+
+/// Rebuilds the secret from shares produced by [`split`]. Passing fewer shares than the
+/// threshold yields a wrong secret rather than an error.
+pub fn combine(shares: &[String]) -> anyhow::Result<Vec<u8>> {
+    let points = shares
+        .iter()
+        .map(|share| parse_share(share))
+        .collect::<anyhow::Result<Vec<(u8, Vec<u8>)>>>()?;
+    let Some((_, first)) = points.first() else {
+        anyhow::bail!("At least one recovery share is required");
+    };
+    let secret_length = first.len();
+    for (i, (index, bytes)) in points.iter().enumerate() {
+        if bytes.len() != secret_length {
+            anyhow::bail!("The recovery shares have different lengths");
+        }
+        if points[..i].iter().any(|(other, _)| other == index) {
+            anyhow::bail!("Recovery share {index} was given more than once");
+        }
+    }
+    let mut secret = vec![0u8; secret_length];
+    for (i, (x_i, y_i)) in points.iter().enumerate() {
+        // Lagrange basis polynomial for x_i, evaluated at 0
+        let mut basis = 1u8;
+        for (j, (x_j, _)) in points.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(*x_j, gf_inv(x_j ^ x_i)));
+            }
+        }
+        for (byte, y) in secret.iter_mut().zip(y_i) {
+            *byte ^= gf_mul(basis, *y);
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_gf_inv() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[rstest(shares, threshold, case(1, 1), case(3, 2), case(5, 3), case(5, 5))]
+    fn test_split_combine(shares: u8, threshold: u8) {
+        let secret = get_random_bytes(32);
+        let split_shares = split(&secret, shares, threshold).unwrap();
+        assert_eq!(split_shares.len(), shares as usize);
+        let subset: Vec<String> = split_shares
+            .iter()
+            .rev()
+            .take(threshold as usize)
+            .cloned()
+            .collect();
+        assert_eq!(combine(&subset).unwrap(), secret);
+        if threshold > 1 {
+            assert_ne!(combine(&subset[1..]).unwrap(), secret);
+        }
+    }
+
+    #[rstest(
+        shares,
+        case(vec![]),
+        case(vec!["1-zz".to_string()]),
+        case(vec!["0-ab".to_string()]),
+        case(vec!["ab".to_string()]),
+        case(vec!["1-ab".to_string(), "2-abcd".to_string()]),
+        case(vec!["1-ab".to_string(), "1-cd".to_string()])
+    )]
+    fn test_combine_invalid(shares: Vec<String>) {
+        assert!(combine(&shares).is_err());
+    }
+
+    #[test]
+    fn test_split_invalid_threshold() {
+        assert!(split(b"secret", 3, 0).is_err());
+        assert!(split(b"secret", 3, 4).is_err());
+    }
+}
@@ -2,4 +2,6 @@ pub mod cli;
 pub mod crypto;
 pub mod pass;
 pub mod repl;
+#[cfg(feature = "serve")]
+pub mod serve;
 pub mod store;
@@ -1,12 +1,14 @@
 use clap::Parser;
 use lock_box::{
     cli::{
-        args::{Args, DEFAULT_PASSWORD_FILENAME},
+        args::{Args, ListFormat},
         io::RpasswordPromptPassword,
         run_cli,
+        settings::Settings,
     },
     repl::repl,
 };
+use std::io::Write;
 
 fn main() {
     let mut input = std::io::stdin().lock();
@@ -17,10 +19,24 @@ fn main() {
             &mut input,
             &mut output,
             prompt_password,
-            DEFAULT_PASSWORD_FILENAME.to_string(),
+            None,
+            None,
+            ListFormat::Text,
+            &Settings::default(),
         )
     } else {
         let args = Args::parse();
-        run_cli(&mut input, &mut output, prompt_password, args);
+        let exit_code = run_cli(
+            &mut input,
+            &mut output,
+            &mut std::io::stderr(),
+            prompt_password,
+            args,
+        );
+        if exit_code != 0 {
+            // exiting skips destructors, which would flush a partial line
+            let _ = output.flush();
+            std::process::exit(exit_code);
+        }
     }
 }
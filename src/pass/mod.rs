@@ -1,36 +1,607 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::cli::commands::copy_to_clipboard;
-use crate::cli::io::{print, print_key_value_with_color, MessageType};
+use crate::cli::io::{bold, print, print_key_value_with_color, MessageType};
+use crate::crypto::scrub;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct PasswordEntry {
     pub service: String,
     pub username: Option<String>,
     password: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub favorite: bool,
+    /// When the entry expires, in seconds since the Unix epoch. Expired entries are skipped by
+    /// lookups and listings until `gc` removes them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// When the entry was added or last changed, e.g. its password replaced, its service renamed
+    /// or it was disabled, in seconds since the Unix epoch. Entries added before lockbox recorded
+    /// it have none and their age is unknown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+    /// Whether the entry is kept for reference only, e.g. for a closed account. Disabled entries
+    /// are hidden from listings unless asked for, but can still be shown.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// How long `show` leaves the password on the clipboard before clearing it, in seconds.
+    /// Without it the password stays on the clipboard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard_ttl: Option<u64>,
+    /// The folder the entry is filed under, a path like `work/aws/prod`. Entries added without
+    /// one, including those from before folders existed, are in the root folder, the empty path.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub folder: String,
+}
+
+/// The current time in seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The lowercase host of a domain or URL, e.g. `www.github.com` for
+/// `https://www.github.com:443/login`. An IPv6 host keeps its brackets, e.g. `[::1]` for
+/// `http://[::1]:8080/`, and is empty without the closing one.
+pub fn host(domain: &str) -> String {
+    let domain = domain
+        .split_once("://")
+        .map_or(domain, |(_, rest)| rest)
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    let domain = domain.rsplit_once('@').map_or(domain, |(_, host)| host);
+    let domain = if domain.starts_with('[') {
+        domain.find(']').map_or("", |end| &domain[..=end])
+    } else {
+        domain.split(':').next().unwrap_or_default()
+    };
+    domain.trim_end_matches('.').to_lowercase()
+}
+
+/// `url` with `https://` added when it has no scheme, e.g. `https://github.com/login` for
+/// `github.com/login`. Fails when the URL has no host, see `host`.
+pub fn normalized_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    let url = if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("https://{url}")
+    };
+    if host(&url).is_empty() {
+        return Err(format!("invalid url `{url}`, it has no host"));
+    }
+    Ok(url)
+}
+
+/// The host of a URL with a leading `www.` removed, so that `https://www.github.com/login` and
+/// `github.com` compare equal.
+pub fn normalized_host(url: &str) -> String {
+    let host = host(url);
+    match host.strip_prefix("www.") {
+        Some(host) => host.to_string(),
+        None => host,
+    }
+}
+
+/// `folder` with surrounding whitespace and empty segments removed, so that ` /work//aws/ ` and
+/// `work/aws` are the same folder.
+pub fn normalized_folder(folder: &str) -> String {
+    let segments: Vec<&str> = folder
+        .split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    segments.join("/")
+}
+
+/// A blank username is the same as no username.
+fn non_blank(username: Option<&str>) -> Option<&str> {
+    username.filter(|username| !username.trim().is_empty())
 }
 
 impl PasswordEntry {
     pub fn new(service: String, username: Option<String>, password: String) -> PasswordEntry {
         PasswordEntry {
             service,
-            username,
+            username: username.filter(|username| !username.trim().is_empty()),
             password,
+            metadata: HashMap::new(),
+            favorite: false,
+            expires_at: None,
+            updated_at: None,
+            disabled: false,
+            clipboard_ttl: None,
+            folder: String::new(),
         }
     }
-    pub fn print_password<W: Write>(&self, writer: &mut W, message_type: Option<MessageType>) {
-        print_key_value_with_color(writer, "Password", &self.password, None, message_type, None);
+
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> PasswordEntry {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_favorite(mut self, favorite: bool) -> PasswordEntry {
+        self.favorite = favorite;
+        self
+    }
+
+    /// Makes the entry expire `ttl` from now.
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> PasswordEntry {
+        self.expires_at = ttl.map(|ttl| unix_now().saturating_add(ttl.as_secs()));
+        self
+    }
+
+    /// Clears the password from the clipboard `clipboard_ttl` after `show` copied it.
+    pub fn with_clipboard_ttl(mut self, clipboard_ttl: Option<Duration>) -> PasswordEntry {
+        self.clipboard_ttl = clipboard_ttl.map(|ttl| ttl.as_secs());
+        self
+    }
+
+    /// Files the entry under `folder`, see `normalized_folder`.
+    pub fn with_folder(mut self, folder: &str) -> PasswordEntry {
+        self.folder = normalized_folder(folder);
+        self
+    }
+
+    /// Whether the entry is in `folder` or one of its subfolders. Every entry is in the root
+    /// folder.
+    pub fn is_in_folder(&self, folder: &str) -> bool {
+        let folder = normalized_folder(folder);
+        folder.is_empty()
+            || self
+                .folder
+                .strip_prefix(&folder)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// The normalized host of the `url` metadata of the entry, see `normalized_host`.
+    pub fn url_host(&self) -> Option<String> {
+        self.metadata
+            .get("url")
+            .map(|url| normalized_host(url))
+            .filter(|host| !host.is_empty())
+    }
+
+    /// Records that the entry was added or changed at `updated_at`.
+    pub fn with_updated_at(mut self, updated_at: u64) -> PasswordEntry {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    /// Records that the entry was changed just now.
+    fn touch(&mut self) {
+        self.updated_at = Some(unix_now());
+    }
+
+    /// Whether the entry was last updated more than `age` ago. An entry of unknown age is never
+    /// older than anything.
+    pub fn is_older_than(&self, age: Duration) -> bool {
+        self.updated_at
+            .is_some_and(|updated_at| updated_at.saturating_add(age.as_secs()) < unix_now())
+    }
+
+    /// Whether both entries hold the same data, no matter when they were added.
+    fn same_contents(&self, other: &PasswordEntry) -> bool {
+        PasswordEntry {
+            updated_at: None,
+            ..self.clone()
+        } == PasswordEntry {
+            updated_at: None,
+            ..other.clone()
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= unix_now())
     }
 
-    pub fn copy_password(&self) -> anyhow::Result<()> {
-        copy_to_clipboard(self.password.clone())
+    /// Whether the entry is the one for `service` and `username`. Without a username only an
+    /// entry without a username matches, even if the service has entries with usernames.
+    pub fn matches(&self, service: &str, username: Option<&str>) -> bool {
+        self.service == service && non_blank(self.username.as_deref()) == non_blank(username)
+    }
+
+    /// The raw value of `field`: password, username, service or a metadata key.
+    pub fn field(&self, field: &str) -> Option<&str> {
+        match field {
+            "password" => Some(&self.password),
+            "username" => self.username.as_deref(),
+            "service" => Some(&self.service),
+            key => self.metadata.get(key).map(String::as_str),
+        }
+    }
+
+    /// Overwrites the password and the metadata values, see `crypto::scrub`.
+    pub fn scrub(&mut self) {
+        scrub(&mut self.password);
+        self.metadata.values_mut().for_each(scrub);
+    }
+
+    /// The entry prepared for output. The password is only included when `show_password` is set.
+    pub fn view(&self, show_password: bool) -> EntryView {
+        EntryView {
+            index: None,
+            service: self.service.clone(),
+            username: self.username.clone(),
+            metadata: self.metadata.clone(),
+            favorite: self.favorite,
+            disabled: self.disabled,
+            clipboard_ttl: self.clipboard_ttl.map(Duration::from_secs),
+            folder: self.folder.clone(),
+            password: show_password.then(|| self.password.clone()),
+        }
+    }
+}
+
+/// A password entry prepared for output and detached from the store, as returned by the list and
+/// show commands. The password is only set when it is revealed, and the index only when the
+/// entry is numbered as by `list --show-index`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryView {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    pub service: String,
+    pub username: Option<String>,
+    pub metadata: HashMap<String, String>,
+    pub favorite: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    #[serde(skip)]
+    pub clipboard_ttl: Option<Duration>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub folder: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+impl EntryView {
+    pub fn with_index(mut self, index: Option<usize>) -> EntryView {
+        self.index = index;
+        self
+    }
+
+    /// The entry as a JSON object for machine readable output.
+    pub fn to_json(&self) -> Value {
+        json!(self)
+    }
+
+    /// Prints the entry on one line, as listed by `list`. A hidden password is printed as `***`.
+    pub fn print<W: Write>(&self, writer: &mut W, message_type: Option<MessageType>) {
+        if let Some(index) = self.index {
+            let _ = write!(writer, "[{index}] ");
+        }
+        print_key_value_with_color(
+            writer,
+            "Service",
+            &self.service,
+            None,
+            message_type,
+            Some(","),
+        );
+        if let Some(username) = &self.username {
+            print_key_value_with_color(writer, "Username", username, None, message_type, Some(","));
+        }
+        let password = self.password.as_deref().unwrap_or("***");
+        let end = self.disabled.then_some(",");
+        print_key_value_with_color(writer, "Password", password, None, message_type, end);
+        if self.disabled {
+            print_key_value_with_color(writer, "Disabled", "yes", None, message_type, None);
+        }
+    }
+
+    /// Prints the password, if revealed, the folder, if any, and the metadata sorted by key, one
+    /// per line, as shown by `show`.
+    pub fn print_details<W: Write>(&self, writer: &mut W, message_type: Option<MessageType>) {
+        if let Some(password) = &self.password {
+            print_key_value_with_color(writer, "Password", password, None, message_type, None);
+        }
+        if !self.folder.is_empty() {
+            print_key_value_with_color(writer, "Folder", &self.folder, None, message_type, None);
+        }
+        let mut metadata: Vec<(&String, &String)> = self.metadata.iter().collect();
+        metadata.sort();
+        for (key, value) in metadata {
+            print_key_value_with_color(writer, key, value, None, message_type, None);
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Passwords(Vec<PasswordEntry>);
 
+/// Which entries to list. Inclusion filters apply first, then any entry whose service or
+/// username contains one of the `exclude` patterns (ignoring case) is removed. Disabled entries
+/// are only listed with `include_disabled`, and with a `folder` only the entries in it or its
+/// subfolders are listed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EntryFilter {
+    pub favorites_only: bool,
+    pub exclude: Vec<String>,
+    pub include_disabled: bool,
+    pub folder: Option<String>,
+}
+
+impl EntryFilter {
+    /// A filter that matches every entry, disabled ones included, for lookups rather than
+    /// listings.
+    pub fn all() -> EntryFilter {
+        EntryFilter {
+            include_disabled: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, entry: &PasswordEntry) -> bool {
+        let included = (!self.favorites_only || entry.favorite)
+            && (self.include_disabled || !entry.disabled)
+            && self
+                .folder
+                .as_ref()
+                .is_none_or(|folder| entry.is_in_folder(folder));
+        included
+            && !self.exclude.iter().any(|pattern| {
+                let pattern = pattern.to_lowercase();
+                entry.service.to_lowercase().contains(&pattern)
+                    || entry
+                        .username
+                        .as_ref()
+                        .is_some_and(|username| username.to_lowercase().contains(&pattern))
+            })
+    }
+}
+
+/// Values longer than this are shortened in a table, see `print_table`.
+const MAX_COLUMN_WIDTH: usize = 32;
+
+/// Prints `entries` as a table with a column per field, each as wide as its longest value:
+///
+/// ```text
+/// Service │ Username │ Password
+/// ────────┼──────────┼─────────
+/// github  │ octocat  │ ***
+/// ```
+///
+/// The index column is only printed when the entries have indices.
+pub fn print_table<W: Write>(writer: &mut W, entries: &[EntryView]) {
+    let show_index = entries.iter().any(|entry| entry.index.is_some());
+    let header: Vec<String> = ["#", "Service", "Username", "Password"]
+        .iter()
+        .skip(usize::from(!show_index))
+        .map(|title| title.to_string())
+        .collect();
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|entry| {
+            let mut row = vec![];
+            if show_index {
+                row.push(
+                    entry
+                        .index
+                        .map(|index| index.to_string())
+                        .unwrap_or_default(),
+                );
+            }
+            row.push(entry.service.clone());
+            row.push(entry.username.clone().unwrap_or_default());
+            row.push(entry.password.clone().unwrap_or_else(|| "***".to_string()));
+            row.iter()
+                .map(|value| truncate(value, MAX_COLUMN_WIDTH))
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let format_row = |row: &[String]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{value:<width$}"))
+            .collect();
+        cells.join(" │ ").trim_end().to_string()
+    };
+    print(writer, &bold(&format_row(&header)), None);
+    let rule: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+    print(writer, &rule.join("─┼─"), None);
+    for row in rows {
+        print(writer, &format_row(&row), None);
+    }
+}
+
+/// The entries and subfolders of a folder, for `print_tree`.
+#[derive(Default)]
+struct FolderTree<'a> {
+    folders: BTreeMap<&'a str, FolderTree<'a>>,
+    entries: Vec<&'a EntryView>,
+}
+
+/// Prints `entries` as a tree of their folders, subfolders before entries and both in order,
+/// with each entry on one line as listed by `list`:
+///
+/// ```text
+/// ├── work/
+/// │   └── aws/
+/// │       └── Service: console, Username: admin, Password: ***
+/// └── Service: github, Username: octocat, Password: ***
+/// ```
+pub fn print_tree<W: Write>(writer: &mut W, entries: &[EntryView]) {
+    let mut root = FolderTree::default();
+    for entry in entries {
+        let folder = entry
+            .folder
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .fold(&mut root, |tree, segment| {
+                tree.folders.entry(segment).or_default()
+            });
+        folder.entries.push(entry);
+    }
+    print_folder(writer, &root, "");
+}
+
+fn print_folder<W: Write>(writer: &mut W, tree: &FolderTree, indent: &str) {
+    let count = tree.folders.len() + tree.entries.len();
+    let branch = |position: usize| {
+        if position + 1 == count {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        }
+    };
+    for (position, (name, folder)) in tree.folders.iter().enumerate() {
+        let (branch, continuation) = branch(position);
+        print(
+            writer,
+            &format!("{indent}{branch}{}", bold(&format!("{name}/"))),
+            None,
+        );
+        print_folder(writer, folder, &format!("{indent}{continuation}"));
+    }
+    for (position, entry) in tree.entries.iter().enumerate() {
+        let (branch, _) = branch(tree.folders.len() + position);
+        let _ = write!(writer, "{indent}{branch}");
+        entry.print(writer, Some(MessageType::Info));
+    }
+}
+
+/// Shortens `value` to `width` characters, ending with an ellipsis when it was cut.
+fn truncate(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        value.to_string()
+    } else {
+        let kept: String = value.chars().take(width - 1).collect();
+        format!("{kept}…")
+    }
+}
+
+/// Prints how many of `total` items were left out of a listing cut off at `limit`, if any.
+pub fn print_remaining<W: Write>(writer: &mut W, total: usize, limit: Option<usize>) {
+    if let Some(remaining) = limit
+        .map(|limit| total.saturating_sub(limit))
+        .filter(|remaining| *remaining > 0)
+    {
+        print(
+            writer,
+            &format!("... and {remaining} more"),
+            Some(MessageType::Info),
+        );
+    }
+}
+
+/// Scores how well `pattern` fuzzy-matches `candidate`, ignoring case. Every character of the
+/// pattern has to appear in the candidate in order. Each matched character scores 1, plus 2 when it
+/// directly follows the previous match and 3 when it starts a word. Returns `None` when the pattern
+/// is not a subsequence of the candidate.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<usize> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for pattern_char in pattern.to_lowercase().chars() {
+        let index = position
+            + candidate[position..]
+                .iter()
+                .position(|&c| c == pattern_char)?;
+        score += 1;
+        if previous_match.is_some_and(|previous| previous + 1 == index) {
+            score += 2;
+        }
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous_match = Some(index);
+        position = index + 1;
+    }
+    Some(score)
+}
+
+const NATO_ALPHABET: [&str; 26] = [
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliett",
+    "kilo", "lima", "mike", "november", "oscar", "papa", "quebec", "romeo", "sierra", "tango",
+    "uniform", "victor", "whiskey", "x-ray", "yankee", "zulu",
+];
+
+fn symbol_name(c: char) -> Option<&'static str> {
+    Some(match c {
+        ' ' => "space",
+        '!' => "exclamation",
+        '"' => "double-quote",
+        '#' => "hash",
+        '$' => "dollar",
+        '%' => "percent",
+        '&' => "ampersand",
+        '\'' => "apostrophe",
+        '(' => "open-paren",
+        ')' => "close-paren",
+        '*' => "asterisk",
+        '+' => "plus",
+        ',' => "comma",
+        '-' => "dash",
+        '.' => "period",
+        '/' => "slash",
+        ':' => "colon",
+        ';' => "semicolon",
+        '<' => "less-than",
+        '=' => "equals",
+        '>' => "greater-than",
+        '?' => "question",
+        '@' => "at",
+        '[' => "open-bracket",
+        '\\' => "backslash",
+        ']' => "close-bracket",
+        '^' => "caret",
+        '_' => "underscore",
+        '`' => "backtick",
+        '{' => "open-brace",
+        '|' => "pipe",
+        '}' => "close-brace",
+        '~' => "tilde",
+        _ => return None,
+    })
+}
+
+/// Spells `password` out for reading it aloud, one token per character joined by spaces. Letters
+/// use the NATO alphabet, capitalized for uppercase letters, e.g. `Alpha` for `A` and `alpha` for
+/// `a`, digits are kept and symbols are named. Other characters are kept as they are.
+pub fn phonetic_spelling(password: &str) -> String {
+    password
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphabetic() {
+                let word = NATO_ALPHABET[(c.to_ascii_lowercase() as u8 - b'a') as usize];
+                if c.is_ascii_uppercase() {
+                    word[..1].to_uppercase() + &word[1..]
+                } else {
+                    word.to_string()
+                }
+            } else {
+                symbol_name(c).map_or_else(|| c.to_string(), str::to_string)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DedupReport {
+    pub pruned: usize,
+    pub near_duplicates: Vec<(String, Option<String>)>,
+}
+
 impl Default for Passwords {
     fn default() -> Self {
         Self::new()
@@ -52,17 +623,53 @@ impl Passwords {
         self.0.push(new_password);
     }
 
+    /// The entries that have not expired, in storage order.
+    fn live(&self) -> impl Iterator<Item = &PasswordEntry> {
+        self.0.iter().filter(|pwd| !pwd.is_expired())
+    }
+
     pub fn find(&self, service: String, username: Option<String>) -> Option<&PasswordEntry> {
-        self.0
-            .iter()
-            .find(|pwd| pwd.service == service && pwd.username == username)
+        self.live()
+            .find(|pwd| pwd.matches(&service, username.as_deref()))
+    }
+
+    /// Like `find`, but returns the most recently added of the matching entries.
+    pub fn find_latest(&self, service: &str, username: Option<&str>) -> Option<&PasswordEntry> {
+        self.live()
+            .filter(|pwd| pwd.matches(service, username))
+            .last()
+    }
+
+    /// Whether the first entries are exactly those of `other`, in the same order.
+    pub fn starts_with(&self, other: &Passwords) -> bool {
+        self.0.starts_with(&other.0)
+    }
+
+    /// Like `find`, but compares service names ignoring case.
+    pub fn find_ignoring_case(
+        &self,
+        service: &str,
+        username: Option<&str>,
+    ) -> Option<&PasswordEntry> {
+        self.live().find(|pwd| {
+            pwd.service.to_lowercase() == service.to_lowercase()
+                && non_blank(pwd.username.as_deref()) == non_blank(username)
+        })
+    }
+
+    /// The usernames of the entries for `service`, to help pick one when a lookup fails.
+    pub fn usernames(&self, service: &str) -> Vec<&str> {
+        self.live()
+            .filter(|pwd| pwd.service == service)
+            .filter_map(|pwd| non_blank(pwd.username.as_deref()))
+            .collect()
     }
 
     pub fn remove(&mut self, service: String, username: Option<String>) -> Option<PasswordEntry> {
         if let Some(index) = self
             .0
             .iter()
-            .position(|pwd| pwd.service == service && pwd.username == username)
+            .position(|pwd| !pwd.is_expired() && pwd.matches(&service, username.as_deref()))
         {
             Some(self.0.remove(index))
         } else {
@@ -70,52 +677,253 @@ impl Passwords {
         }
     }
 
+    /// Removes exact duplicates (same service, username and password), keeping the first one.
+    /// Entries sharing a service and username but with different passwords are only reported.
+    pub fn dedup(&mut self) -> DedupReport {
+        let mut report = DedupReport::default();
+        let mut unique: Vec<PasswordEntry> = Vec::with_capacity(self.0.len());
+        for pwd in self.0.drain(..) {
+            if unique.iter().any(|kept| kept.same_contents(&pwd)) {
+                report.pruned += 1;
+            } else {
+                unique.push(pwd);
+            }
+        }
+        for (index, pwd) in unique.iter().enumerate() {
+            let key = (pwd.service.clone(), pwd.username.clone());
+            let is_near_duplicate = unique[..index]
+                .iter()
+                .any(|other| other.service == pwd.service && other.username == pwd.username);
+            if is_near_duplicate && !report.near_duplicates.contains(&key) {
+                report.near_duplicates.push(key);
+            }
+        }
+        self.0 = unique;
+        report
+    }
+
+    pub fn services(&self) -> BTreeSet<String> {
+        self.live().map(|pwd| pwd.service.clone()).collect()
+    }
+
+    /// The entries last updated more than `age` ago, in listing order. Expired entries are left
+    /// out.
+    pub fn older_than(&self, age: Duration) -> Vec<&PasswordEntry> {
+        self.entries(&EntryFilter::default())
+            .into_iter()
+            .filter(|pwd| pwd.is_older_than(age))
+            .collect()
+    }
+
+    /// Removes the entries that `older_than` returns and returns how many were removed.
+    pub fn remove_older_than(&mut self, age: Duration) -> usize {
+        let before = self.0.len();
+        self.0
+            .retain(|pwd| pwd.is_expired() || !pwd.is_older_than(age));
+        before - self.0.len()
+    }
+
+    /// The usernames with entries for both `from` and `to`, which renaming `from` to `to` would
+    /// duplicate. An entry without a username is listed as `(no username)`.
+    pub fn rename_collisions(&self, from: &str, to: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|pwd| !pwd.is_expired() && pwd.service == from)
+            .filter(|pwd| {
+                self.0
+                    .iter()
+                    .any(|other| !other.is_expired() && other.matches(to, pwd.username.as_deref()))
+            })
+            .map(|pwd| {
+                non_blank(pwd.username.as_deref())
+                    .unwrap_or("(no username)")
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// Renames the service of every entry of `from` to `to`, keeping their usernames and
+    /// passwords, and returns how many were renamed.
+    pub fn rename_service(&mut self, from: &str, to: &str) -> usize {
+        let mut renamed = 0;
+        for pwd in self
+            .0
+            .iter_mut()
+            .filter(|pwd| !pwd.is_expired() && pwd.service == from)
+        {
+            pwd.service = to.to_string();
+            pwd.touch();
+            renamed += 1;
+        }
+        renamed
+    }
+
+    /// Overwrites the secrets of every entry and removes them all, see `PasswordEntry::scrub`.
+    pub fn scrub(&mut self) {
+        self.0.iter_mut().for_each(PasswordEntry::scrub);
+        self.0.clear();
+    }
+
+    /// Removes the expired entries and returns how many were removed.
+    pub fn gc(&mut self) -> usize {
+        let before = self.0.len();
+        self.0.retain(|pwd| !pwd.is_expired());
+        before - self.0.len()
+    }
+
     pub fn parse_passwords(raw_passwords: &str) -> Result<Passwords, anyhow::Error> {
         let passwords: Passwords = serde_json::from_str(raw_passwords)?;
         Ok(passwords)
     }
 
+    /// The positions of the entries in listing order, favorites first. Expired entries are left
+    /// out.
+    fn listing_order(&self) -> Vec<usize> {
+        let mut positions: Vec<usize> = (0..self.0.len())
+            .filter(|position| !self.0[*position].is_expired())
+            .collect();
+        positions.sort_by_key(|position| !self.0[*position].favorite);
+        positions
+    }
+
+    /// The entries in listing order, favorites first.
+    pub fn entries(&self, filter: &EntryFilter) -> Vec<&PasswordEntry> {
+        self.indexed_entries(filter)
+            .into_iter()
+            .map(|(_, pwd)| pwd)
+            .collect()
+    }
+
+    /// The entries in listing order, each with its 1-based index in the unfiltered listing, so
+    /// that an index printed by a filtered listing still refers to the same entry. Indices change
+    /// whenever entries are added, removed or marked as favorites.
+    pub fn indexed_entries(&self, filter: &EntryFilter) -> Vec<(usize, &PasswordEntry)> {
+        self.listing_order()
+            .into_iter()
+            .enumerate()
+            .map(|(index, position)| (index + 1, &self.0[position]))
+            .filter(|(_, pwd)| filter.matches(pwd))
+            .collect()
+    }
+
+    /// Every entry for `service` and `username` with its index, see `indexed_entries`. More than
+    /// one entry matches when duplicates were kept, e.g. ones that `dedup` only reports.
+    pub fn find_all(&self, service: &str, username: Option<&str>) -> Vec<(usize, &PasswordEntry)> {
+        self.indexed_entries(&EntryFilter::all())
+            .into_iter()
+            .filter(|(_, pwd)| pwd.matches(service, username))
+            .collect()
+    }
+
+    /// Every entry whose `url` metadata has the same host as `url`, with its index, see
+    /// `indexed_entries`. The scheme, port, path and a leading `www.` are ignored.
+    pub fn find_all_by_url(&self, url: &str) -> Vec<(usize, &PasswordEntry)> {
+        let host = normalized_host(url);
+        self.indexed_entries(&EntryFilter::all())
+            .into_iter()
+            .filter(|(_, pwd)| pwd.url_host().is_some_and(|url_host| url_host == host))
+            .collect()
+    }
+
+    /// The entry at the 1-based `index` of the unfiltered listing, see `indexed_entries`.
+    pub fn find_by_index(&self, index: usize) -> Option<&PasswordEntry> {
+        let position = *self.listing_order().get(index.checked_sub(1)?)?;
+        self.0.get(position)
+    }
+
+    pub fn remove_by_index(&mut self, index: usize) -> Option<PasswordEntry> {
+        let position = *self.listing_order().get(index.checked_sub(1)?)?;
+        Some(self.0.remove(position))
+    }
+
+    /// Replaces the password of the entry for `service` and `username`, keeping the rest of it, and
+    /// records when. Returns whether there was such an entry.
+    pub fn set_password(
+        &mut self,
+        service: &str,
+        username: Option<&str>,
+        password: String,
+    ) -> bool {
+        match self
+            .0
+            .iter_mut()
+            .find(|pwd| !pwd.is_expired() && pwd.matches(service, username))
+        {
+            Some(pwd) => {
+                scrub(&mut pwd.password);
+                pwd.password = password;
+                pwd.touch();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Disables or enables the entry for `service` and `username`, see `PasswordEntry::disabled`.
+    /// Returns whether there was such an entry.
+    pub fn set_disabled(&mut self, service: &str, username: Option<&str>, disabled: bool) -> bool {
+        match self
+            .0
+            .iter_mut()
+            .find(|pwd| !pwd.is_expired() && pwd.matches(service, username))
+        {
+            Some(pwd) => {
+                pwd.disabled = disabled;
+                pwd.touch();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Like `set_disabled`, for the entry at the 1-based `index` of the unfiltered listing.
+    pub fn set_disabled_by_index(&mut self, index: usize, disabled: bool) -> bool {
+        let position = index
+            .checked_sub(1)
+            .and_then(|index| self.listing_order().get(index).copied());
+        match position.and_then(|position| self.0.get_mut(position)) {
+            Some(pwd) => {
+                pwd.disabled = disabled;
+                pwd.touch();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// What to print when a listing is empty, telling an empty store apart from a filter that
+    /// hides every entry.
+    pub fn empty_message(&self) -> &'static str {
+        if self.live().next().is_none() {
+            "No passwords stored."
+        } else {
+            "No entries match."
+        }
+    }
+
+    /// Prints the entries matching `filter`. With a `limit`, only that many entries are printed,
+    /// followed by the number of entries left out. With `show_index`, each entry starts with its
+    /// index, see `indexed_entries`.
     pub fn print_all<W: Write>(
         &self,
         writer: &mut W,
         show_passwords: bool,
+        filter: &EntryFilter,
+        limit: Option<usize>,
+        show_index: bool,
         message_type: Option<MessageType>,
     ) {
-        if !self.0.is_empty() {
-            for pwd in self.0.iter() {
-                print_key_value_with_color(
-                    writer,
-                    "Service",
-                    &pwd.service,
-                    None,
-                    message_type,
-                    Some(","),
-                );
-                if pwd.username.is_some() {
-                    print_key_value_with_color(
-                        writer,
-                        "Username",
-                        pwd.username.as_ref().unwrap(),
-                        None,
-                        message_type,
-                        Some(","),
-                    );
-                }
-                if show_passwords {
-                    print_key_value_with_color(
-                        writer,
-                        "Password",
-                        &pwd.password,
-                        None,
-                        message_type,
-                        None,
-                    );
-                } else {
-                    print_key_value_with_color(writer, "Password", "***", None, message_type, None);
-                }
+        let passwords = self.indexed_entries(filter);
+        if !passwords.is_empty() {
+            let total = passwords.len();
+            for (index, pwd) in passwords.into_iter().take(limit.unwrap_or(total)) {
+                pwd.view(show_passwords)
+                    .with_index(show_index.then_some(index))
+                    .print(writer, message_type);
             }
+            print_remaining(writer, total, limit);
         } else {
-            print(writer, "No passwords found!", Some(MessageType::Warning));
+            print(writer, self.empty_message(), Some(MessageType::Info));
         }
     }
 }
@@ -131,6 +939,548 @@ mod tests {
         assert_eq!(Passwords::default(), Passwords(vec![]));
     }
 
+    #[test]
+    fn test_parse_passwords_metadata() {
+        let passwords = Passwords::parse_passwords(
+            r#"[{"service":"service1","username":null,"password":"password1"}]"#,
+        )
+        .unwrap();
+        assert!(passwords.0[0].metadata.is_empty());
+
+        let entry = PasswordEntry::new("service2".to_string(), None, "password2".to_string())
+            .with_metadata(HashMap::from([
+                ("phone".to_string(), "555".to_string()),
+                ("email".to_string(), "x@y.com".to_string()),
+            ]));
+        let raw_passwords = serde_json::to_string(&Passwords::from(vec![entry.clone()])).unwrap();
+        let passwords = Passwords::parse_passwords(&raw_passwords).unwrap();
+        assert_eq!(passwords.0[0], entry);
+
+        let mut output = Vec::new();
+        entry.view(false).print_details(&mut output, None);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "email: x@y.com\nphone: 555\n"
+        );
+    }
+
+    #[rstest(
+        field,
+        expected,
+        case("password", Some("password1")),
+        case("username", None),
+        case("service", Some("service1")),
+        case("phone", Some("555")),
+        case("email", None)
+    )]
+    fn test_field(field: &str, expected: Option<&str>) {
+        let entry = PasswordEntry::new("service1".to_string(), None, "password1".to_string())
+            .with_metadata(HashMap::from([("phone".to_string(), "555".to_string())]));
+        assert_eq!(entry.field(field), expected);
+    }
+
+    #[rstest(
+        favorites_only,
+        exclude,
+        expected,
+        case(false, vec![], vec!["github", "archived-mail", "gitlab", "forum"]),
+        case(false, vec!["ARCHIVED"], vec!["github", "gitlab", "forum"]),
+        case(false, vec!["git", "mail"], vec!["forum"]),
+        case(false, vec!["old"], vec!["github", "archived-mail", "gitlab"]),
+        case(true, vec![], vec!["github", "archived-mail"]),
+        case(true, vec!["archived"], vec!["github"])
+    )]
+    fn test_entry_filter(favorites_only: bool, exclude: Vec<&str>, expected: Vec<&str>) {
+        let passwords = Passwords::from(vec![
+            PasswordEntry::new("github".to_string(), None, "password1".to_string())
+                .with_favorite(true),
+            PasswordEntry::new("gitlab".to_string(), None, "password2".to_string()),
+            PasswordEntry::new("archived-mail".to_string(), None, "password3".to_string())
+                .with_favorite(true),
+            PasswordEntry::new(
+                "forum".to_string(),
+                Some("old-account".to_string()),
+                "password4".to_string(),
+            ),
+        ]);
+        let filter = EntryFilter {
+            favorites_only,
+            exclude: exclude.into_iter().map(String::from).collect(),
+            ..Default::default()
+        };
+        let services: Vec<&str> = passwords
+            .entries(&filter)
+            .iter()
+            .map(|pwd| pwd.service.as_str())
+            .collect();
+        assert_eq!(services, expected);
+    }
+
+    #[test]
+    fn test_rename_service() {
+        let mut passwords = Passwords::from(vec![
+            PasswordEntry::new(
+                "twitter".to_string(),
+                Some("alice".to_string()),
+                "password1".to_string(),
+            ),
+            PasswordEntry::new("twitter".to_string(), None, "password2".to_string()),
+            PasswordEntry::new(
+                "x".to_string(),
+                Some("alice".to_string()),
+                "password3".to_string(),
+            ),
+            PasswordEntry::new("x".to_string(), None, "password4".to_string()),
+            PasswordEntry::new("github".to_string(), None, "password5".to_string()),
+        ]);
+        passwords
+            .0
+            .iter_mut()
+            .for_each(|pwd| pwd.updated_at = Some(1));
+        assert_eq!(
+            passwords.rename_collisions("twitter", "x"),
+            vec!["alice", "(no username)"]
+        );
+        assert!(passwords
+            .rename_collisions("twitter", "mastodon")
+            .is_empty());
+
+        assert_eq!(passwords.rename_service("twitter", "mastodon"), 2);
+        assert_eq!(passwords.rename_service("twitter", "mastodon"), 0);
+        let alice = passwords.find("mastodon".to_string(), Some("alice".to_string()));
+        assert_eq!(
+            alice.and_then(|pwd| pwd.field("password")),
+            Some("password1")
+        );
+        let anonymous = passwords.find("mastodon".to_string(), None);
+        assert_eq!(
+            anonymous.and_then(|pwd| pwd.field("password")),
+            Some("password2")
+        );
+        assert!(anonymous.is_some_and(|pwd| pwd.updated_at > Some(1)));
+        let github = passwords.find("github".to_string(), None);
+        assert_eq!(github.and_then(|pwd| pwd.updated_at), Some(1));
+    }
+
+    #[test]
+    fn test_disabled_entries() {
+        let mut passwords = Passwords::from(vec![
+            PasswordEntry::new("github".to_string(), None, "password1".to_string()),
+            PasswordEntry::new("gitlab".to_string(), None, "password2".to_string()),
+        ]);
+        assert!(passwords.set_disabled("github", None, true));
+        assert!(!passwords.set_disabled("forum", None, true));
+        assert!(passwords.0[0].updated_at.is_some());
+        assert!(passwords.0[1].updated_at.is_none());
+        let services = |passwords: &Passwords, filter: &EntryFilter| -> Vec<String> {
+            passwords
+                .entries(filter)
+                .iter()
+                .map(|pwd| pwd.service.clone())
+                .collect()
+        };
+        assert_eq!(
+            services(&passwords, &EntryFilter::default()),
+            vec!["gitlab"]
+        );
+        assert_eq!(
+            services(&passwords, &EntryFilter::all()),
+            vec!["github", "gitlab"]
+        );
+        // disabled entries can still be looked up and keep their index
+        assert!(passwords.find("github".to_string(), None).is_some());
+        assert_eq!(passwords.find_all("github", None).len(), 1);
+        assert_eq!(passwords.indexed_entries(&EntryFilter::default())[0].0, 2);
+
+        let mut output = Vec::new();
+        passwords
+            .find_by_index(1)
+            .unwrap()
+            .view(false)
+            .print(&mut output, None);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Service: github,Password: ***,Disabled: yes\n"
+        );
+
+        assert!(passwords.set_disabled_by_index(1, false));
+        assert!(!passwords.set_disabled_by_index(3, false));
+        assert_eq!(
+            services(&passwords, &EntryFilter::default()),
+            vec!["github", "gitlab"]
+        );
+    }
+
+    #[rstest(
+        password,
+        expected,
+        case("Ab7$", "Alpha bravo 7 dollar"),
+        case("x-Y", "x-ray dash Yankee"),
+        case("a b", "alpha space bravo"),
+        case("é", "é"),
+        case("", "")
+    )]
+    fn test_phonetic(password: &str, expected: &str) {
+        assert_eq!(phonetic_spelling(password), expected);
+    }
+
+    #[rstest(
+        pattern,
+        candidate,
+        expected,
+        case("gh", "github", Some(5)),
+        case("GH", "GitHub", Some(5)),
+        case("gh", "my-github", Some(5)),
+        case("git", "github", Some(10)),
+        case("hg", "github", None),
+        case("xyz", "github", None),
+        case("", "github", Some(0))
+    )]
+    fn test_fuzzy_score(pattern: &str, candidate: &str, expected: Option<usize>) {
+        assert_eq!(fuzzy_score(pattern, candidate), expected);
+    }
+
+    #[rstest(
+        url,
+        expected,
+        case("github.com", "github.com"),
+        case("https://www.GitHub.com/login?next=/", "github.com"),
+        case("http://user@gist.github.com:8080/", "gist.github.com"),
+        case("www.example.org.", "example.org"),
+        case("http://[::1]:8080/login", "[::1]"),
+        case("https://user@[2001:DB8::1]/", "[2001:db8::1]"),
+        case("http://[::1:8080/", ""),
+        case("", "")
+    )]
+    fn test_normalized_host(url: &str, expected: &str) {
+        assert_eq!(normalized_host(url), expected);
+    }
+
+    #[rstest(
+        url,
+        expected,
+        case("github.com/login", Ok("https://github.com/login")),
+        case(" http://[::1]:8080 ", Ok("http://[::1]:8080")),
+        case("", Err(())),
+        case("https://", Err(())),
+        case("https:///login", Err(())),
+        case("https://user@:443/", Err(())),
+        case("[::1", Err(()))
+    )]
+    fn test_normalized_url(url: &str, expected: Result<&str, ()>) {
+        assert_eq!(normalized_url(url).as_deref().map_err(|_| ()), expected);
+    }
+
+    #[test]
+    fn test_find_all_by_url() {
+        let entry = |service: &str, url: Option<&str>| {
+            let metadata = url
+                .map(|url| HashMap::from([("url".to_string(), url.to_string())]))
+                .unwrap_or_default();
+            PasswordEntry::new(service.to_string(), None, "password".to_string())
+                .with_metadata(metadata)
+        };
+        let passwords = Passwords::from(vec![
+            entry("github", Some("https://www.github.com/login")),
+            entry("gist", Some("https://gist.github.com")),
+            entry("github.com", None),
+        ]);
+        let services = |url: &str| -> Vec<&str> {
+            passwords
+                .find_all_by_url(url)
+                .into_iter()
+                .map(|(_, pwd)| pwd.service.as_str())
+                .collect()
+        };
+        assert_eq!(services("github.com"), vec!["github"]);
+        assert_eq!(services("http://GITHUB.com/settings"), vec!["github"]);
+        assert_eq!(services("gist.github.com"), vec!["gist"]);
+        assert!(services("gitlab.com").is_empty());
+    }
+
+    #[test]
+    fn test_find_without_username() {
+        let entry = |username: Option<&str>, password: &str| {
+            PasswordEntry::new(
+                "wifi".to_string(),
+                username.map(|u| u.to_string()),
+                password.to_string(),
+            )
+        };
+        let mut passwords = Passwords::from(vec![
+            entry(Some("guest"), "password1"),
+            entry(None, "password2"),
+            entry(Some("admin"), "password3"),
+        ]);
+        assert_eq!(
+            passwords.find("wifi".to_string(), None),
+            Some(&entry(None, "password2"))
+        );
+        assert_eq!(
+            passwords.find("wifi".to_string(), Some(" ".to_string())),
+            Some(&entry(None, "password2"))
+        );
+        assert_eq!(
+            passwords.find("wifi".to_string(), Some("admin".to_string())),
+            Some(&entry(Some("admin"), "password3"))
+        );
+        assert_eq!(passwords.usernames("wifi"), vec!["guest", "admin"]);
+
+        assert_eq!(
+            passwords.remove("wifi".to_string(), None),
+            Some(entry(None, "password2"))
+        );
+        assert_eq!(passwords.find("wifi".to_string(), None), None);
+        assert_eq!(passwords.remove("wifi".to_string(), None), None);
+        assert_eq!(passwords.0.len(), 2);
+        assert_eq!(entry(Some(""), "password4").username, None);
+    }
+
+    #[test]
+    fn test_indexed_entries() {
+        let entry = |service: &str, password: &str| {
+            PasswordEntry::new(service.to_string(), None, password.to_string())
+        };
+        let mut passwords = Passwords::from(vec![
+            entry("gitlab", "password1"),
+            entry("github", "password2").with_favorite(true),
+            entry("gitlab", "password3"),
+        ]);
+        let indexed: Vec<(usize, &str)> = passwords
+            .indexed_entries(&EntryFilter::default())
+            .into_iter()
+            .map(|(index, pwd)| (index, pwd.password.as_str()))
+            .collect();
+        assert_eq!(
+            indexed,
+            vec![(1, "password2"), (2, "password1"), (3, "password3")]
+        );
+
+        // a filtered listing keeps the indices of the full listing
+        let filter = EntryFilter {
+            exclude: vec!["github".to_string()],
+            ..Default::default()
+        };
+        let indices: Vec<usize> = passwords
+            .indexed_entries(&filter)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(indices, vec![2, 3]);
+
+        // duplicates of a service and username are told apart by their index
+        assert_eq!(passwords.find_by_index(3).unwrap().password, "password3");
+        assert!(passwords.find_by_index(0).is_none());
+        assert!(passwords.find_by_index(4).is_none());
+        assert_eq!(passwords.remove_by_index(2).unwrap().password, "password1");
+        assert!(passwords.remove_by_index(3).is_none());
+        assert_eq!(
+            passwords,
+            Passwords::from(vec![
+                entry("github", "password2").with_favorite(true),
+                entry("gitlab", "password3"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_expired_entries_are_hidden() {
+        let expired = PasswordEntry {
+            expires_at: Some(1),
+            ..PasswordEntry::new("temp".to_string(), None, "password1".to_string())
+        };
+        let live = PasswordEntry::new("github".to_string(), None, "password2".to_string())
+            .with_ttl(Some(Duration::from_secs(3600)));
+        assert!(expired.is_expired());
+        assert!(!live.is_expired());
+        assert!(live.expires_at.unwrap() >= unix_now() + 3599);
+
+        let mut passwords = Passwords::from(vec![expired.clone(), live.clone()]);
+        assert!(passwords.find("temp".to_string(), None).is_none());
+        assert!(passwords.find_ignoring_case("TEMP", None).is_none());
+        assert_eq!(passwords.services(), BTreeSet::from(["github".to_string()]));
+        assert_eq!(passwords.entries(&EntryFilter::default()), vec![&live]);
+        assert_eq!(passwords.find_by_index(1), Some(&live));
+        assert!(passwords.remove("temp".to_string(), None).is_none());
+
+        assert_eq!(passwords.gc(), 1);
+        assert_eq!(passwords, Passwords::from(vec![live]));
+        assert_eq!(
+            Passwords::from(vec![expired]).empty_message(),
+            "No passwords stored."
+        );
+    }
+
+    #[test]
+    fn test_clipboard_ttl() {
+        let entry = PasswordEntry::new("bank".to_string(), None, "password1".to_string())
+            .with_clipboard_ttl(Some(Duration::from_secs(30)));
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains("\"clipboard_ttl\":30"));
+        let parsed: PasswordEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed.view(false).clipboard_ttl,
+            Some(Duration::from_secs(30))
+        );
+        // the view is printed as JSON by list, which has no use for it
+        assert!(!parsed
+            .view(false)
+            .to_json()
+            .to_string()
+            .contains("clipboard_ttl"));
+
+        let plain = PasswordEntry::new("forum".to_string(), None, "password2".to_string());
+        assert!(!serde_json::to_string(&plain)
+            .unwrap()
+            .contains("clipboard_ttl"));
+        assert_eq!(plain.view(false).clipboard_ttl, None);
+    }
+
+    #[rstest(
+        show_index,
+        expected,
+        case(
+            false,
+            vec![
+                "─────────────────────────────────┼──────────┼─────────",
+                "github                           │ octocat  │ ***",
+                "a-service-with-a-very-long-name… │          │ hunter2",
+            ]
+        ),
+        case(
+            true,
+            vec![
+                "───┼──────────────────────────────────┼──────────┼─────────",
+                "1  │ github                           │ octocat  │ ***",
+                "10 │ a-service-with-a-very-long-name… │          │ hunter2",
+            ]
+        )
+    )]
+    fn test_print_table(show_index: bool, expected: Vec<&str>) {
+        let entries = vec![
+            PasswordEntry::new(
+                "github".to_string(),
+                Some("octocat".to_string()),
+                "password1".to_string(),
+            )
+            .view(false)
+            .with_index(show_index.then_some(1)),
+            PasswordEntry::new(
+                "a-service-with-a-very-long-name-indeed".to_string(),
+                None,
+                "hunter2".to_string(),
+            )
+            .view(true)
+            .with_index(show_index.then_some(10)),
+        ];
+        let mut output = Vec::new();
+        print_table(&mut output, &entries);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].contains("Service"));
+        assert_eq!(lines[1..], expected);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let entry = |service: &str, username: Option<&str>, password: &str| {
+            PasswordEntry::new(
+                service.to_string(),
+                username.map(|u| u.to_string()),
+                password.to_string(),
+            )
+        };
+        let mut passwords = Passwords::from(vec![
+            entry("service1", Some("username1"), "password1"),
+            // added again later
+            entry("service1", Some("username1"), "password1").with_updated_at(1),
+            entry("service1", Some("username1"), "password2"),
+            entry("service2", None, "password3"),
+            entry("service2", None, "password3"),
+            entry("service2", None, "password3"),
+            entry("service3", Some("username3"), "password4"),
+        ]);
+        let report = passwords.dedup();
+        assert_eq!(report.pruned, 3);
+        assert_eq!(
+            report.near_duplicates,
+            vec![("service1".to_string(), Some("username1".to_string()))]
+        );
+        assert_eq!(
+            passwords,
+            Passwords::from(vec![
+                entry("service1", Some("username1"), "password1"),
+                entry("service1", Some("username1"), "password2"),
+                entry("service2", None, "password3"),
+                entry("service3", Some("username3"), "password4"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_print_all_favorites() {
+        let passwords = Passwords::from(vec![
+            PasswordEntry::new("service1".to_string(), None, "password1".to_string()),
+            PasswordEntry::new("service2".to_string(), None, "password2".to_string())
+                .with_favorite(true),
+        ]);
+        let raw_passwords = serde_json::to_string(&passwords).unwrap();
+        assert_eq!(raw_passwords.matches("favorite").count(), 1);
+        assert_eq!(
+            Passwords::parse_passwords(&raw_passwords).unwrap(),
+            passwords
+        );
+
+        let mut output = Vec::new();
+        passwords.print_all(
+            &mut output,
+            true,
+            &EntryFilter::default(),
+            None,
+            false,
+            None,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.find("service2").unwrap() < output_str.find("service1").unwrap());
+
+        let mut output = Vec::new();
+        passwords.print_all(
+            &mut output,
+            true,
+            &EntryFilter {
+                favorites_only: true,
+                ..Default::default()
+            },
+            None,
+            false,
+            None,
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("service2"));
+        assert!(!output_str.contains("service1"));
+
+        let mut output = Vec::new();
+        Passwords::from(vec![PasswordEntry::new(
+            "service1".to_string(),
+            None,
+            "password1".to_string(),
+        )])
+        .print_all(
+            &mut output,
+            false,
+            &EntryFilter {
+                favorites_only: true,
+                ..Default::default()
+            },
+            None,
+            false,
+            None,
+        );
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("No entries match."));
+    }
+
     #[rstest(
         test_passwords,
         show_passwords,
@@ -223,7 +1573,14 @@ mod tests {
     fn test_print_all(test_passwords: Vec<PasswordEntry>, show_passwords: bool) {
         let passwords = Passwords::from(test_passwords);
         let mut output = Vec::new();
-        passwords.print_all(&mut output, show_passwords, None);
+        passwords.print_all(
+            &mut output,
+            show_passwords,
+            &EntryFilter::default(),
+            None,
+            false,
+            None,
+        );
         let output_str = String::from_utf8(output).unwrap();
         for password in passwords.0 {
             if show_passwords {
@@ -237,4 +1594,103 @@ mod tests {
             };
         }
     }
+
+    #[rstest(
+        folder,
+        expected,
+        case("", ""),
+        case("work", "work"),
+        case(" /work//aws/ prod/", "work/aws/prod"),
+        case("/", "")
+    )]
+    fn test_normalized_folder(folder: &str, expected: &str) {
+        assert_eq!(normalized_folder(folder), expected);
+    }
+
+    #[rstest(
+        entry_folder,
+        folder,
+        expected,
+        case("", "", true),
+        case("work/aws/prod", "", true),
+        case("work/aws/prod", "work", true),
+        case("work/aws/prod", "work/aws/", true),
+        case("work/aws/prod", "work/aws/prod", true),
+        case("work/aws-old", "work/aws", false),
+        case("work", "work/aws", false),
+        case("", "work", false)
+    )]
+    fn test_is_in_folder(entry_folder: &str, folder: &str, expected: bool) {
+        let entry = PasswordEntry::new("aws".to_string(), None, "password1".to_string())
+            .with_folder(entry_folder);
+        assert_eq!(entry.is_in_folder(folder), expected);
+        let filter = EntryFilter {
+            folder: Some(folder.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(filter.matches(&entry), expected);
+    }
+
+    #[test]
+    fn test_folder_serialization() {
+        // entries from before folders existed are in the root folder
+        let old: PasswordEntry =
+            serde_json::from_str(r#"{"service":"forum","username":null,"password":"password1"}"#)
+                .unwrap();
+        assert_eq!(old.folder, "");
+        assert!(!serde_json::to_string(&old).unwrap().contains("folder"));
+
+        let entry = PasswordEntry::new("aws".to_string(), None, "password2".to_string())
+            .with_folder("work/aws");
+        let parsed: PasswordEntry =
+            serde_json::from_str(&serde_json::to_string(&entry).unwrap()).unwrap();
+        assert_eq!(parsed.folder, "work/aws");
+        assert_eq!(parsed.view(false).to_json()["folder"], "work/aws");
+    }
+
+    #[test]
+    fn test_print_tree() {
+        let entry = |service: &str, folder: &str| {
+            PasswordEntry::new(service.to_string(), None, "password".to_string())
+                .with_folder(folder)
+                .view(false)
+        };
+        let entries = vec![
+            entry("github", ""),
+            entry("console", "work/aws/prod"),
+            entry("jira", "work"),
+            entry("staging", "work/aws/staging"),
+        ];
+        let mut output = Vec::new();
+        print_tree(&mut output, &entries);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<String> = output
+            .lines()
+            .map(|line| {
+                // drop the colors and the rest of the entry after its service
+                let mut line = line.to_string();
+                while let Some(start) = line.find('\x1b') {
+                    let end = start + line[start..].find('m').unwrap();
+                    line.replace_range(start..=end, "");
+                }
+                match line.split_once("Service: ") {
+                    Some((tree, rest)) => format!("{tree}{}", rest.split(',').next().unwrap()),
+                    None => line,
+                }
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "├── work/",
+                "│   ├── aws/",
+                "│   │   ├── prod/",
+                "│   │   │   └── console",
+                "│   │   └── staging/",
+                "│   │       └── staging",
+                "│   └── jira",
+                "└── github",
+            ]
+        );
+    }
 }
@@ -1,154 +1,512 @@
 use crate::{
     cli::{
-        args::{get_password_store_path, Length, DEFAULT_PASSWORD_FILENAME},
+        args::{
+            get_password_store_path, master_password_prompt, Length, ListFormat, ShowFormat,
+            PROJECT_STORE_FILENAME, STDIO_FILE_NAME,
+        },
         commands::{
-            add_password, generate_password, list_passwords, remove_password, show_password,
-            update_master_password,
+            add_password, audit_reveals, check_master_strength, describe_generator_error,
+            find_entry, generate_password, list_entries, list_passwords, print_entry,
+            remove_password, update_master_password, AddOptions, GenerateOptions, ListOptions,
+            NotFound,
         },
         io::{
             bold, colorize, print, read_hidden_input, read_hidden_input_with_confirmation,
             read_terminal_input, MessageType, PromptPassword,
         },
+        report_entry_error,
+        settings::Settings,
+        MASTER_PASSWORD_RETRIES,
     },
-    store::PasswordStore,
+    pass::{EntryFilter, EntryView},
+    store::{PasswordStore, StoreError, StoreOptions},
 };
 use passwords::PasswordGenerator;
+use serde_json::{json, Value};
 use std::{
-    io::{BufRead, Write},
-    path::PathBuf,
+    io::{BufRead, IsTerminal, Sink, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError},
+    time::{Duration, Instant},
 };
 
+/// Starts the REPL on the store named by `file_name`. With `ListFormat::Ndjson` the banner, menus
+/// and prompts are left out and every command prints its result as one JSON object per line, so
+/// that the REPL can be driven over pipes. A missing store is initialized with the options in
+/// `create`, or after asking, with the default options.
 pub fn repl<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     prompt_password: &dyn PromptPassword,
-    file_name: String,
+    file_name: Option<String>,
+    create: Option<StoreOptions>,
+    format: ListFormat,
+    settings: &Settings,
 ) {
-    print(writer, &bold("Welcome to L🦀CKBOX!\n"), None);
-    let file_path =
-        get_password_store_path(file_name).unwrap_or(PathBuf::from(DEFAULT_PASSWORD_FILENAME));
-    let master = if !file_path.exists() {
-        read_hidden_input_with_confirmation(writer, "master password", prompt_password)
+    if format == ListFormat::Text {
+        print(writer, &bold("Welcome to L🦀CKBOX!\n"), None);
+    }
+    if file_name.as_deref() == Some(STDIO_FILE_NAME) {
+        print_error(
+            writer,
+            "open",
+            anyhow::anyhow!("The REPL can't read the password store from stdin"),
+            format,
+        );
+        return;
+    }
+    let is_default = file_name.is_none();
+    let file_path = match get_password_store_path(file_name) {
+        Ok(file_path) => file_path,
+        Err(err) => {
+            print_error(writer, "open", err, format);
+            return;
+        }
+    };
+    if format == ListFormat::Text && is_default && file_path.ends_with(PROJECT_STORE_FILENAME) {
+        print(
+            writer,
+            &format!("Using the password store {}", file_path.display()),
+            Some(MessageType::Info),
+        );
+    }
+    let is_new_store = !PasswordStore::exists(&file_path);
+    if is_new_store && create.is_none() {
+        if format == ListFormat::Ndjson {
+            print_error(
+                writer,
+                "open",
+                anyhow::anyhow!(
+                    "{}; pass --create to initialize it",
+                    StoreError::NotFound(file_path)
+                ),
+                format,
+            );
+            return;
+        }
+        if !confirm_create(reader, writer, &file_path) {
+            return;
+        }
+    }
+    let prompt = master_password_prompt(Some(&file_path));
+    let master = if is_new_store {
+        read_hidden_input_with_confirmation(writer, &prompt, prompt_password, settings)
+    } else {
+        read_hidden_input(&prompt, prompt_password, settings)
+    };
+    if is_new_store && !check_new_master(reader, writer, "open", &master, format, settings) {
+        return;
+    }
+    let password_store = if is_new_store {
+        PasswordStore::create_with_options(file_path, master, create.unwrap_or_default())
     } else {
-        read_hidden_input("master password", prompt_password)
+        PasswordStore::open(file_path, master)
     };
-    let password_store = match PasswordStore::new(file_path, master) {
+    let password_store = match password_store {
         Ok(password_store) => password_store,
+        Err(err) if format == ListFormat::Ndjson => {
+            print_result(writer, "open", Err(err.into()));
+            return;
+        }
         Err(err) => {
             writeln!(writer, "{}", err).unwrap_or_else(|_| println!("{}", err));
             return;
         }
     };
-    run_repl(reader, writer, prompt_password, password_store);
+    run_repl(
+        reader,
+        writer,
+        prompt_password,
+        password_store,
+        format,
+        settings,
+    );
 }
 
+/// How long a signal waits for the command holding the store to finish before exiting without
+/// scrubbing it.
+const SCRUB_GRACE: Duration = Duration::from_secs(1);
+
+/// Locks the shared store for one step of a command. Commands only hold the lock while they use
+/// the store and never while they wait for input, so that a signal can scrub it at any prompt.
+fn lock_store(shared_store: &Mutex<PasswordStore>) -> MutexGuard<'_, PasswordStore> {
+    shared_store.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Scrubs the shared store, see `PasswordStore::scrub`, waiting up to `grace` for a running
+/// command to release it. Returns whether it was scrubbed.
+fn scrub_shared_store(password_store: &Mutex<PasswordStore>, grace: Duration) -> bool {
+    let deadline = Instant::now() + grace;
+    loop {
+        match password_store.try_lock() {
+            Ok(mut password_store) => break password_store.scrub(),
+            Err(TryLockError::Poisoned(poisoned)) => break poisoned.into_inner().scrub(),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(10))
+            }
+            Err(TryLockError::WouldBlock) => return false,
+        }
+    }
+    true
+}
+
+/// Stops listening for signals when the REPL ends.
+#[cfg(unix)]
+struct SignalGuard(signal_hook::iterator::Handle);
+
+#[cfg(unix)]
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// In an interactive session, scrubs the store before exiting on SIGINT or SIGTERM, e.g. Ctrl-C,
+/// so that the master password and the decrypted entries are not left in memory.
+#[cfg(unix)]
+fn scrub_on_signal(password_store: &Arc<Mutex<PasswordStore>>) -> Option<SignalGuard> {
+    use signal_hook::{
+        consts::{SIGINT, SIGTERM},
+        iterator::Signals,
+    };
+    if !std::io::stdin().is_terminal() {
+        return None;
+    }
+    let mut signals = Signals::new([SIGINT, SIGTERM]).ok()?;
+    let handle = signals.handle();
+    let password_store = Arc::clone(password_store);
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            scrub_shared_store(&password_store, SCRUB_GRACE);
+            std::process::exit(128 + signal);
+        }
+    });
+    Some(SignalGuard(handle))
+}
+
+#[cfg(not(unix))]
+fn scrub_on_signal(_password_store: &Arc<Mutex<PasswordStore>>) {}
+
+/// Loads the store and runs the REPL on it. Like `open_password_store`, a wrong master password is
+/// re-prompted up to `MASTER_PASSWORD_RETRIES` times, but only in a text session answered at a
+/// terminal, so that scripts and ndjson clients fail on the first wrong password.
 pub fn run_repl<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     prompt_password: &dyn PromptPassword,
     mut password_store: PasswordStore,
+    format: ListFormat,
+    settings: &Settings,
 ) {
+    let mut retries_left = match format {
+        ListFormat::Text => MASTER_PASSWORD_RETRIES,
+        ListFormat::Ndjson => 0,
+    };
     while let Err(err) = password_store.load() {
-        print(
-            writer,
-            &format!("Failed to load password store: {err}"),
-            Some(MessageType::Error),
+        let wrong_master_password = matches!(err, StoreError::WrongMasterPassword);
+        match format {
+            ListFormat::Text => print(
+                writer,
+                &format!("Failed to load password store: {err}"),
+                Some(MessageType::Error),
+            ),
+            ListFormat::Ndjson => print_result(writer, "open", Err(err.into())),
+        }
+        if !wrong_master_password || retries_left == 0 || !prompt_password.is_interactive() {
+            return;
+        }
+        retries_left -= 1;
+        let master = read_hidden_input(
+            &master_password_prompt(password_store.file_path().map(PathBuf::as_path)),
+            prompt_password,
+            settings,
         );
-        let master = read_hidden_input("master password", prompt_password);
         password_store.update_master(master);
     }
+    let shared_store = Arc::new(Mutex::new(password_store));
+    let _signal_guard = scrub_on_signal(&shared_store);
+    run_commands(
+        reader,
+        writer,
+        prompt_password,
+        &shared_store,
+        format,
+        settings,
+    );
+}
+
+/// Reads and runs commands on the loaded store until `exit` or the end of the input.
+fn run_commands<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prompt_password: &dyn PromptPassword,
+    shared_store: &Mutex<PasswordStore>,
+    format: ListFormat,
+    settings: &Settings,
+) {
     loop {
-        let message = [
-            format!(
-                "[{}] {} password",
-                colorize(&bold("1"), MessageType::Success),
-                colorize(&bold("add"), MessageType::Success)
-            ),
-            format!(
-                "[{}] {} random password",
-                colorize(&bold("2"), MessageType::Success),
-                colorize(&bold("generate"), MessageType::Success)
-            ),
-            format!(
-                "[{}] {} passwords",
-                colorize(&bold("3"), MessageType::Success),
-                colorize(&bold("list"), MessageType::Success)
-            ),
-            format!(
-                "[{}] {} password",
-                colorize(&bold("4"), MessageType::Success),
-                colorize(&bold("remove"), MessageType::Success)
-            ),
-            format!(
-                "[{}] {} password",
-                colorize(&bold("5"), MessageType::Success),
-                colorize(&bold("show"), MessageType::Success)
-            ),
-            format!(
-                "[{}] {} password",
-                colorize(&bold("6"), MessageType::Success),
-                colorize(&bold("update master"), MessageType::Success)
-            ),
-            format!(
-                "[{}] {}",
-                colorize(&bold("7"), MessageType::Success),
-                colorize(&bold("exit"), MessageType::Success)
-            ),
-        ];
+        if format == ListFormat::Text {
+            let message = [
+                format!(
+                    "[{}] {} password",
+                    colorize(&bold("1"), MessageType::Success),
+                    colorize(&bold("add"), MessageType::Success)
+                ),
+                format!(
+                    "[{}] {} random password",
+                    colorize(&bold("2"), MessageType::Success),
+                    colorize(&bold("generate"), MessageType::Success)
+                ),
+                format!(
+                    "[{}] {} passwords",
+                    colorize(&bold("3"), MessageType::Success),
+                    colorize(&bold("list"), MessageType::Success)
+                ),
+                format!(
+                    "[{}] {} password",
+                    colorize(&bold("4"), MessageType::Success),
+                    colorize(&bold("remove"), MessageType::Success)
+                ),
+                format!(
+                    "[{}] {} password",
+                    colorize(&bold("5"), MessageType::Success),
+                    colorize(&bold("show"), MessageType::Success)
+                ),
+                format!(
+                    "[{}] {} password",
+                    colorize(&bold("6"), MessageType::Success),
+                    colorize(&bold("update master"), MessageType::Success)
+                ),
+                format!(
+                    "[{}] {}",
+                    colorize(&bold("7"), MessageType::Success),
+                    colorize(&bold("exit"), MessageType::Success)
+                ),
+            ];
 
-        let message = message.join(" ");
-        writeln!(writer, "\nEnter {message}").unwrap();
-        let input = read_terminal_input(reader, writer, None);
+            let message = message.join(" ");
+            writeln!(writer, "\nEnter {message}").unwrap();
+        }
+        let input = read_input(reader, writer, None, format);
         match input.as_str() {
-            "1" | "add" | "a" => {
-                handle_add_password(reader, writer, prompt_password, &mut password_store)
+            "1" | "add" | "a" => handle_add_password(
+                reader,
+                writer,
+                prompt_password,
+                shared_store,
+                format,
+                settings,
+            ),
+            "2" | "generate" | "g" => handle_generate_password(writer, format, settings),
+            "3" | "list" | "l" => handle_list_passwords(writer, shared_store, format, settings),
+            "4" | "remove" | "r" => {
+                handle_remove_password(reader, writer, shared_store, format, settings)
             }
-            "2" | "generate" | "g" => handle_generate_password(writer),
-            "3" | "list" | "l" => handle_list_passwords(writer, &mut password_store),
-            "4" | "remove" | "r" => handle_remove_password(reader, writer, &mut password_store),
-            "5" | "show" | "s" => handle_show_password(reader, writer, &mut password_store),
-            "6" | "update" | "u" => {
-                handle_update_master_password(writer, prompt_password, &mut password_store)
+            "5" | "show" | "s" => {
+                handle_show_password(reader, writer, shared_store, format, settings)
             }
+            "6" | "update" | "u" => handle_update_master_password(
+                reader,
+                writer,
+                prompt_password,
+                shared_store,
+                format,
+                settings,
+            ),
             _ => break,
         }
     }
 }
 
-fn handle_add_password<R: BufRead, W: Write>(
+/// Reads the next line of input. The prompts are left out in ndjson mode.
+fn read_input<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
-    prompt_password: &dyn PromptPassword,
-    password_store: &mut PasswordStore,
-) {
+    prompt: Option<&str>,
+    format: ListFormat,
+) -> String {
+    match format {
+        ListFormat::Text => read_terminal_input(reader, writer, prompt),
+        ListFormat::Ndjson => {
+            let mut input = String::new();
+            reader.read_line(&mut input).unwrap();
+            input.trim().to_owned()
+        }
+    }
+}
+
+/// Prints the result of a command in ndjson mode as a single JSON object, e.g.
+/// `{"command":"show","ok":true,"entry":{...}}` or `{"command":"show","ok":false,"error":"..."}`.
+fn print_result<W: Write>(writer: &mut W, command: &str, result: anyhow::Result<Value>) {
+    let mut response = json!({ "command": command, "ok": result.is_ok() });
+    match result {
+        Ok(Value::Object(fields)) => response.as_object_mut().unwrap().extend(fields),
+        Ok(_) => (),
+        Err(err) => response["error"] = json!(err.to_string()),
+    }
+    writeln!(writer, "{response}").unwrap();
+}
+
+fn print_error<W: Write>(writer: &mut W, command: &str, err: anyhow::Error, format: ListFormat) {
+    match format {
+        ListFormat::Text => print(writer, &format!("Error: {err}"), Some(MessageType::Error)),
+        ListFormat::Ndjson => print_result(writer, command, Err(err)),
+    }
+}
+
+/// Where a command writes its messages: `writer` in text mode and nowhere in ndjson mode, which
+/// prints a JSON result instead.
+fn command_output<'a, W: Write>(
+    writer: &'a mut W,
+    sink: &'a mut Sink,
+    format: ListFormat,
+) -> &'a mut dyn Write {
+    match format {
+        ListFormat::Text => writer,
+        ListFormat::Ndjson => sink,
+    }
+}
+
+/// Checks a new master password with `check_master_strength`, printing why it is refused. Text
+/// mode asks before using a weak one, while ndjson mode refuses it unless --allow-weak-master was
+/// passed, as there is nobody to ask.
+fn check_new_master<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    command: &str,
+    master: &str,
+    format: ListFormat,
+    settings: &Settings,
+) -> bool {
+    let result = match format {
+        ListFormat::Text => {
+            check_master_strength(Some(reader), writer, master, settings.allow_weak_master)
+        }
+        ListFormat::Ndjson => {
+            check_master_strength(None::<&mut R>, writer, master, settings.allow_weak_master)
+        }
+    };
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            print_error(writer, command, err, format);
+            false
+        }
+    }
+}
+
+/// Asks before initializing a store that does not exist yet, the REPL counterpart of `--create`.
+fn confirm_create<R: BufRead, W: Write>(reader: &mut R, writer: &mut W, file_path: &Path) -> bool {
+    print(
+        writer,
+        &format!("The password store {} does not exist", file_path.display()),
+        Some(MessageType::Warning),
+    );
     let message = [
         format!(
-            "[{}] {} random password",
+            "[{}] {} a new password store",
             colorize(&bold("1"), MessageType::Success),
-            colorize(&bold("generate"), MessageType::Success)
+            colorize(&bold("create"), MessageType::Success)
         ),
         format!(
-            "[{}] {} your own password",
+            "[{}] {}",
             colorize(&bold("2"), MessageType::Success),
-            colorize(&bold("enter"), MessageType::Success)
+            colorize(&bold("exit"), MessageType::Success)
+        ),
+    ];
+    writeln!(writer, "{}", message.join(" ")).unwrap();
+    let input = read_terminal_input(reader, writer, None);
+    matches!(input.as_str(), "1" | "create" | "c")
+}
+
+/// Asks whether to go ahead when the store file changed on disk since this session last loaded
+/// or saved it, e.g. because a sync tool replaced it. Going ahead reloads the store from disk and
+/// applies the change on top of it, so the external changes are kept. Ndjson mode always goes
+/// ahead.
+fn confirm_reload_if_modified<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    shared_store: &Mutex<PasswordStore>,
+    format: ListFormat,
+) -> bool {
+    if !lock_store(shared_store).is_modified_externally() || format == ListFormat::Ndjson {
+        return true;
+    }
+    print(
+        writer,
+        "The password store was modified outside of this session",
+        Some(MessageType::Warning),
+    );
+    let message = [
+        format!(
+            "[{}] {} and apply changes",
+            colorize(&bold("1"), MessageType::Success),
+            colorize(&bold("reload"), MessageType::Success)
         ),
         format!(
             "[{}] {}",
-            colorize(&bold("3"), MessageType::Success),
+            colorize(&bold("2"), MessageType::Success),
             colorize(&bold("cancel"), MessageType::Success)
         ),
     ];
-    let message = message.join(" ");
-    writeln!(writer, "{}", message).unwrap();
+    writeln!(writer, "{}", message.join(" ")).unwrap();
     let input = read_terminal_input(reader, writer, None);
+    match input.as_str() {
+        "1" | "reload" | "r" => true,
+        _ => {
+            print(writer, "Cancelled", Some(MessageType::Warning));
+            false
+        }
+    }
+}
+
+fn handle_add_password<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prompt_password: &dyn PromptPassword,
+    shared_store: &Mutex<PasswordStore>,
+    format: ListFormat,
+    settings: &Settings,
+) {
+    if format == ListFormat::Text {
+        let message = [
+            format!(
+                "[{}] {} random password",
+                colorize(&bold("1"), MessageType::Success),
+                colorize(&bold("generate"), MessageType::Success)
+            ),
+            format!(
+                "[{}] {} your own password",
+                colorize(&bold("2"), MessageType::Success),
+                colorize(&bold("enter"), MessageType::Success)
+            ),
+            format!(
+                "[{}] {}",
+                colorize(&bold("3"), MessageType::Success),
+                colorize(&bold("cancel"), MessageType::Success)
+            ),
+        ];
+        let message = message.join(" ");
+        writeln!(writer, "{}", message).unwrap();
+    }
+    let input = read_input(reader, writer, None, format);
     let generate = match input.as_str() {
         "1" | "generate" | "g" | "random" | "r" => true,
         "2" | "enter" | "e" => false,
         _ => return,
     };
-    let service = read_terminal_input(reader, writer, Some("Please enter the service name"));
-    let username =
-        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"));
+    let service = read_input(
+        reader,
+        writer,
+        Some("Please enter the service name"),
+        format,
+    );
+    let username = read_input(
+        reader,
+        writer,
+        Some("Please enter the username (Optional)"),
+        format,
+    );
     let username = Option::from(username).filter(|s| !s.is_empty());
     let password_generator = PasswordGenerator::new()
         .length(Length::Sixteen.get_val())
@@ -157,34 +515,102 @@ fn handle_add_password<R: BufRead, W: Write>(
         .numbers(true)
         .symbols(false)
         .strict(true);
-    match add_password(
-        writer,
+    let password = (!generate).then(|| {
+        read_hidden_input(
+            &format!("password for {service}"),
+            prompt_password,
+            settings,
+        )
+    });
+    if !confirm_reload_if_modified(reader, writer, shared_store, format) {
+        return;
+    }
+    let result = add_password(
+        &mut command_output(writer, &mut std::io::sink(), format),
         prompt_password,
-        password_store,
-        service,
-        username,
-        None,
-        generate,
-        password_generator,
-    ) {
-        Ok(_) => print(
+        &mut lock_store(shared_store),
+        service.clone(),
+        username.clone(),
+        AddOptions {
+            password,
+            password_generator: generate.then_some(password_generator),
+            ..Default::default()
+        },
+        settings,
+    );
+    match (result, format) {
+        (Ok(_), ListFormat::Text) => print(
             writer,
             "Password added successfully",
             Some(MessageType::Success),
         ),
-        Err(err) => print(writer, &format!("Error: {err}"), Some(MessageType::Error)),
+        (Err(err), ListFormat::Text) => {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error))
+        }
+        (result, ListFormat::Ndjson) => print_result(
+            writer,
+            "add",
+            result.map(|_| json!({ "service": service, "username": username })),
+        ),
     };
 }
 
-fn handle_generate_password<W: Write>(writer: &mut W) {
-    match generate_password(writer, Length::Sixteen, false, true, true, true, 1) {
+fn handle_generate_password<W: Write>(writer: &mut W, format: ListFormat, settings: &Settings) {
+    if format == ListFormat::Ndjson {
+        // the caller gets the password itself, so it is not copied to the clipboard
+        let password_generator = PasswordGenerator::new()
+            .length(Length::Sixteen.get_val())
+            .lowercase_letters(true)
+            .uppercase_letters(true)
+            .numbers(true)
+            .symbols(false)
+            .strict(true);
+        let result = password_generator
+            .generate_one()
+            .map(|password| json!({ "password": password }))
+            .map_err(|err| {
+                anyhow::anyhow!(
+                    "Error generating password: {}",
+                    describe_generator_error(&password_generator, err)
+                )
+            });
+        print_result(writer, "generate", result);
+        return;
+    }
+    match generate_password(writer, &GenerateOptions::default(), None, settings) {
         Ok(_) => (),
         Err(err) => print(writer, &format!("Error: {err}"), Some(MessageType::Error)),
     };
 }
 
-fn handle_list_passwords<W: Write>(writer: &mut W, password_store: &mut PasswordStore) {
-    list_passwords(writer, password_store, true).unwrap_or_else(|err| {
+fn handle_list_passwords<W: Write>(
+    writer: &mut W,
+    shared_store: &Mutex<PasswordStore>,
+    format: ListFormat,
+    settings: &Settings,
+) {
+    let mut password_store = lock_store(shared_store);
+    if format == ListFormat::Ndjson {
+        let result = list_entries(&mut password_store, true, &[], &EntryFilter::default())
+            .and_then(|entries| audit_reveals("list", &entries, settings).map(|_| entries));
+        print_result(
+            writer,
+            "list",
+            result.map(|entries| json!({ "entries": entries })),
+        );
+        return;
+    }
+    list_passwords(
+        writer,
+        &mut password_store,
+        &EntryFilter::default(),
+        &ListOptions {
+            show_passwords: true,
+            ..Default::default()
+        },
+        settings,
+    )
+    .unwrap_or_else(|err| {
         print(
             writer,
             &format!("Failed to load passwords to store: {err}"),
@@ -196,49 +622,152 @@ fn handle_list_passwords<W: Write>(writer: &mut W, password_store: &mut Password
 fn handle_remove_password<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
-    password_store: &mut PasswordStore,
+    shared_store: &Mutex<PasswordStore>,
+    format: ListFormat,
+    settings: &Settings,
 ) {
-    let service = read_terminal_input(reader, writer, Some("Please enter the service name"));
-    let username =
-        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"));
+    let service = read_input(
+        reader,
+        writer,
+        Some("Please enter the service name"),
+        format,
+    );
+    let username = read_input(
+        reader,
+        writer,
+        Some("Please enter the username (Optional)"),
+        format,
+    );
     let username = Option::from(username).filter(|s| !s.is_empty());
-    remove_password(writer, password_store, service, username).unwrap_or_else(|err| {
-        print(
+    if !confirm_reload_if_modified(reader, writer, shared_store, format) {
+        return;
+    }
+    let mut password_store = lock_store(shared_store);
+    if format == ListFormat::Ndjson {
+        let result = find_existing_entry(&mut password_store, &service, &username, settings)
+            .and_then(|_| {
+                remove_password(
+                    &mut std::io::sink(),
+                    &mut password_store,
+                    service.clone(),
+                    username.clone(),
+                )
+            });
+        print_result(
             writer,
-            &format!("Failed to remove password: {err}"),
-            Some(MessageType::Error),
-        )
-    })
+            "remove",
+            result.map(|_| json!({ "service": service, "username": username })),
+        );
+        return;
+    }
+    if let Err(err) = remove_password(writer, &mut password_store, service, username) {
+        match err.downcast_ref::<NotFound>() {
+            Some(not_found) => print(writer, &not_found.to_string(), Some(MessageType::Warning)),
+            None => print(
+                writer,
+                &format!("Failed to remove password: {err}"),
+                Some(MessageType::Error),
+            ),
+        }
+    }
 }
 
 fn handle_show_password<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
-    password_store: &mut PasswordStore,
+    shared_store: &Mutex<PasswordStore>,
+    format: ListFormat,
+    settings: &Settings,
 ) {
-    let service = read_terminal_input(reader, writer, Some("Please enter the service name"));
-    let username =
-        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"));
+    let service = read_input(
+        reader,
+        writer,
+        Some("Please enter the service name"),
+        format,
+    );
+    let username = read_input(
+        reader,
+        writer,
+        Some("Please enter the username (Optional)"),
+        format,
+    );
     let username = Option::from(username).filter(|s| !s.is_empty());
-    if show_password(writer, password_store, service, username).is_err() {
-        print(writer, "Password not found", None);
-    };
+    // the store is released before printing, which waits out the clipboard lifetime of the entry
+    let result = find_existing_entry(&mut lock_store(shared_store), &service, &username, settings);
+    if format == ListFormat::Ndjson {
+        print_result(
+            writer,
+            "show",
+            result.map(|entry| json!({ "entry": entry })),
+        );
+        return;
+    }
+    // notes about the clipboard go to stderr, like the diagnostics of `lockbox show`
+    let result = result.and_then(|entry| {
+        print_entry(
+            writer,
+            &mut std::io::stderr(),
+            entry,
+            ShowFormat::Text,
+            settings,
+        )
+    });
+    if let Err(err) = result {
+        report_entry_error(writer, err, false);
+    }
+}
+
+/// Loads the store and returns the entry for `service` and `username`, with the password. A
+/// missing entry is an error.
+fn find_existing_entry(
+    password_store: &mut PasswordStore,
+    service: &str,
+    username: &Option<String>,
+    settings: &Settings,
+) -> anyhow::Result<EntryView> {
+    find_entry(password_store, service, username.as_deref(), settings)?.ok_or_else(|| {
+        NotFound(password_store.not_found_message(service, username.as_deref())).into()
+    })
 }
 
-fn handle_update_master_password<W: Write>(
+fn handle_update_master_password<R: BufRead, W: Write>(
+    reader: &mut R,
     writer: &mut W,
     prompt_password: &dyn PromptPassword,
-    password_store: &mut PasswordStore,
+    shared_store: &Mutex<PasswordStore>,
+    format: ListFormat,
+    settings: &Settings,
 ) {
     let new_master_password =
-        read_hidden_input_with_confirmation(writer, "new password", prompt_password);
-    update_master_password(writer, new_master_password, password_store).unwrap_or_else(|err| {
-        print(
-            writer,
-            &format!("Failed to update master password: {err}"),
-            Some(MessageType::Error),
-        );
-    });
+        read_hidden_input_with_confirmation(writer, "new password", prompt_password, settings);
+    if !check_new_master(
+        reader,
+        writer,
+        "update",
+        &new_master_password,
+        format,
+        settings,
+    ) {
+        return;
+    }
+    if !confirm_reload_if_modified(reader, writer, shared_store, format) {
+        return;
+    }
+    let result = update_master_password(
+        &mut command_output(writer, &mut std::io::sink(), format),
+        new_master_password,
+        &mut lock_store(shared_store),
+    );
+    match format {
+        ListFormat::Text => result.unwrap_or_else(|err| {
+            print(
+                writer,
+                &format!("Failed to update master password: {err}"),
+                Some(MessageType::Error),
+            );
+        }),
+        ListFormat::Ndjson => print_result(writer, "update", result.map(|_| Value::Null)),
+    }
 }
 
 #[cfg(test)]
@@ -248,6 +777,7 @@ mod tests {
     use super::*;
 
     use mockall::predicate::eq;
+    use std::collections::HashMap;
     use tempfile::NamedTempFile;
 
     use rstest::rstest;
@@ -256,23 +786,27 @@ mod tests {
     fn test_repl() {
         let mut input = b"" as &[u8];
         let mut output = Vec::new();
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_file_name = temp_file.path().to_str().unwrap().to_string();
         let mut mock_prompt_password = MockPromptPassword::new();
         mock_prompt_password
             .expect_prompt_password()
             .with(eq(format!(
-                "Please enter the master password\n{}",
+                "Please enter the master password for the store '{temp_file_name}'\n{}",
                 colorize(">> ", MessageType::DarkYellow)
             )))
             .times(1)
             .returning(|_| Ok("secret".to_string()));
-        let temp_file = NamedTempFile::new().unwrap();
-        let temp_file_name = temp_file.path().to_str().unwrap().to_string();
+        PasswordStore::create(temp_file.path().to_path_buf(), "secret".to_string()).unwrap();
 
         repl(
             &mut input,
             &mut output,
             &mock_prompt_password,
-            temp_file_name,
+            Some(temp_file_name),
+            None,
+            ListFormat::Text,
+            &Settings::default(),
         );
 
         let output_str = String::from_utf8(output).unwrap();
@@ -291,6 +825,117 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_repl_stdio_store() {
+        let mut output = Vec::new();
+        repl(
+            &mut (b"" as &[u8]),
+            &mut output,
+            &MockPromptPassword::new(),
+            Some(STDIO_FILE_NAME.to_string()),
+            None,
+            ListFormat::Text,
+            &Settings::default(),
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("The REPL can't read the password store from stdin"));
+    }
+
+    #[test]
+    fn test_repl_missing_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let mut output = Vec::new();
+        repl(
+            &mut (b"2\n" as &[u8]),
+            &mut output,
+            &MockPromptPassword::new(),
+            Some(file_path.to_str().unwrap().to_string()),
+            None,
+            ListFormat::Text,
+            &Settings::default(),
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("does not exist"));
+        assert!(!file_path.exists());
+    }
+
+    #[test]
+    fn test_repl_ndjson() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut password_store =
+            PasswordStore::create(temp_file.path().to_path_buf(), "secret".to_string()).unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "service".to_string(),
+                Some("username".to_string()),
+                "password".to_string(),
+                HashMap::new(),
+                false,
+            )
+            .unwrap()
+            .save()
+            .unwrap();
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(|_| Ok("secret".to_string()));
+        let mut input = b"list\nshow\nservice\nusername\nshow\nmissing\n\ngenerate\nremove\nservice\nusername\nlist\nexit\n" as &[u8];
+        let mut output = Vec::new();
+
+        repl(
+            &mut input,
+            &mut output,
+            &mock_prompt_password,
+            Some(temp_file.path().to_str().unwrap().to_string()),
+            None,
+            ListFormat::Ndjson,
+            &Settings::default(),
+        );
+
+        let output_str = String::from_utf8(output).unwrap();
+        let results: Vec<Value> = output_str
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[0]["command"], "list");
+        assert_eq!(results[0]["entries"][0]["password"], "password");
+        assert_eq!(results[1]["entry"]["username"], "username");
+        assert_eq!(results[2]["ok"], false);
+        assert_eq!(results[2]["error"], "Password not found");
+        assert_eq!(results[3]["password"].as_str().unwrap().len(), 16);
+        assert_eq!(results[4]["command"], "remove");
+        assert_eq!(results[4]["ok"], true);
+        assert_eq!(results[5]["entries"], json!([]));
+    }
+
+    #[test]
+    fn test_repl_ndjson_missing_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let mut output = Vec::new();
+        repl(
+            &mut (b"" as &[u8]),
+            &mut output,
+            &MockPromptPassword::new(),
+            Some(file_path.to_str().unwrap().to_string()),
+            None,
+            ListFormat::Ndjson,
+            &Settings::default(),
+        );
+        let result: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(result["ok"], false);
+        assert!(result["error"]
+            .as_str()
+            .unwrap()
+            .contains("pass --create to initialize it"));
+        assert!(!file_path.exists());
+    }
+
     #[rstest(
         input,
         expected_output,
@@ -319,7 +964,7 @@ mod tests {
     )]
     fn test_run_repl(input: &[u8], expected_output: Vec<&str>) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, "secret".to_string()).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -328,9 +973,11 @@ mod tests {
             &mut password_store,
             "service".to_string(),
             Some("username".to_string()),
-            Some("password".to_string()),
-            false,
-            PasswordGenerator::default(),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
         )
         .unwrap();
         let mut input = input;
@@ -341,6 +988,8 @@ mod tests {
             &mut output,
             mock_prompt_password,
             password_store,
+            ListFormat::Text,
+            &Settings::default(),
         );
 
         let output_str = String::from_utf8(output).unwrap();
@@ -361,10 +1010,165 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_run_repl_corrupt_store() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        // a version 3 header with unknown flags, long enough not to look truncated
+        std::fs::write(
+            &temp_file,
+            [b"LKBX\x03\x00\xff".as_slice(), &[0; 32]].concat(),
+        )
+        .unwrap();
+        let password_store = PasswordStore::open(temp_file, "secret".to_string()).unwrap();
+        let mut input = b"" as &[u8];
+        let mut output = Vec::new();
+        let mock_prompt_password = &MockPromptPassword::new();
+        run_repl(
+            &mut input,
+            &mut output,
+            mock_prompt_password,
+            password_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("The password store is corrupt"));
+    }
+
+    #[rstest(
+        format,
+        interactive,
+        prompts,
+        case(ListFormat::Text, true, MASTER_PASSWORD_RETRIES),
+        case(ListFormat::Text, false, 0),
+        case(ListFormat::Ndjson, true, 0)
+    )]
+    fn test_run_repl_wrong_master_password(format: ListFormat, interactive: bool, prompts: usize) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        PasswordStore::create(temp_file.clone(), "secret".to_string())
+            .unwrap()
+            .save()
+            .unwrap();
+        let password_store = PasswordStore::open(temp_file, "wrong".to_string()).unwrap();
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_is_interactive()
+            .return_const(interactive);
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(prompts)
+            .returning(|_| Ok("still wrong".to_string()));
+        let mut input = b"list\nexit\n" as &[u8];
+        let mut output = Vec::new();
+        run_repl(
+            &mut input,
+            &mut output,
+            &mock_prompt_password,
+            password_store,
+            format,
+            &Settings::default(),
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output_str.matches("Master password incorrect").count(),
+            prompts + 1
+        );
+        assert!(!output_str.contains("exit"));
+    }
+
+    #[test]
+    fn test_scrub_shared_store() {
+        let mut password_store = PasswordStore::in_memory("master".to_string());
+        password_store.load().unwrap();
+        let shared_store = Mutex::new(password_store);
+        {
+            // a running command holds the store for longer than the grace period
+            let _command = shared_store.lock().unwrap();
+            assert!(!scrub_shared_store(
+                &shared_store,
+                Duration::from_millis(20)
+            ));
+        }
+        assert!(scrub_shared_store(&shared_store, Duration::ZERO));
+        assert!(shared_store.lock().unwrap().load().is_err());
+    }
+
+    #[test]
+    fn test_prompts_leave_store_unlocked() {
+        /// Input that notes whether the store is locked whenever a line is read, i.e. at every
+        /// prompt, where a signal would have to wait for the lock to scrub the store.
+        struct ProbingInput {
+            shared_store: Arc<Mutex<PasswordStore>>,
+            lines: std::vec::IntoIter<&'static str>,
+            locked_at_prompt: bool,
+        }
+
+        impl std::io::Read for ProbingInput {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.locked_at_prompt |= self.shared_store.try_lock().is_err();
+                let line = self.lines.next().unwrap_or_default();
+                buf[..line.len()].copy_from_slice(line.as_bytes());
+                Ok(line.len())
+            }
+        }
+
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store = PasswordStore::create(temp_file, "secret".to_string()).unwrap();
+        password_store.load().unwrap();
+        let shared_store = Arc::new(Mutex::new(password_store));
+        let mut mock_prompt_password = MockPromptPassword::new();
+        let prompted_store = Arc::clone(&shared_store);
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(3)
+            .returning(move |_| {
+                assert!(prompted_store.try_lock().is_ok());
+                Ok("password".to_string())
+            });
+        let mut input = std::io::BufReader::new(ProbingInput {
+            shared_store: Arc::clone(&shared_store),
+            lines: vec![
+                "add\n",
+                "2\n",
+                "service\n",
+                "username\n",
+                "show\n",
+                "service\n",
+                "username\n",
+                "update\n",
+                "yes\n",
+                "remove\n",
+                "service\n",
+                "username\n",
+                "exit\n",
+            ]
+            .into_iter(),
+            locked_at_prompt: false,
+        });
+        let mut output = Vec::new();
+
+        run_commands(
+            &mut input,
+            &mut output,
+            &mock_prompt_password,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
+
+        assert!(!input.get_ref().locked_at_prompt);
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Password added successfully"));
+        assert!(output_str.contains("Master password updated successfully"));
+        assert!(output_str.contains("Password deleted"));
+        assert!(scrub_shared_store(&shared_store, Duration::ZERO));
+    }
+
     #[test]
     fn test_handle_add_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let shared_store =
+            Mutex::new(PasswordStore::create(temp_file, "secret".to_string()).unwrap());
         let mut input = b"1\ntest_service\ntest_username\n" as &[u8];
         let mut output = Vec::new();
         let mock_prompt_password = &MockPromptPassword::new();
@@ -373,7 +1177,9 @@ mod tests {
             &mut input,
             &mut output,
             mock_prompt_password,
-            &mut password_store,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
         );
 
         let output_str = String::from_utf8(output).unwrap();
@@ -390,7 +1196,7 @@ mod tests {
     fn test_handle_generate_password() {
         let mut output = Vec::new();
 
-        handle_generate_password(&mut output);
+        handle_generate_password(&mut output, ListFormat::Text, &Settings::default());
 
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Random password generated."));
@@ -399,7 +1205,7 @@ mod tests {
     #[test]
     fn test_handle_list_passwords() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, "secret".to_string()).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -408,14 +1214,22 @@ mod tests {
             &mut password_store,
             "service".to_string(),
             Some("username".to_string()),
-            Some("password".to_string()),
-            false,
-            PasswordGenerator::default(),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
         )
         .unwrap();
         let mut output = Vec::new();
 
-        handle_list_passwords(&mut output, &mut password_store);
+        let shared_store = Mutex::new(password_store);
+        handle_list_passwords(
+            &mut output,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
 
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("service"));
@@ -426,7 +1240,7 @@ mod tests {
     #[test]
     fn test_handle_remove_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, "secret".to_string()).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -435,21 +1249,36 @@ mod tests {
             &mut password_store,
             "service".to_string(),
             Some("username".to_string()),
-            Some("password".to_string()),
-            false,
-            PasswordGenerator::default(),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
         )
         .unwrap();
 
         let mut input = b"test_service\ntest_username\n" as &[u8];
         let mut output = Vec::new();
-        handle_remove_password(&mut input, &mut output, &mut password_store);
+        let shared_store = Mutex::new(password_store);
+        handle_remove_password(
+            &mut input,
+            &mut output,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Password not found"));
 
         input = b"service\nusername\n" as &[u8];
         output = Vec::new();
-        handle_remove_password(&mut input, &mut output, &mut password_store);
+        handle_remove_password(
+            &mut input,
+            &mut output,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Password deleted"));
     }
@@ -457,7 +1286,7 @@ mod tests {
     #[test]
     fn test_handle_show_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::create(temp_file, "secret".to_string()).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -466,39 +1295,234 @@ mod tests {
             &mut password_store,
             "service".to_string(),
             Some("username".to_string()),
-            Some("password".to_string()),
-            false,
-            PasswordGenerator::default(),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
         )
         .unwrap();
 
         let mut input = b"test_service\ntest_username\n" as &[u8];
         let mut output = Vec::new();
-        handle_show_password(&mut input, &mut output, &mut password_store);
+        let shared_store = Mutex::new(password_store);
+        handle_show_password(
+            &mut input,
+            &mut output,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Password not found"));
 
         input = b"service\nusername\n" as &[u8];
         output = Vec::new();
-        handle_show_password(&mut input, &mut output, &mut password_store);
+        handle_show_password(
+            &mut input,
+            &mut output,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("password"));
     }
 
-    #[test]
-    fn test_handle_update_master_password() {
+    #[rstest(
+        new_master,
+        input,
+        format,
+        allow_weak_master,
+        expected_output,
+        expect_updated,
+        case(
+            "C0rrect-H0rse-Battery!",
+            b"",
+            ListFormat::Text,
+            false,
+            "Master password updated successfully",
+            true
+        ),
+        case(
+            "newmasterpassword",
+            b"yes\n",
+            ListFormat::Text,
+            false,
+            "Master password updated successfully",
+            true
+        ),
+        case(
+            "newmasterpassword",
+            b"no\n",
+            ListFormat::Text,
+            false,
+            "Choose a stronger master password",
+            false
+        ),
+        case(
+            "newmasterpassword",
+            b"",
+            ListFormat::Ndjson,
+            false,
+            "Pass --allow-weak-master to use it anyway",
+            false
+        ),
+        case(
+            "newmasterpassword",
+            b"",
+            ListFormat::Ndjson,
+            true,
+            "\"ok\":true",
+            true
+        )
+    )]
+    fn test_handle_update_master_password(
+        new_master: &'static str,
+        input: &[u8],
+        format: ListFormat,
+        allow_weak_master: bool,
+        expected_output: &str,
+        expect_updated: bool,
+    ) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let shared_store =
+            Mutex::new(PasswordStore::create(temp_file.clone(), "secret".to_string()).unwrap());
         let mut writer = Vec::new();
         let mut mock_prompt_password = MockPromptPassword::new();
         mock_prompt_password
             .expect_prompt_password()
-            .returning(|_| Ok("newmasterpassword".to_string()));
-        handle_update_master_password(&mut writer, &mock_prompt_password, &mut password_store);
+            .returning(|_| Ok(new_master.to_string()));
+        handle_update_master_password(
+            &mut { input },
+            &mut writer,
+            &mock_prompt_password,
+            &shared_store,
+            format,
+            &Settings {
+                allow_weak_master,
+                ..Default::default()
+            },
+        );
         let output_str = String::from_utf8(writer).unwrap();
-        assert!(output_str.contains(&colorize(
-            "Master password updated successfully",
-            MessageType::Success
-        )));
+        assert!(output_str.contains(expected_output), "{output_str}");
+        let master = if expect_updated { new_master } else { "secret" };
+        assert!(PasswordStore::open(temp_file, master.to_string()).is_ok());
+    }
+
+    #[rstest(
+        input,
+        expect_created,
+        case(b"1\nyes\n" as &[u8], true),
+        case(b"1\nno\n" as &[u8], false)
+    )]
+    fn test_repl_weak_master_for_new_store(input: &[u8], expect_created: bool) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(2)
+            .returning(|_| Ok("secret".to_string()));
+        let mut output = Vec::new();
+        repl(
+            &mut { input },
+            &mut output,
+            &mock_prompt_password,
+            Some(file_path.to_str().unwrap().to_string()),
+            None,
+            ListFormat::Text,
+            &Settings::default(),
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("The master password is weak"));
+        assert_eq!(file_path.exists(), expect_created);
+    }
+
+    #[test]
+    fn test_repl_ndjson_weak_master_for_new_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(2)
+            .returning(|_| Ok("secret".to_string()));
+        let mut output = Vec::new();
+        repl(
+            &mut (b"" as &[u8]),
+            &mut output,
+            &mock_prompt_password,
+            Some(file_path.to_str().unwrap().to_string()),
+            Some(StoreOptions::default()),
+            ListFormat::Ndjson,
+            &Settings::default(),
+        );
+        let result: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(result["command"], "open");
+        assert_eq!(result["ok"], false);
+        assert!(result["error"]
+            .as_str()
+            .unwrap()
+            .contains("--allow-weak-master"));
+        assert!(!file_path.exists());
+    }
+
+    #[rstest(
+        input,
+        expected_output,
+        expect_removed,
+        case(b"service\nusername\n1\n" as &[u8], "Password deleted", true),
+        case(b"service\nusername\n2\n" as &[u8], "Cancelled", false)
+    )]
+    fn test_handle_remove_password_modified_externally(
+        input: &[u8],
+        expected_output: &str,
+        expect_removed: bool,
+    ) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), "secret".to_string()).unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "service".to_string(),
+            Some("username".to_string()),
+            AddOptions {
+                password: Some("password".to_string()),
+                ..Default::default()
+            },
+            &Settings::default(),
+        )
+        .unwrap();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&temp_file)
+            .unwrap();
+        file.set_modified(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let mut input = input;
+        let mut output = Vec::new();
+        let shared_store = Mutex::new(password_store);
+        handle_remove_password(
+            &mut input,
+            &mut output,
+            &shared_store,
+            ListFormat::Text,
+            &Settings::default(),
+        );
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("modified outside of this session"));
+        assert!(output_str.contains(expected_output));
+        let mut password_store = shared_store.into_inner().unwrap();
+        password_store.load().unwrap();
+        assert_eq!(
+            password_store
+                .find("service".to_string(), Some("username".to_string()))
+                .is_none(),
+            expect_removed
+        );
     }
 }
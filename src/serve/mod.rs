@@ -0,0 +1,434 @@
+//! A localhost-only HTTP endpoint that lets a browser extension look up credentials by domain.
+//! Every request must send the session token printed when the server starts, and only the
+//! service, username and password of matching entries are ever returned. Like `show`, every
+//! returned password is checked against `--max-reveals` and recorded in `--audit-log`.
+
+use crate::{
+    cli::{
+        commands::audit_reveals,
+        io::{print, MessageType},
+        settings::Settings,
+    },
+    crypto::get_random_bytes,
+    pass::{host, normalized_host, EntryFilter, EntryView, PasswordEntry},
+    store::PasswordStore,
+};
+use ring::constant_time::verify_slices_are_equal;
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+/// How long a client may take to send its request or read the response. Requests are handled one
+/// at a time, so a client that stalls must not keep the others waiting.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Generates the token that requests must send as `Authorization: Bearer <token>`.
+pub fn generate_token() -> String {
+    get_random_bytes(32)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Serves `GET /entries?domain=<domain>` on 127.0.0.1 until the process is stopped. `port` 0
+/// picks a free port. The address and the session token are printed to `writer`. The store is
+/// expected to be loaded already and is reloaded when it is changed on disk.
+pub fn serve<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    port: u16,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let token = generate_token();
+    print(
+        writer,
+        &format!("Listening on http://{}", listener.local_addr()?),
+        Some(MessageType::Success),
+    );
+    print(writer, &format!("Token: {token}"), Some(MessageType::Info));
+    writer.flush()?;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+                continue;
+            }
+        };
+        if password_store.is_modified_externally() {
+            if let Err(err) = password_store.load() {
+                print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            }
+        }
+        if let Err(err) = set_timeouts(&stream) {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            continue;
+        }
+        let mut reader = BufReader::new(&stream);
+        if let Err(err) =
+            handle_request(&mut reader, &mut &stream, password_store, &token, settings)
+        {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+        }
+    }
+    Ok(())
+}
+
+fn set_timeouts(stream: &TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(STREAM_TIMEOUT))?;
+    stream.set_write_timeout(Some(STREAM_TIMEOUT))
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    authorization: Option<String>,
+}
+
+/// Reads the request line and headers. The body is ignored and the whole request is limited to
+/// `MAX_REQUEST_SIZE` bytes.
+fn parse_request<R: BufRead>(reader: &mut R) -> std::io::Result<Option<Request>> {
+    let mut reader = reader.take(MAX_REQUEST_SIZE);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        authorization: None,
+    };
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            // the request was cut off before the end of the headers
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            return Ok(Some(request));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                request.authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+}
+
+/// Answers a single request read from `reader` with a JSON response written to `writer`.
+pub fn handle_request<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    password_store: &PasswordStore,
+    token: &str,
+    settings: &Settings,
+) -> std::io::Result<()> {
+    let (status, body) = match parse_request(reader)? {
+        Some(request) => respond(&request, password_store, token, settings),
+        None => (400, json!({ "error": "Bad request" })),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    let body = body.to_string();
+    write!(
+        writer,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    writer.flush()
+}
+
+fn respond(
+    request: &Request,
+    password_store: &PasswordStore,
+    token: &str,
+    settings: &Settings,
+) -> (u16, Value) {
+    let authorized = request
+        .authorization
+        .as_deref()
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .is_some_and(|given| verify_slices_are_equal(given.as_bytes(), token.as_bytes()).is_ok());
+    if !authorized {
+        return (401, json!({ "error": "Missing or invalid token" }));
+    }
+    if request.path != "/entries" {
+        return (404, json!({ "error": "Not found" }));
+    }
+    if request.method != "GET" {
+        return (405, json!({ "error": "Only GET is allowed" }));
+    }
+    let domain = request
+        .query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "domain")
+        .map(|(_, value)| host(&percent_decode(value)));
+    let Some(domain) = domain.filter(|domain| !domain.is_empty()) else {
+        return (
+            400,
+            json!({ "error": "The domain query parameter is required" }),
+        );
+    };
+    let entries: Vec<EntryView> = password_store
+        .entries(&EntryFilter::default())
+        .into_iter()
+        .filter(|password| matches_domain(password, &domain))
+        .map(|password| password.view(true))
+        .collect();
+    // nothing is returned beyond the reveal limit or without being recorded
+    if let Err(err) = audit_reveals("serve", &entries, settings) {
+        return (403, json!({ "error": err.to_string() }));
+    }
+    let entries: Vec<Value> = entries
+        .into_iter()
+        .map(|entry| {
+            json!({
+                "service": entry.service,
+                "username": entry.username,
+                "password": entry.password,
+            })
+        })
+        .collect();
+    (200, json!({ "entries": entries }))
+}
+
+/// An entry matches a domain when the host of its `url` metadata is the domain or one of its
+/// parent domains, so `github.com` matches requests for `github.com` and `gist.github.com`.
+/// Entries without a `url` match on their service instead, but only when it contains a dot: a
+/// service like `bank` or `com` is a name rather than a domain, and would match every domain
+/// that ends with it.
+fn matches_domain(password: &PasswordEntry, domain: &str) -> bool {
+    let host = match password.url_host() {
+        Some(host) => host,
+        None => normalized_host(&password.service),
+    };
+    host.contains('.') && (domain == host || domain.ends_with(&format!(".{host}")))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::commands::RevealLimit;
+    use rstest::rstest;
+    use std::{collections::HashMap, io::Cursor};
+    use tempfile::NamedTempFile;
+
+    const TOKEN: &str = "test_token";
+
+    fn password_store() -> (NamedTempFile, PasswordStore) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut password_store =
+            PasswordStore::create(temp_file.path().to_path_buf(), "master".to_string()).unwrap();
+        let entries = [
+            ("github.com", Some("octocat"), "hunter2", None),
+            ("https://example.com/login", None, "example", None),
+            ("gitlab.com", Some("octocat"), "gitlab", None),
+            ("bank", None, "bank", None),
+            ("com", None, "com", None),
+            (
+                "Work mail",
+                None,
+                "work",
+                Some("https://www.mail.example.org/login"),
+            ),
+        ];
+        for (service, username, password, url) in entries {
+            password_store
+                .load()
+                .unwrap()
+                .push(
+                    service.to_string(),
+                    username.map(String::from),
+                    password.to_string(),
+                    url.map(|url| HashMap::from([("url".to_string(), url.to_string())]))
+                        .unwrap_or_default(),
+                    false,
+                )
+                .unwrap()
+                .save()
+                .unwrap();
+        }
+        (temp_file, password_store)
+    }
+
+    fn request(request: &str, password_store: &PasswordStore) -> (String, Value) {
+        request_with_settings(request, password_store, &Settings::default())
+    }
+
+    fn request_with_settings(
+        request: &str,
+        password_store: &PasswordStore,
+        settings: &Settings,
+    ) -> (String, Value) {
+        let mut output = Vec::new();
+        handle_request(
+            &mut Cursor::new(request.as_bytes()),
+            &mut output,
+            password_store,
+            TOKEN,
+            settings,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let (head, body) = output.split_once("\r\n\r\n").unwrap();
+        let status_line = head.lines().next().unwrap().to_string();
+        (status_line, serde_json::from_str(body).unwrap())
+    }
+
+    #[rstest(
+        domain,
+        expected,
+        case("github.com", vec!["hunter2"]),
+        case("gist.github.com", vec!["hunter2"]),
+        case("https%3A%2F%2FWWW.GitHub.com%3A443%2Flogin", vec!["hunter2"]),
+        case("example.com", vec!["example"]),
+        case("notgithub.com", vec![]),
+        case("com", vec![]),
+        case("online.bank", vec![]),
+        case("mail.example.org", vec!["work"]),
+        case("inbox.mail.example.org", vec!["work"]),
+        case("example.org", vec![])
+    )]
+    fn test_handle_request(domain: &str, expected: Vec<&str>) {
+        let (_temp_file, password_store) = password_store();
+        let (status_line, body) = request(
+            &format!(
+                "GET /entries?domain={domain} HTTP/1.1\r\nHost: 127.0.0.1\r\nAuthorization: Bearer {TOKEN}\r\n\r\n"
+            ),
+            &password_store,
+        );
+        assert_eq!(status_line, "HTTP/1.1 200 OK");
+        let passwords: Vec<&str> = body["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["password"].as_str().unwrap())
+            .collect();
+        assert_eq!(passwords, expected);
+        assert!(!body.to_string().contains("master"));
+    }
+
+    #[rstest(
+        request_text,
+        expected_status,
+        case("GET /entries?domain=github.com HTTP/1.1\r\n\r\n", "401"),
+        case(
+            "GET /entries?domain=github.com HTTP/1.1\r\nAuthorization: Bearer wrong\r\n\r\n",
+            "401"
+        ),
+        case(
+            "GET /entries?domain=github.com HTTP/1.1\r\nAuthorization: test_token\r\n\r\n",
+            "401"
+        ),
+        case(
+            "GET /master HTTP/1.1\r\nAuthorization: Bearer test_token\r\n\r\n",
+            "404"
+        ),
+        case(
+            "POST /entries?domain=github.com HTTP/1.1\r\nAuthorization: Bearer test_token\r\n\r\n",
+            "405"
+        ),
+        case(
+            "GET /entries HTTP/1.1\r\nAuthorization: Bearer test_token\r\n\r\n",
+            "400"
+        ),
+        case(
+            "GET /entries?domain=github.com HTTP/1.1\r\nAuthorization: Bearer test_token\r\n",
+            "400"
+        ),
+        case("\r\n", "400")
+    )]
+    fn test_handle_request_rejected(request_text: &str, expected_status: &str) {
+        let (_temp_file, password_store) = password_store();
+        let (status_line, body) = request(request_text, &password_store);
+        assert!(status_line.starts_with(&format!("HTTP/1.1 {expected_status} ")));
+        assert!(body.get("entries").is_none());
+    }
+
+    #[test]
+    fn test_handle_request_reveal_limit() {
+        let (_temp_file, password_store) = password_store();
+        let settings = Settings {
+            reveal_limit: Some(RevealLimit::new(1, None).into()),
+            ..Default::default()
+        };
+        let lookup = |domain: &str| {
+            request_with_settings(
+                &format!(
+                    "GET /entries?domain={domain} HTTP/1.1\r\nAuthorization: Bearer {TOKEN}\r\n\r\n"
+                ),
+                &password_store,
+                &settings,
+            )
+        };
+        assert_eq!(lookup("github.com").0, "HTTP/1.1 200 OK");
+        let (status_line, body) = lookup("gitlab.com");
+        assert_eq!(status_line, "HTTP/1.1 403 Forbidden");
+        assert!(body.get("entries").is_none());
+        assert!(!body.to_string().contains("gitlab\""));
+        // the same entry again doesn't count
+        assert_eq!(lookup("gist.github.com").0, "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn test_set_timeouts() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        set_timeouts(&stream).unwrap();
+        assert_eq!(stream.read_timeout().unwrap(), Some(STREAM_TIMEOUT));
+        assert_eq!(stream.write_timeout().unwrap(), Some(STREAM_TIMEOUT));
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+    }
+}
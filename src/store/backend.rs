@@ -0,0 +1,106 @@
+//! Where an encrypted store is kept. `PasswordStore` only reads and writes whole encrypted
+//! stores through a `StoreBackend`, so that a store on another machine, e.g. over SFTP or in S3,
+//! can be supported by implementing the trait, without changing how stores are loaded and saved.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub trait StoreBackend: Send {
+    /// Reads the whole encrypted store.
+    fn read_all(&self) -> std::io::Result<Vec<u8>>;
+
+    /// Replaces the whole encrypted store with `contents`. An interrupted write should leave the
+    /// previous store intact.
+    fn write_all(&mut self, contents: &[u8]) -> std::io::Result<()>;
+
+    /// When the store was last written, to notice changes made by someone else. Backends that
+    /// can't tell return `None`.
+    fn modified(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// The path of the store on this machine, next to which backups of older formats are kept.
+    /// Remote backends have none.
+    fn file_path(&self) -> Option<&PathBuf> {
+        None
+    }
+}
+
+/// The default backend, a store file on the local filesystem.
+pub struct FileBackend {
+    file_path: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(file_path: PathBuf) -> Self {
+        Self { file_path }
+    }
+}
+
+impl StoreBackend for FileBackend {
+    fn read_all(&self) -> std::io::Result<Vec<u8>> {
+        fs::read(&self.file_path)
+    }
+
+    fn write_all(&mut self, contents: &[u8]) -> std::io::Result<()> {
+        write_atomically(&self.file_path, contents)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.file_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn file_path(&self) -> Option<&PathBuf> {
+        Some(&self.file_path)
+    }
+}
+
+/// Replaces `file_path` with `contents` by writing them to a temporary file in the same directory
+/// and renaming it over the original, so that an interrupted save leaves the previous store
+/// intact. The new file keeps the permissions of the one it replaces, and a symlinked store is
+/// replaced at its target.
+fn write_atomically(file_path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_path = fs::canonicalize(file_path).unwrap_or_else(|_| file_path.to_path_buf());
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = file_path.with_file_name(format!(".{file_name}.{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut file = fs::File::create(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(&file_path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &file_path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_backend() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let mut backend = FileBackend::new(file_path.clone());
+        assert!(backend.read_all().is_err());
+        assert_eq!(backend.modified(), None);
+
+        backend.write_all(b"first").unwrap();
+        backend.write_all(b"second").unwrap();
+        assert_eq!(backend.read_all().unwrap(), b"second");
+        assert!(backend.modified().is_some());
+        assert_eq!(backend.file_path(), Some(&file_path));
+        // the temporary file was renamed over the store
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+}
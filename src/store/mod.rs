@@ -1,78 +1,739 @@
-use crate::cli::io::{print, MessageType};
-use crate::pass::PasswordEntry;
+use crate::cli::io::MessageType;
+use crate::pass::{fuzzy_score, normalized_host, DedupReport, EntryFilter, PasswordEntry};
 use crate::{
-    crypto::{encrypt_contents, get_cipher, get_random_salt},
+    crypto::{derive_encryption_key, get_random_nonce, get_random_salt, scrub, Cipher},
     pass::Passwords,
 };
-use aes_gcm::aead::Aead;
+use ring::{constant_time, digest, hmac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
-const EMPTY_PASSWORDS: &str = "[]";
+mod backend;
+pub use backend::{FileBackend, StoreBackend};
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+/// The message signed with the encryption key to get its fingerprint.
+const FINGERPRINT_MESSAGE: &[u8] = b"lockbox key fingerprint";
+/// The message signed with the encryption key to get the key of the file MAC.
+const MAC_KEY_MESSAGE: &[u8] = b"lockbox file mac";
+/// The message signed with the encryption key to get the key check.
+const KEY_CHECK_MESSAGE: &[u8] = b"lockbox key check";
+const KEY_CHECK_LENGTH: usize = 8;
+const MAC_LENGTH: usize = 32;
+const DIGEST_LENGTH: usize = 32;
+/// Files shorter than this can't hold even a headerless version 0 store, which starts with the
+/// salt and the nonce, so they were cut short, e.g. by an interrupted save.
+const MIN_STORE_LENGTH: u64 = (SALT_LENGTH + NONCE_LENGTH) as u64;
+
+/// Stores from version 1 on start with `MAGIC` and a version byte, from version 2 on followed by
+/// the id of the cipher and from version 3 on by a byte of flags. Older stores have no header and
+/// are treated as version 0. Stores before version 2 are always encrypted with AES-256-GCM. From
+/// version 4 on the file ends with a MAC of everything before it, keyed with the encryption key,
+/// and a SHA-256 digest of everything before that. From version 5 on the nonce is followed by a
+/// key check and the cipher authenticates everything before the ciphertext.
+const MAGIC: &[u8; 4] = b"LKBX";
+const HEADER_LENGTH: usize = MAGIC.len() + 3;
+pub const CURRENT_VERSION: u8 = 5;
+
+/// Set in the flags of a store whose entries can be added but never removed or changed.
+const APPEND_ONLY_FLAG: u8 = 0b1;
+const KNOWN_FLAGS: u8 = APPEND_ONLY_FLAG;
+
+/// Upgrades the decrypted payload of the store version at each index to the next version.
+const MIGRATIONS: [fn(Value) -> Value; CURRENT_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+];
+
+/// Version 0 stored a bare list of passwords, version 1 wraps it in an object.
+fn migrate_v0_to_v1(payload: Value) -> Value {
+    json!({ "passwords": payload })
+}
+
+/// Version 2 only added the cipher to the header, the payload is unchanged.
+fn migrate_v1_to_v2(payload: Value) -> Value {
+    payload
+}
+
+/// Version 3 only added the flags to the header, the payload is unchanged.
+fn migrate_v2_to_v3(payload: Value) -> Value {
+    payload
+}
+
+/// Version 4 only added the MAC and the digest at the end of the file, the payload is unchanged.
+fn migrate_v3_to_v4(payload: Value) -> Value {
+    payload
+}
+
+/// Version 5 only added the key check and authenticated the header with the cipher, the payload
+/// is unchanged.
+fn migrate_v4_to_v5(payload: Value) -> Value {
+    payload
+}
+
+fn migrate(version: u8, payload: Value) -> Value {
+    MIGRATIONS[version as usize..]
+        .iter()
+        .fold(payload, |payload, migration| migration(payload))
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    passwords: Passwords,
+}
+
+/// The settings a store is created with. They are recorded in its header and kept by every save.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StoreOptions {
+    pub cipher: Cipher,
+    pub append_only: bool,
+}
+
+impl StoreOptions {
+    fn flags(&self) -> u8 {
+        if self.append_only {
+            APPEND_ONLY_FLAG
+        } else {
+            0
+        }
+    }
+}
+
+/// The key of the file MAC, derived from the encryption key so that it differs from the key that
+/// encrypts the passwords.
+fn mac_key(encryption_key: &[u8; 32]) -> hmac::Key {
+    let tag = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, encryption_key),
+        MAC_KEY_MESSAGE,
+    );
+    hmac::Key::new(hmac::HMAC_SHA256, tag.as_ref())
+}
+
+/// A short tag of the encryption key stored in the header, which tells a wrong master password
+/// apart from a modified file before the MAC is checked.
+fn key_check(encryption_key: &[u8; 32]) -> [u8; KEY_CHECK_LENGTH] {
+    let tag = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, encryption_key),
+        KEY_CHECK_MESSAGE,
+    );
+    tag.as_ref()[..KEY_CHECK_LENGTH]
+        .try_into()
+        .expect("the tag is longer than the key check")
+}
+
+/// The parts of an encrypted store file.
+struct EncryptedStore<'a> {
+    version: u8,
+    options: StoreOptions,
+    salt: &'a [u8],
+    nonce: &'a [u8],
+    key_check: Option<&'a [u8]>,
+    /// Everything before the ciphertext, which the cipher authenticates from version 5 on.
+    header: &'a [u8],
+    ciphertext: &'a [u8],
+    /// Everything the MAC is computed over, from the header to the end of the ciphertext.
+    authenticated: &'a [u8],
+    mac: Option<&'a [u8]>,
+}
+
+impl<'a> EncryptedStore<'a> {
+    fn parse(contents: &'a [u8]) -> Result<Self, StoreError> {
+        let version = match contents.strip_prefix(MAGIC) {
+            Some([version, ..]) => *version,
+            _ => 0,
+        };
+        if version > CURRENT_VERSION {
+            return Err(StoreError::UnsupportedVersion(version));
+        }
+        let (authenticated, mac) = match version {
+            ..=3 => (contents, None),
+            _ => {
+                let (authenticated, mac) = Self::split_trailer(contents)?;
+                (authenticated, Some(mac))
+            }
+        };
+        let rest = match authenticated.strip_prefix(MAGIC) {
+            Some([_, rest @ ..]) => rest,
+            _ => authenticated,
+        };
+        let (cipher, rest) = match rest {
+            _ if version < 2 => (Cipher::Aes256Gcm, rest),
+            [id, rest @ ..] => (
+                Cipher::from_id(*id).ok_or(StoreError::UnsupportedCipher(*id))?,
+                rest,
+            ),
+            [] => {
+                return Err(StoreError::CorruptFile(
+                    "the file has no cipher".to_string(),
+                ))
+            }
+        };
+        let (flags, rest) = match rest {
+            _ if version < 3 => (0, rest),
+            [flags, rest @ ..] if flags & !KNOWN_FLAGS == 0 => (*flags, rest),
+            [flags, ..] => {
+                return Err(StoreError::CorruptFile(format!(
+                    "the file has unknown flags {flags:#010b}"
+                )))
+            }
+            [] => return Err(StoreError::CorruptFile("the file has no flags".to_string())),
+        };
+        let key_check_length = if version < 5 { 0 } else { KEY_CHECK_LENGTH };
+        let header_length = SALT_LENGTH + NONCE_LENGTH + key_check_length;
+        if rest.len() < header_length {
+            return Err(StoreError::CorruptFile(
+                "the file is too short to contain a salt and nonce".to_string(),
+            ));
+        }
+        let ciphertext_start = authenticated.len() - rest.len() + header_length;
+        Ok(Self {
+            version,
+            options: StoreOptions {
+                cipher,
+                append_only: flags & APPEND_ONLY_FLAG != 0,
+            },
+            salt: &rest[..SALT_LENGTH],
+            nonce: &rest[SALT_LENGTH..SALT_LENGTH + NONCE_LENGTH],
+            key_check: (version >= 5).then(|| &rest[SALT_LENGTH + NONCE_LENGTH..header_length]),
+            header: if version < 5 {
+                &[]
+            } else {
+                &authenticated[..ciphertext_start]
+            },
+            ciphertext: &authenticated[ciphertext_start..],
+            authenticated,
+            mac,
+        })
+    }
+
+    /// Splits the MAC and the digest off the end of `contents` and checks the digest, which
+    /// catches a truncated or corrupted file before any key is derived. The MAC can only be
+    /// checked with the encryption key, see `check_mac`.
+    fn split_trailer(contents: &[u8]) -> Result<(&[u8], &[u8]), StoreError> {
+        if contents.len() < MAC_LENGTH + DIGEST_LENGTH {
+            return Err(StoreError::IntegrityCheckFailed);
+        }
+        let (signed, expected_digest) = contents.split_at(contents.len() - DIGEST_LENGTH);
+        if digest::digest(&digest::SHA256, signed).as_ref() != expected_digest {
+            return Err(StoreError::IntegrityCheckFailed);
+        }
+        Ok(signed.split_at(signed.len() - MAC_LENGTH))
+    }
+
+    /// Checks the MAC, which catches a file that was modified by someone who recomputed the
+    /// digest, e.g. to change the header. Stores before version 4 have no MAC.
+    fn check_mac(&self, encryption_key: &[u8; 32]) -> Result<(), StoreError> {
+        match self.mac {
+            Some(mac) => hmac::verify(&mac_key(encryption_key), self.authenticated, mac)
+                .map_err(|_| StoreError::IntegrityCheckFailed),
+            None => Ok(()),
+        }
+    }
+
+    /// Decrypts the ciphertext. From version 5 on the key check is compared first, so that only a
+    /// wrong master password is reported as such, and the MAC is checked before the cipher is
+    /// used. Older stores have no key check, so a ciphertext that fails to decrypt is taken to
+    /// mean a wrong master password.
+    fn decrypt(&self, encryption_key: &[u8; 32]) -> Result<Vec<u8>, StoreError> {
+        let cipher = self.options.cipher;
+        let Some(expected_key_check) = self.key_check else {
+            let plain_text = cipher
+                .decrypt(encryption_key, self.nonce, self.ciphertext, self.header)
+                .ok_or(StoreError::WrongMasterPassword)?;
+            self.check_mac(encryption_key)?;
+            return Ok(plain_text);
+        };
+        constant_time::verify_slices_are_equal(&key_check(encryption_key), expected_key_check)
+            .map_err(|_| StoreError::WrongMasterPassword)?;
+        self.check_mac(encryption_key)?;
+        cipher
+            .decrypt(encryption_key, self.nonce, self.ciphertext, self.header)
+            .ok_or(StoreError::IntegrityCheckFailed)
+    }
+}
+
+/// Derives a key from `master_password` and `salt` the same way opening a store does and returns
+/// how long the derivation took.
+pub fn time_key_derivation(master_password: &str, salt: &[u8]) -> Duration {
+    let start = Instant::now();
+    derive_encryption_key(master_password, salt);
+    start.elapsed()
+}
+
+/// Encrypts `payload` into the current store format with the cipher of `options` and a fresh
+/// nonce.
+fn encrypt_store(
+    payload: &str,
+    master_password: &str,
+    salt: &[u8],
+    options: StoreOptions,
+) -> Vec<u8> {
+    let enc_key = derive_encryption_key(master_password, salt);
+    let nonce = get_random_nonce();
+    let mut content = Vec::with_capacity(
+        HEADER_LENGTH
+            + SALT_LENGTH
+            + NONCE_LENGTH
+            + KEY_CHECK_LENGTH
+            + payload.len()
+            + MAC_LENGTH
+            + DIGEST_LENGTH,
+    );
+    content.extend(MAGIC);
+    content.push(CURRENT_VERSION);
+    content.push(options.cipher.id());
+    content.push(options.flags());
+    content.extend(salt);
+    content.extend(nonce);
+    content.extend(key_check(&enc_key));
+    let ciphertext = options
+        .cipher
+        .encrypt(&enc_key, &nonce, payload.as_bytes(), &content);
+    content.extend(ciphertext);
+    let mac = hmac::sign(&mac_key(&enc_key), &content);
+    content.extend(mac.as_ref());
+    let digest = digest::digest(&digest::SHA256, &content);
+    content.extend(digest.as_ref());
+    content
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    WrongMasterPassword,
+    NotFound(PathBuf),
+    AlreadyExists(PathBuf),
+    CorruptFile(String),
+    /// The store file and the most recent backup of it, if any.
+    Truncated(PathBuf, Option<PathBuf>),
+    UnsupportedVersion(u8),
+    UnsupportedCipher(u8),
+    AppendOnly,
+    IntegrityCheckFailed,
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongMasterPassword => write!(f, "Master password incorrect. Please try again."),
+            Self::NotFound(file_path) => write!(
+                f,
+                "The password store {} does not exist",
+                file_path.display()
+            ),
+            Self::AlreadyExists(file_path) => write!(
+                f,
+                "The password store {} already exists",
+                file_path.display()
+            ),
+            Self::CorruptFile(reason) => write!(f, "The password store is corrupt: {reason}"),
+            Self::Truncated(file_path, backup) => {
+                write!(
+                    f,
+                    "The password store {} is empty or truncated",
+                    file_path.display()
+                )?;
+                match backup {
+                    Some(backup) => write!(f, ", restore it from the backup {}", backup.display()),
+                    None => Ok(()),
+                }
+            }
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported password store version: {version}")
+            }
+            Self::UnsupportedCipher(id) => write!(f, "Unsupported password store cipher: {id}"),
+            Self::IntegrityCheckFailed => write!(
+                f,
+                "The password store failed its integrity check, it was truncated or modified"
+            ),
+            Self::AppendOnly => write!(
+                f,
+                "The password store is append-only, its entries can't be removed or changed"
+            ),
+            Self::Io(err) => write!(f, "Failed to access the password store: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Where the encrypted store is read from and written to. A backend store is kept by a
+/// `StoreBackend`, by default a file. A stream store is read once from a reader (e.g. stdin) and
+/// keeps the encrypted contents in memory until written out with `PasswordStore::write_to`. A
+/// memory store never leaves memory, for tests and dry runs.
+enum Storage {
+    Backend(Box<dyn StoreBackend>),
+    Stream { contents: Vec<u8>, written: bool },
+    Memory(Vec<u8>),
+}
 
 pub struct PasswordStore {
-    pub file_path: PathBuf,
+    storage: Storage,
     master_password: String,
     passwords: Option<Passwords>,
+    last_modified: Option<SystemTime>,
+    version: u8,
+    options: StoreOptions,
+    /// The entries as loaded from an append-only store, which every save must still start with.
+    loaded_passwords: Option<Passwords>,
+}
+
+/// The most recently modified non-empty backup of the store at `file_path`, e.g. the
+/// `store.v3.bak` left by an upgrade of the format or a `store.bak` made by hand.
+fn find_backup(file_path: &Path) -> Option<PathBuf> {
+    let file_name = file_path.file_name()?.to_str()?;
+    let dir = file_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let suffix = name.to_str().and_then(|name| name.strip_prefix(file_name));
+            suffix.is_some_and(|suffix| {
+                suffix == ".bak" || (suffix.starts_with(".v") && suffix.ends_with(".bak"))
+            })
+        })
+        .filter_map(|entry| {
+            let metadata = entry
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.len() > 0)?;
+            Some((metadata.modified().ok(), entry.path()))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+fn empty_store_contents(master_password: &str, options: StoreOptions) -> Vec<u8> {
+    let payload = json!({ "passwords": Passwords::new() }).to_string();
+    encrypt_store(&payload, master_password, &get_random_salt(), options)
 }
 
 impl PasswordStore {
-    pub fn new(file_path: PathBuf, master_password: String) -> anyhow::Result<Self> {
-        if !file_path.exists() || fs::metadata(&file_path)?.len() == 0 {
-            let salt = get_random_salt();
-            let (empty_json, nonce) = encrypt_contents(EMPTY_PASSWORDS, &master_password, &salt);
-            let mut content = salt.to_vec();
-            content.extend(nonce);
-            content.extend(empty_json);
-            fs::write(&file_path, content)?;
+    /// Whether a store was initialized at `file_path`. A missing or empty file is no store.
+    pub fn exists(file_path: &Path) -> bool {
+        fs::metadata(file_path).is_ok_and(|metadata| metadata.len() > 0)
+    }
+
+    /// Opens the existing store at `file_path` without loading it. A missing store is an error, and
+    /// so is a file too short to be a store, see `missing_store_error`.
+    pub fn open(file_path: PathBuf, master_password: String) -> Result<Self, StoreError> {
+        match fs::metadata(&file_path) {
+            Ok(metadata) if metadata.len() >= MIN_STORE_LENGTH => {
+                Ok(Self::from_file(file_path, master_password))
+            }
+            _ => Err(Self::missing_store_error(file_path)),
+        }
+    }
+
+    /// Why there is no store to open at `file_path`: the file does not exist, or it is empty or
+    /// truncated, in which case the error points to the most recent backup next to it.
+    pub fn missing_store_error(file_path: PathBuf) -> StoreError {
+        if fs::metadata(&file_path).is_ok() {
+            let backup = find_backup(&file_path);
+            StoreError::Truncated(file_path, backup)
+        } else {
+            StoreError::NotFound(file_path)
+        }
+    }
+
+    /// Initializes an empty store at `file_path`, which must not hold a store yet.
+    pub fn create(file_path: PathBuf, master_password: String) -> Result<Self, StoreError> {
+        Self::create_with_options(file_path, master_password, StoreOptions::default())
+    }
+
+    /// Like `create`, but records `options` in the new store, e.g. the cipher it is encrypted
+    /// with.
+    pub fn create_with_options(
+        file_path: PathBuf,
+        master_password: String,
+        options: StoreOptions,
+    ) -> Result<Self, StoreError> {
+        if Self::exists(&file_path) {
+            return Err(StoreError::AlreadyExists(file_path));
+        }
+        let mut backend = FileBackend::new(file_path);
+        backend.write_all(&empty_store_contents(&master_password, options))?;
+        let mut store = Self::with_backend(Box::new(backend), master_password);
+        store.options = options;
+        Ok(store)
+    }
+
+    fn from_file(file_path: PathBuf, master_password: String) -> Self {
+        Self::with_backend(Box::new(FileBackend::new(file_path)), master_password)
+    }
+
+    /// Opens the store kept by `backend` without loading it, e.g. a store on a remote server.
+    pub fn with_backend(backend: Box<dyn StoreBackend>, master_password: String) -> Self {
+        Self {
+            storage: Storage::Backend(backend),
+            master_password,
+            passwords: None,
+            last_modified: None,
+            version: CURRENT_VERSION,
+            options: StoreOptions::default(),
+            loaded_passwords: None,
+        }
+    }
+
+    /// Reads the whole encrypted store from `reader`. An empty input starts a new store with
+    /// `options`.
+    pub fn from_reader<R: Read>(
+        reader: &mut R,
+        master_password: String,
+        options: StoreOptions,
+    ) -> Result<Self, StoreError> {
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        if contents.is_empty() {
+            contents = empty_store_contents(&master_password, options);
         }
         let store = Self {
-            file_path,
+            storage: Storage::Stream {
+                contents,
+                written: false,
+            },
             master_password,
             passwords: None,
+            last_modified: None,
+            version: CURRENT_VERSION,
+            options,
+            loaded_passwords: None,
         };
         Ok(store)
     }
 
-    pub fn load(&mut self) -> anyhow::Result<&mut Self> {
-        let encrypted_file = std::fs::read(&self.file_path)?;
-        let salt = &encrypted_file[..16];
-        let cipher = get_cipher(&self.master_password, salt);
-        let nonce = &encrypted_file[16..28];
-        let encrypted_data = &encrypted_file[28..];
-        let plain_text = cipher
-            .decrypt(nonce.into(), encrypted_data.as_ref())
-            .map_err(|_| anyhow::anyhow!("Master password incorrect. Please try again."))?;
-        let plain_text_str = String::from_utf8(plain_text)?;
-        let parsed_passwords = Passwords::parse_passwords(&plain_text_str)?;
-        self.passwords = Some(parsed_passwords);
+    /// Starts a new store that is only kept in memory. Saving it updates the encrypted contents
+    /// in memory and nothing is ever written to disk or to a writer.
+    pub fn in_memory(master_password: String) -> Self {
+        Self {
+            storage: Storage::Memory(empty_store_contents(
+                &master_password,
+                StoreOptions::default(),
+            )),
+            master_password,
+            passwords: None,
+            last_modified: None,
+            version: CURRENT_VERSION,
+            options: StoreOptions::default(),
+            loaded_passwords: None,
+        }
+    }
+
+    pub fn file_path(&self) -> Option<&PathBuf> {
+        match &self.storage {
+            Storage::Backend(backend) => backend.file_path(),
+            Storage::Stream { .. } | Storage::Memory(_) => None,
+        }
+    }
+
+    pub fn is_stream(&self) -> bool {
+        matches!(self.storage, Storage::Stream { .. })
+    }
+
+    fn read_contents(&self) -> Result<Vec<u8>, StoreError> {
+        match &self.storage {
+            Storage::Backend(backend) => Ok(backend.read_all()?),
+            Storage::Stream { contents, .. } | Storage::Memory(contents) => Ok(contents.clone()),
+        }
+    }
+
+    fn write_contents(&mut self, new_contents: Vec<u8>) -> Result<(), StoreError> {
+        match &mut self.storage {
+            Storage::Backend(backend) => backend.write_all(&new_contents)?,
+            Storage::Stream { contents, written } => {
+                *contents = new_contents;
+                *written = true;
+            }
+            Storage::Memory(contents) => *contents = new_contents,
+        }
+        Ok(())
+    }
+
+    /// Writes the encrypted contents of a stream store to `writer` if they were changed with
+    /// `save`. This is a no-op for file stores, which `save` writes itself, and memory stores.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match &self.storage {
+            Storage::Stream {
+                contents,
+                written: true,
+            } => {
+                writer.write_all(contents)?;
+                writer.flush()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The format version of the store as it was last read or written. Older stores are
+    /// migrated when loaded and saved in the current format by the next `save`.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The cipher of the store as it was last read or written. Saving keeps the cipher.
+    pub fn cipher(&self) -> Cipher {
+        self.options.cipher
+    }
+
+    /// Whether the store was created with `--append-only`. Entries of such a store can't be
+    /// removed or changed, only superseded by adding a newer entry for the same service and
+    /// username.
+    pub fn is_append_only(&self) -> bool {
+        self.options.append_only
+    }
+
+    /// Fails for an append-only store, before a command removes or changes any entries.
+    pub fn check_mutable(&self) -> Result<(), StoreError> {
+        if self.is_append_only() {
+            return Err(StoreError::AppendOnly);
+        }
+        Ok(())
+    }
+
+    pub fn load(&mut self) -> Result<&mut Self, StoreError> {
+        let contents = self.read_contents()?;
+        let encrypted_store = EncryptedStore::parse(&contents)?;
+        let enc_key = derive_encryption_key(&self.master_password, encrypted_store.salt);
+        self.decrypt(&encrypted_store, &enc_key)
+    }
+
+    /// Loads the store with a raw encryption key instead of the master password, e.g. one
+    /// rebuilt from recovery shares. Saving afterwards encrypts with the master password.
+    pub fn recover(&mut self, encryption_key: &[u8; 32]) -> Result<&mut Self, StoreError> {
+        let contents = self.read_contents()?;
+        let encrypted_store = EncryptedStore::parse(&contents)?;
+        self.decrypt(&encrypted_store, encryption_key)
+    }
+
+    /// The key derived from the master password and the salt of the store.
+    pub fn encryption_key(&self) -> Result<[u8; 32], StoreError> {
+        let contents = self.read_contents()?;
+        let encrypted_store = EncryptedStore::parse(&contents)?;
+        Ok(derive_encryption_key(
+            &self.master_password,
+            encrypted_store.salt,
+        ))
+    }
+
+    /// A short fingerprint of the encryption key, e.g. `3f2a 91c0 5be7 0d44`. Copies of a store
+    /// opened with the same master password have the same fingerprint. It is an HMAC of a constant
+    /// message, so it reveals nothing about the key or the master password. Since the key also
+    /// depends on the salt, separately created stores differ even with the same master password.
+    pub fn key_fingerprint(&self) -> Result<String, StoreError> {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.encryption_key()?);
+        let tag = hmac::sign(&key, FINGERPRINT_MESSAGE);
+        let groups: Vec<String> = tag.as_ref()[..8]
+            .chunks(2)
+            .map(|group| format!("{:02x}{:02x}", group[0], group[1]))
+            .collect();
+        Ok(groups.join(" "))
+    }
+
+    fn decrypt(
+        &mut self,
+        encrypted_store: &EncryptedStore,
+        encryption_key: &[u8; 32],
+    ) -> Result<&mut Self, StoreError> {
+        let plain_text = encrypted_store.decrypt(encryption_key)?;
+        let payload: Value = serde_json::from_slice(&plain_text)
+            .map_err(|err| StoreError::CorruptFile(err.to_string()))?;
+        let payload: Payload = serde_json::from_value(migrate(encrypted_store.version, payload))
+            .map_err(|err| StoreError::CorruptFile(err.to_string()))?;
+        self.options = encrypted_store.options;
+        self.loaded_passwords = self.is_append_only().then(|| payload.passwords.clone());
+        self.passwords = Some(payload.passwords);
+        self.version = encrypted_store.version;
+        self.last_modified = self.get_modified();
         Ok(self)
     }
 
-    pub fn dump(&mut self) -> anyhow::Result<&mut Self> {
-        let encrypted_file = std::fs::read(&self.file_path)?;
-        let salt = &encrypted_file[..16];
-        let cipher = get_cipher(&self.master_password, salt);
-        let nonce = &encrypted_file[16..28];
-        let plain_text = serde_json::to_string(&self.passwords)?;
-        let encrypted_text = cipher
-            .encrypt(nonce.into(), plain_text.as_ref())
-            .map_err(|e| anyhow::anyhow!("Failed to encrypt passwords: {:?}", e))?;
-        let mut content = salt.to_vec();
-        content.extend(nonce);
-        content.extend(encrypted_text);
-        std::fs::write(&self.file_path, content)?;
+    /// Backs up a file store in an older format next to it before it is first overwritten in
+    /// the current format, e.g. `store` to `store.v0.bak`.
+    fn backup_old_version(&self, contents: &[u8]) -> Result<(), StoreError> {
+        if let (Some(file_path), true) = (self.file_path(), self.version < CURRENT_VERSION) {
+            let mut backup_path = file_path.clone().into_os_string();
+            backup_path.push(format!(".v{}.bak", self.version));
+            fs::write(backup_path, contents)?;
+        }
+        Ok(())
+    }
+
+    /// Encrypts the loaded passwords and writes them back to the store.
+    /// An append-only store is only saved when all the entries it was loaded with are kept
+    /// unchanged in front of the new ones.
+    pub fn save(&mut self) -> Result<&mut Self, StoreError> {
+        if let (Some(loaded), Some(passwords)) = (&self.loaded_passwords, &self.passwords) {
+            if !passwords.starts_with(loaded) {
+                return Err(StoreError::AppendOnly);
+            }
+        }
+        let contents = self.read_contents()?;
+        let salt = EncryptedStore::parse(&contents)?.salt;
+        let payload = json!({ "passwords": self.passwords }).to_string();
+        let new_contents = encrypt_store(&payload, &self.master_password, salt, self.options);
+        self.backup_old_version(&contents)?;
+        self.write_contents(new_contents)?;
+        if self.is_append_only() {
+            self.loaded_passwords = self.passwords.clone();
+        }
+        self.version = CURRENT_VERSION;
+        self.last_modified = self.get_modified();
         Ok(self)
     }
 
+    fn get_modified(&self) -> Option<SystemTime> {
+        match &self.storage {
+            Storage::Backend(backend) => backend.modified(),
+            Storage::Stream { .. } | Storage::Memory(_) => None,
+        }
+    }
+
+    /// Whether the store file was modified by someone else since it was last loaded or saved.
+    pub fn is_modified_externally(&self) -> bool {
+        self.last_modified.is_some() && self.get_modified() != self.last_modified
+    }
+
     pub fn push(
         &mut self,
         service: String,
         username: Option<String>,
         password: String,
+        metadata: HashMap<String, String>,
+        favorite: bool,
     ) -> anyhow::Result<&mut Self> {
-        let new_password = PasswordEntry::new(service, username, password);
+        let new_password = PasswordEntry::new(service, username, password)
+            .with_metadata(metadata)
+            .with_favorite(favorite);
+        self.append(new_password)
+    }
+
+    pub fn append(&mut self, new_password: PasswordEntry) -> anyhow::Result<&mut Self> {
         if let Some(ref mut passwords) = self.passwords {
             passwords.append(new_password);
         } else {
@@ -81,51 +742,289 @@ impl PasswordStore {
         Ok(self)
     }
 
-    pub fn pop<W: Write>(
-        &mut self,
-        writer: &mut W,
-        service: String,
-        username: Option<String>,
-    ) -> &mut Self {
-        if let Some(_password) = self
-            .passwords
+    /// Removes the entry for `service` and `username` without saving, see `Passwords::remove`.
+    pub fn remove(&mut self, service: String, username: Option<String>) -> Option<PasswordEntry> {
+        self.passwords
             .as_mut()
             .and_then(|passwords| passwords.remove(service, username))
-        {
-            print(writer, "Password deleted", Some(MessageType::Success));
+    }
+
+    /// Explains a failed lookup. When no username was given but the service only has entries
+    /// with usernames, those usernames are listed.
+    pub fn not_found_message(&self, service: &str, username: Option<&str>) -> String {
+        let usernames = self
+            .passwords
+            .as_ref()
+            .map(|passwords| passwords.usernames(service))
+            .unwrap_or_default();
+        if username.is_none_or(|username| username.trim().is_empty()) && !usernames.is_empty() {
+            format!(
+                "Password not found. {service} has no entry without a username, specify one of: {}",
+                usernames.join(", ")
+            )
         } else {
-            print(writer, "Password not found", Some(MessageType::Warning));
+            "Password not found".to_string()
         }
-        self
     }
 
+    /// Fails when several entries match `service` and `username`, listing their indices so that
+    /// one can be picked with `--index`. Lookups by name never pick one of them arbitrarily. In an
+    /// append-only store the newest entry supersedes the older ones, so lookups are never
+    /// ambiguous.
+    pub fn check_unambiguous(&self, service: &str, username: Option<&str>) -> anyhow::Result<()> {
+        if self.is_append_only() {
+            return Ok(());
+        }
+        let indices: Vec<String> = self
+            .passwords
+            .as_ref()
+            .map(|passwords| passwords.find_all(service, username))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(index, _)| index.to_string())
+            .collect();
+        if indices.len() > 1 {
+            let entry = match username.filter(|username| !username.trim().is_empty()) {
+                Some(username) => format!("{service} ({username})"),
+                None => format!("{service} without a username"),
+            };
+            return Err(anyhow::anyhow!(
+                "{entry} matches {} entries, pick one with --index: {}",
+                indices.len(),
+                indices.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// The entry for `service` and `username`. In an append-only store that is the newest one.
     pub fn find(&self, service: String, username: Option<String>) -> Option<&PasswordEntry> {
+        self.passwords.as_ref().and_then(|passwords| {
+            if self.is_append_only() {
+                passwords.find_latest(&service, username.as_deref())
+            } else {
+                passwords.find(service, username)
+            }
+        })
+    }
+
+    pub fn find_ignoring_case(
+        &self,
+        service: &str,
+        username: Option<&str>,
+    ) -> Option<&PasswordEntry> {
+        self.passwords
+            .as_ref()
+            .and_then(|passwords| passwords.find_ignoring_case(service, username))
+    }
+
+    pub fn entries(&self, filter: &EntryFilter) -> Vec<&PasswordEntry> {
+        self.passwords
+            .as_ref()
+            .map(|passwords| passwords.entries(filter))
+            .unwrap_or_default()
+    }
+
+    pub fn indexed_entries(&self, filter: &EntryFilter) -> Vec<(usize, &PasswordEntry)> {
+        self.passwords
+            .as_ref()
+            .map(|passwords| passwords.indexed_entries(filter))
+            .unwrap_or_default()
+    }
+
+    /// The entry whose `url` metadata has the host of `url`, see `Passwords::find_all_by_url`.
+    /// Like `check_unambiguous`, fails when several entries match, listing their indices.
+    pub fn find_by_url(&self, url: &str) -> anyhow::Result<Option<&PasswordEntry>> {
+        let matches = self
+            .passwords
+            .as_ref()
+            .map(|passwords| passwords.find_all_by_url(url))
+            .unwrap_or_default();
+        if matches.len() > 1 && !self.is_append_only() {
+            let indices: Vec<String> = matches.iter().map(|(index, _)| index.to_string()).collect();
+            return Err(anyhow::anyhow!(
+                "{} matches {} entries, pick one with --index: {}",
+                normalized_host(url),
+                matches.len(),
+                indices.join(", ")
+            ));
+        }
+        Ok(matches.last().map(|(_, pwd)| *pwd))
+    }
+
+    pub fn find_by_index(&self, index: usize) -> Option<&PasswordEntry> {
+        self.passwords
+            .as_ref()
+            .and_then(|passwords| passwords.find_by_index(index))
+    }
+
+    pub fn remove_by_index(&mut self, index: usize) -> Option<PasswordEntry> {
+        self.passwords
+            .as_mut()
+            .and_then(|passwords| passwords.remove_by_index(index))
+    }
+
+    pub fn set_password(
+        &mut self,
+        service: &str,
+        username: Option<&str>,
+        password: String,
+    ) -> bool {
+        self.passwords
+            .as_mut()
+            .is_some_and(|passwords| passwords.set_password(service, username, password))
+    }
+
+    pub fn set_disabled(&mut self, service: &str, username: Option<&str>, disabled: bool) -> bool {
+        self.passwords
+            .as_mut()
+            .is_some_and(|passwords| passwords.set_disabled(service, username, disabled))
+    }
+
+    pub fn set_disabled_by_index(&mut self, index: usize, disabled: bool) -> bool {
+        self.passwords
+            .as_mut()
+            .is_some_and(|passwords| passwords.set_disabled_by_index(index, disabled))
+    }
+
+    pub fn empty_message(&self) -> &'static str {
         self.passwords
             .as_ref()
-            .and_then(|passwords| passwords.find(service, username))
+            .map_or("No passwords stored.", Passwords::empty_message)
     }
 
     pub fn print<W: Write>(
         &self,
         writer: &mut W,
         show_passwords: bool,
+        filter: &EntryFilter,
+        limit: Option<usize>,
+        show_index: bool,
         message_type: Option<MessageType>,
     ) {
         if let Some(passwords) = self.passwords.as_ref() {
-            passwords.print_all(writer, show_passwords, message_type)
+            passwords.print_all(
+                writer,
+                show_passwords,
+                filter,
+                limit,
+                show_index,
+                message_type,
+            )
+        }
+    }
+
+    pub fn dedup(&mut self) -> DedupReport {
+        self.passwords
+            .as_mut()
+            .map(|passwords| passwords.dedup())
+            .unwrap_or_default()
+    }
+
+    /// The entries last updated more than `age` ago, see `Passwords::older_than`.
+    pub fn older_than(&self, age: Duration) -> Vec<&PasswordEntry> {
+        self.passwords
+            .as_ref()
+            .map(|passwords| passwords.older_than(age))
+            .unwrap_or_default()
+    }
+
+    pub fn remove_older_than(&mut self, age: Duration) -> usize {
+        self.passwords
+            .as_mut()
+            .map(|passwords| passwords.remove_older_than(age))
+            .unwrap_or_default()
+    }
+
+    /// See `Passwords::rename_collisions`.
+    pub fn rename_collisions(&self, from: &str, to: &str) -> Vec<String> {
+        self.passwords
+            .as_ref()
+            .map(|passwords| passwords.rename_collisions(from, to))
+            .unwrap_or_default()
+    }
+
+    /// Renames every entry of `from` to `to`, see `Passwords::rename_service`.
+    pub fn rename_service(&mut self, from: &str, to: &str) -> usize {
+        self.passwords
+            .as_mut()
+            .map(|passwords| passwords.rename_service(from, to))
+            .unwrap_or_default()
+    }
+
+    /// Removes the expired entries, see `Passwords::gc`.
+    pub fn gc(&mut self) -> usize {
+        self.passwords
+            .as_mut()
+            .map(|passwords| passwords.gc())
+            .unwrap_or_default()
+    }
+
+    pub fn services(&self) -> BTreeSet<String> {
+        self.passwords
+            .as_ref()
+            .map(|passwords| passwords.services())
+            .unwrap_or_default()
+    }
+
+    /// The services that best fuzzy-match `pattern`, see `fuzzy_score`. A service equal to the
+    /// pattern (ignoring case) wins outright. Matches scoring less than 2 per pattern character
+    /// are dropped, so the result is empty when nothing matches closely enough and holds more than
+    /// one service when several match equally well.
+    pub fn fuzzy_services(&self, pattern: &str) -> Vec<String> {
+        let services = self.services();
+        let pattern_lowercase = pattern.to_lowercase();
+        if let Some(service) = services
+            .iter()
+            .find(|service| service.to_lowercase() == pattern_lowercase)
+        {
+            return vec![service.clone()];
+        }
+        let min_score = (2 * pattern.chars().count()).max(1);
+        let scored: Vec<(usize, String)> = services
+            .into_iter()
+            .filter_map(|service| fuzzy_score(pattern, &service).map(|score| (score, service)))
+            .filter(|(score, _)| *score >= min_score)
+            .collect();
+        let best_score = scored.iter().map(|(score, _)| *score).max();
+        scored
+            .into_iter()
+            .filter(|(score, _)| Some(*score) == best_score)
+            .map(|(_, service)| service)
+            .collect()
+    }
+
+    /// Overwrites the master password and the decrypted entries, which have to be loaded again
+    /// afterwards. Also done when the store is dropped.
+    pub fn scrub(&mut self) {
+        scrub(&mut self.master_password);
+        for passwords in [&mut self.passwords, &mut self.loaded_passwords] {
+            if let Some(mut passwords) = passwords.take() {
+                passwords.scrub();
+            }
         }
     }
 
     pub fn update_master(&mut self, new_master_password: String) -> &mut Self {
+        scrub(&mut self.master_password);
         self.master_password = new_master_password;
         self
     }
 }
 
+impl Drop for PasswordStore {
+    fn drop(&mut self) {
+        self.scrub();
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::cli::{commands::add_password, io::MockPromptPassword};
-    use passwords::PasswordGenerator;
+    use crate::cli::{
+        commands::{add_password, AddOptions},
+        io::MockPromptPassword,
+        settings::Settings,
+    };
     use rstest::rstest;
     use tempfile::NamedTempFile;
 
@@ -133,27 +1032,155 @@ mod tests {
     const TEST_MASTER_PASSWORD: &str = "test_master";
 
     #[test]
-    fn test_new_password_store() {
+    fn test_create_password_store() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let store =
-            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
-        assert_eq!(store.file_path, temp_file);
+            PasswordStore::create(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert_eq!(store.file_path(), Some(&temp_file));
         assert_eq!(store.master_password, TEST_MASTER_PASSWORD);
         assert!(store.passwords.is_none());
         assert!(temp_file.exists());
     }
 
+    /// A backend keeping the encrypted store in memory shared between store instances, like a
+    /// remote store seen from two machines.
+    struct SharedBackend(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl StoreBackend for SharedBackend {
+        fn read_all(&self) -> std::io::Result<Vec<u8>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn write_all(&mut self, contents: &[u8]) -> std::io::Result<()> {
+            *self.0.lock().unwrap() = contents.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_backend() {
+        let contents = std::sync::Arc::new(std::sync::Mutex::new(empty_store_contents(
+            TEST_MASTER_PASSWORD,
+            StoreOptions::default(),
+        )));
+        let mut store = PasswordStore::with_backend(
+            Box::new(SharedBackend(contents.clone())),
+            TEST_MASTER_PASSWORD.to_string(),
+        );
+        assert_eq!(store.file_path(), None);
+        assert!(!store.is_stream());
+        store
+            .load()
+            .unwrap()
+            .append(PasswordEntry::new(
+                "github".to_string(),
+                None,
+                "password1".to_string(),
+            ))
+            .unwrap()
+            .save()
+            .unwrap();
+        assert!(!store.is_modified_externally());
+
+        let mut reopened = PasswordStore::with_backend(
+            Box::new(SharedBackend(contents)),
+            TEST_MASTER_PASSWORD.to_string(),
+        );
+        let entry = reopened.load().unwrap().find("github".to_string(), None);
+        assert_eq!(
+            entry.and_then(|entry| entry.field("password")),
+            Some("password1")
+        );
+    }
+
     #[test]
-    fn test_new_password_store_with_nonexistent_file() {
+    fn test_scrub() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store = PasswordStore::create(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        store
+            .load()
+            .unwrap()
+            .append(PasswordEntry::new(
+                "github".to_string(),
+                None,
+                "password1".to_string(),
+            ))
+            .unwrap();
+        store.scrub();
+        assert!(store.master_password.is_empty());
+        assert!(store.passwords.is_none());
+        assert!(matches!(
+            store.load().err(),
+            Some(StoreError::WrongMasterPassword)
+        ));
+    }
+
+    #[test]
+    fn test_create_password_store_with_nonexistent_file() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let store =
-            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
-        assert_eq!(store.file_path, temp_file);
+            PasswordStore::create(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert_eq!(store.file_path(), Some(&temp_file));
         assert_eq!(store.master_password, TEST_MASTER_PASSWORD);
         assert!(store.passwords.is_none());
         assert!(PathBuf::from(temp_file.to_str().unwrap()).exists());
     }
 
+    #[test]
+    fn test_open_truncated_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let open = || {
+            PasswordStore::open(temp_file.clone(), TEST_MASTER_PASSWORD.to_string())
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        fs::write(&temp_file, b"").unwrap();
+        assert_eq!(
+            open(),
+            format!(
+                "The password store {} is empty or truncated",
+                temp_file.display()
+            )
+        );
+        // a header without the salt and nonce is as good as empty
+        fs::write(&temp_file, b"LKBX\x04").unwrap();
+        assert!(open().ends_with("is empty or truncated"));
+
+        let backup = temp_dir.path().join("store.v3.bak");
+        fs::write(&backup, b"backup").unwrap();
+        fs::write(temp_dir.path().join("store.bak"), b"").unwrap();
+        fs::write(temp_dir.path().join("other.bak"), b"other").unwrap();
+        assert!(open().ends_with(&format!(
+            ", restore it from the backup {}",
+            backup.display()
+        )));
+    }
+
+    #[test]
+    fn test_open_and_create_existing_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let err = PasswordStore::open(temp_file.clone(), TEST_MASTER_PASSWORD.to_string())
+            .err()
+            .unwrap();
+        assert!(matches!(err, StoreError::NotFound(_)));
+
+        PasswordStore::create(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let err = PasswordStore::create(temp_file.clone(), TEST_MASTER_PASSWORD.to_string())
+            .err()
+            .unwrap();
+        assert!(matches!(err, StoreError::AlreadyExists(_)));
+
+        let mut store = PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert!(store
+            .load()
+            .unwrap()
+            .entries(&EntryFilter::default())
+            .is_empty());
+    }
+
     #[rstest]
     #[case(Vec::new())]
     #[case(
@@ -181,7 +1208,7 @@ mod tests {
     )]
     fn test_load_after_store_passwords(#[case] test_passwords: Vec<PasswordEntry>) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut store = PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let mut store = PasswordStore::create(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
         store.load().unwrap();
         test_passwords.iter().for_each(|test_password| {
             store
@@ -267,7 +1294,7 @@ mod tests {
         expect_password_found: bool,
     ) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut store = PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let mut store = PasswordStore::create(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
         store.load().unwrap();
         store.passwords = Some(test_passwords.into());
         let found_password = store.find(service.to_string(), username.map(|u| u.to_string()));
@@ -309,7 +1336,7 @@ mod tests {
     ) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let mut password_store =
-            PasswordStore::new(temp_file, "master_password".to_string()).unwrap();
+            PasswordStore::create(temp_file, "master_password".to_string()).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         passwords
@@ -321,16 +1348,25 @@ mod tests {
                     &mut password_store,
                     service.to_string(),
                     username.map(|u| u.to_string()),
-                    Some(password.to_string()),
-                    false,
-                    PasswordGenerator::default(),
+                    AddOptions {
+                        password: Some(password.to_string()),
+                        ..Default::default()
+                    },
+                    &Settings::default(),
                 )
                 .unwrap()
             });
 
         let mut output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
-        password_store.print(&mut writer, show_passwords, Some(MessageType::Info));
+        password_store.print(
+            &mut writer,
+            show_passwords,
+            &EntryFilter::default(),
+            None,
+            false,
+            Some(MessageType::Info),
+        );
 
         output = writer.into_inner();
         let output_str = String::from_utf8(output).unwrap();
@@ -343,13 +1379,519 @@ mod tests {
     fn test_update_master() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let mut password_store =
-            PasswordStore::new(temp_file, "some_master_password".to_string()).unwrap();
+            PasswordStore::create(temp_file, "some_master_password".to_string()).unwrap();
         password_store.update_master("new_master_password".to_string());
         assert!(password_store.master_password == "new_master_password");
         assert!(password_store.load().is_err());
         if let Err(err) = password_store.load() {
-            err.to_string()
-                .contains("Master password incorrect. Please try again.");
+            assert!(matches!(err, StoreError::WrongMasterPassword));
+            assert!(err
+                .to_string()
+                .contains("Master password incorrect. Please try again."));
+        };
+    }
+
+    #[rstest]
+    #[case(b"too short".to_vec())]
+    #[case([0u8; 64].to_vec())]
+    fn test_load_invalid_file(#[case] content: Vec<u8>) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        fs::write(&temp_file, content.clone()).unwrap();
+        let password_store = PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string());
+        if content.len() < SALT_LENGTH + NONCE_LENGTH {
+            assert!(matches!(
+                password_store.err(),
+                Some(StoreError::Truncated(..))
+            ));
+        } else {
+            let err = password_store.unwrap().load().err().unwrap();
+            assert!(matches!(err, StoreError::WrongMasterPassword));
+        }
+    }
+
+    #[test]
+    fn test_load_unsupported_version() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut content = MAGIC.to_vec();
+        content.push(CURRENT_VERSION + 1);
+        content.extend([0u8; 64]);
+        fs::write(&temp_file, content).unwrap();
+        let mut password_store =
+            PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let err = password_store.load().err().unwrap();
+        assert!(
+            matches!(err, StoreError::UnsupportedVersion(version) if version == CURRENT_VERSION + 1)
+        );
+    }
+
+    #[test]
+    fn test_load_unsupported_cipher() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut content = MAGIC.to_vec();
+        content.extend([2, 7]);
+        content.extend([0u8; 64]);
+        fs::write(&temp_file, content).unwrap();
+        let mut password_store =
+            PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let err = password_store.load().err().unwrap();
+        assert!(matches!(err, StoreError::UnsupportedCipher(7)));
+    }
+
+    #[rstest]
+    #[case(Cipher::Aes256Gcm)]
+    #[case(Cipher::ChaCha20Poly1305)]
+    fn test_cipher_round_trip(#[case] cipher: Cipher) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store = PasswordStore::create_with_options(
+            temp_file.clone(),
+            TEST_MASTER_PASSWORD.to_string(),
+            StoreOptions {
+                cipher,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(fs::read(&temp_file).unwrap()[MAGIC.len() + 1], cipher.id());
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "github".to_string(),
+                None,
+                "hunter2".to_string(),
+                HashMap::new(),
+                false,
+            )
+            .unwrap()
+            .update_master("new_master".to_string())
+            .save()
+            .unwrap();
+
+        let mut password_store = PasswordStore::open(temp_file, "new_master".to_string()).unwrap();
+        password_store.load().unwrap();
+        assert_eq!(password_store.cipher(), cipher);
+        let github = password_store.find("github".to_string(), None).unwrap();
+        assert_eq!(github.field("password"), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_append_only_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store = PasswordStore::create_with_options(
+            temp_file.clone(),
+            TEST_MASTER_PASSWORD.to_string(),
+            StoreOptions {
+                append_only: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read(&temp_file).unwrap()[MAGIC.len() + 2],
+            APPEND_ONLY_FLAG
+        );
+        for password in ["hunter1", "hunter2"] {
+            password_store
+                .load()
+                .unwrap()
+                .push(
+                    "github".to_string(),
+                    None,
+                    password.to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap()
+                .save()
+                .unwrap();
+        }
+
+        let mut password_store =
+            PasswordStore::open(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        password_store.load().unwrap();
+        assert!(password_store.is_append_only());
+        assert!(password_store.check_mutable().is_err());
+        assert!(password_store.check_unambiguous("github", None).is_ok());
+        let github = password_store.find("github".to_string(), None).unwrap();
+        assert_eq!(github.field("password"), Some("hunter2"));
+
+        password_store.remove("github".to_string(), None);
+        let err = password_store.save().err().unwrap();
+        assert!(matches!(err, StoreError::AppendOnly));
+        let mut password_store =
+            PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert_eq!(
+            password_store
+                .load()
+                .unwrap()
+                .entries(&EntryFilter::default())
+                .len(),
+            2
+        );
+    }
+
+    #[rstest]
+    // the flags byte, e.g. to turn off append-only
+    #[case(MAGIC.len() + 2, true)]
+    // the last byte of the ciphertext
+    #[case(MAC_LENGTH + DIGEST_LENGTH + 1, false)]
+    // the MAC
+    #[case(DIGEST_LENGTH + 1, false)]
+    // the digest
+    #[case(1, false)]
+    fn test_load_tampered_file(#[case] position: usize, #[case] from_start: bool) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let options = StoreOptions {
+            append_only: true,
+            ..Default::default()
+        };
+        let mut contents = empty_store_contents(TEST_MASTER_PASSWORD, options);
+        let position = if from_start {
+            position
+        } else {
+            contents.len() - position
         };
+        contents[position] ^= 1;
+        fs::write(&temp_file, &contents).unwrap();
+        let mut password_store =
+            PasswordStore::open(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let err = password_store.load().err().unwrap();
+        assert!(matches!(err, StoreError::IntegrityCheckFailed));
+
+        // recomputing the digest is caught by the MAC
+        let digest_start = contents.len() - DIGEST_LENGTH;
+        if position < digest_start {
+            let digest = digest::digest(&digest::SHA256, &contents[..digest_start]);
+            contents[digest_start..].copy_from_slice(digest.as_ref());
+            fs::write(&temp_file, &contents).unwrap();
+            let mut password_store =
+                PasswordStore::open(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+            let err = password_store.load().err().unwrap();
+            assert!(matches!(err, StoreError::IntegrityCheckFailed));
+            assert!(password_store.passwords.is_none());
+        }
+
+        contents.truncate(contents.len() - 1);
+        fs::write(&temp_file, &contents).unwrap();
+        let mut password_store =
+            PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let err = password_store.load().err().unwrap();
+        assert!(matches!(err, StoreError::IntegrityCheckFailed));
+    }
+
+    #[rstest]
+    #[case(3, false)]
+    #[case(4, false)]
+    // turning off append-only along the way
+    #[case(3, true)]
+    #[case(4, true)]
+    #[case(CURRENT_VERSION, true)]
+    fn test_load_downgraded_file(#[case] version: u8, #[case] clear_append_only: bool) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let options = StoreOptions {
+            append_only: true,
+            ..Default::default()
+        };
+        let mut contents = empty_store_contents(TEST_MASTER_PASSWORD, options);
+        contents[MAGIC.len()] = version;
+        if clear_append_only {
+            contents[MAGIC.len() + 2] &= !APPEND_ONLY_FLAG;
+        }
+        if version < 4 {
+            // without the trailer that a version 3 store does not have
+            contents.truncate(contents.len() - MAC_LENGTH - DIGEST_LENGTH);
+        } else {
+            let digest_start = contents.len() - DIGEST_LENGTH;
+            let digest = digest::digest(&digest::SHA256, &contents[..digest_start]);
+            contents[digest_start..].copy_from_slice(digest.as_ref());
+        }
+        fs::write(&temp_file, contents).unwrap();
+        let mut password_store =
+            PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert!(password_store.load().is_err());
+        assert!(password_store.passwords.is_none());
+    }
+
+    #[test]
+    fn test_load_unknown_flags() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut contents = empty_store_contents(TEST_MASTER_PASSWORD, StoreOptions::default());
+        contents.truncate(contents.len() - MAC_LENGTH - DIGEST_LENGTH);
+        contents[MAGIC.len()] = 3;
+        contents[MAGIC.len() + 2] = 0b10;
+        fs::write(&temp_file, contents).unwrap();
+        let mut password_store =
+            PasswordStore::open(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let err = password_store.load().err().unwrap();
+        assert!(matches!(err, StoreError::CorruptFile(_)));
+    }
+
+    #[rstest]
+    #[case(0, json!([]), json!({ "passwords": [] }))]
+    #[case(1, json!({ "passwords": [] }), json!({ "passwords": [] }))]
+    #[case(2, json!({ "passwords": [] }), json!({ "passwords": [] }))]
+    #[case(3, json!({ "passwords": [] }), json!({ "passwords": [] }))]
+    #[case(4, json!({ "passwords": [] }), json!({ "passwords": [] }))]
+    #[case(5, json!({ "passwords": [] }), json!({ "passwords": [] }))]
+    fn test_migrate(#[case] version: u8, #[case] payload: Value, #[case] expected: Value) {
+        assert_eq!(migrate(version, payload), expected);
+    }
+
+    #[rstest]
+    #[case(0, include_bytes!("fixtures/v0.store").as_slice())]
+    #[case(1, include_bytes!("fixtures/v1.store").as_slice())]
+    #[case(2, include_bytes!("fixtures/v2.store").as_slice())]
+    #[case(3, include_bytes!("fixtures/v3.store").as_slice())]
+    #[case(4, include_bytes!("fixtures/v4.store").as_slice())]
+    fn test_load_fixture(#[case] version: u8, #[case] fixture: &[u8]) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        fs::write(&temp_file, fixture).unwrap();
+        let mut password_store =
+            PasswordStore::open(temp_file.clone(), "fixture_master".to_string()).unwrap();
+        password_store.load().unwrap();
+        assert_eq!(password_store.version(), version);
+        assert_eq!(password_store.cipher(), Cipher::Aes256Gcm);
+        let github = password_store
+            .find("github".to_string(), Some("octocat".to_string()))
+            .unwrap();
+        assert_eq!(github.field("password"), Some("hunter2"));
+        let email = password_store.find("email".to_string(), None).unwrap();
+        assert_eq!(email.field("recovery"), Some("555"));
+
+        password_store.save().unwrap();
+        assert_eq!(password_store.version(), CURRENT_VERSION);
+        assert!(fs::read(&temp_file).unwrap().starts_with(MAGIC));
+        let backup_file = temp_dir.path().join(format!("store.v{version}.bak"));
+        if version < CURRENT_VERSION {
+            assert_eq!(fs::read(backup_file).unwrap(), fixture);
+        } else {
+            assert!(!backup_file.exists());
+        }
+
+        let mut password_store =
+            PasswordStore::open(temp_file, "fixture_master".to_string()).unwrap();
+        password_store.load().unwrap();
+        assert_eq!(password_store.version(), CURRENT_VERSION);
+        assert!(password_store.find("email".to_string(), None).is_some());
+    }
+
+    #[test]
+    fn test_key_fingerprint() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let password_store =
+            PasswordStore::create(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let fingerprint = password_store.key_fingerprint().unwrap();
+        assert_eq!(fingerprint.len(), 19);
+        assert!(!fingerprint.contains(TEST_MASTER_PASSWORD));
+
+        // a copy of the store has the same fingerprint
+        let copy_file = temp_dir.path().join("copy");
+        fs::copy(&temp_file, &copy_file).unwrap();
+        let copy = PasswordStore::open(copy_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert_eq!(copy.key_fingerprint().unwrap(), fingerprint);
+
+        let other_master = PasswordStore::open(temp_file, "other_master".to_string()).unwrap();
+        assert_ne!(other_master.key_fingerprint().unwrap(), fingerprint);
+        let other_store = PasswordStore::create(
+            temp_dir.path().join("other"),
+            TEST_MASTER_PASSWORD.to_string(),
+        )
+        .unwrap();
+        assert_ne!(other_store.key_fingerprint().unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn test_is_modified_externally() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::create(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert!(!password_store.is_modified_externally());
+        password_store.load().unwrap();
+        assert!(!password_store.is_modified_externally());
+
+        let file = fs::File::options().write(true).open(&temp_file).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        assert!(password_store.is_modified_externally());
+
+        password_store.load().unwrap();
+        assert!(!password_store.is_modified_externally());
+        password_store.save().unwrap();
+        assert!(!password_store.is_modified_externally());
+    }
+
+    #[rstest]
+    #[case(
+        None,
+        "Password not found. wifi has no entry without a username, specify one of: guest, admin"
+    )]
+    #[case(Some("nobody"), "Password not found")]
+    fn test_not_found_message(#[case] username: Option<&str>, #[case] expected: &str) {
+        let mut password_store = PasswordStore::in_memory(TEST_MASTER_PASSWORD.to_string());
+        password_store.load().unwrap();
+        for username in ["guest", "admin"] {
+            password_store
+                .push(
+                    "wifi".to_string(),
+                    Some(username.to_string()),
+                    "password".to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap();
+        }
+        assert_eq!(password_store.not_found_message("wifi", username), expected);
+        assert_eq!(
+            password_store.not_found_message("other", None),
+            "Password not found"
+        );
+    }
+
+    #[rstest]
+    #[case("gh", vec!["github", "github-enterprise"])]
+    #[case("GitHub", vec!["github"])]
+    #[case("gl", vec!["gitlab"])]
+    #[case("ge", vec!["github-enterprise"])]
+    #[case("il", vec!["mail"])]
+    #[case("xa", vec![])]
+    #[case("", vec![])]
+    fn test_fuzzy_services(#[case] pattern: &str, #[case] expected: Vec<&str>) {
+        let mut password_store = PasswordStore::in_memory(TEST_MASTER_PASSWORD.to_string());
+        password_store.load().unwrap();
+        for service in ["github", "gitlab", "github-enterprise", "mail"] {
+            password_store
+                .push(
+                    service.to_string(),
+                    None,
+                    "password".to_string(),
+                    HashMap::new(),
+                    false,
+                )
+                .unwrap();
+        }
+        assert_eq!(password_store.fuzzy_services(pattern), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_is_atomic() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("store");
+        let link_path = temp_dir.path().join("link");
+        PasswordStore::create(file_path.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink(&file_path, &link_path).unwrap();
+
+        let mut password_store =
+            PasswordStore::open(link_path.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "service".to_string(),
+                None,
+                "password".to_string(),
+                HashMap::new(),
+                false,
+            )
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let metadata = fs::metadata(&file_path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert!(fs::symlink_metadata(&link_path)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let mut file_names: Vec<String> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        file_names.sort();
+        assert_eq!(file_names, ["link", "store"]);
+        let mut password_store =
+            PasswordStore::open(file_path, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        assert!(password_store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None)
+            .is_some());
+    }
+
+    #[test]
+    fn test_in_memory_password_store() {
+        let mut password_store = PasswordStore::in_memory(TEST_MASTER_PASSWORD.to_string());
+        assert!(!password_store.is_stream());
+        assert!(password_store.file_path().is_none());
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "service".to_string(),
+                None,
+                "password".to_string(),
+                HashMap::new(),
+                false,
+            )
+            .unwrap()
+            .save()
+            .unwrap();
+        let mut output = Vec::new();
+        password_store.write_to(&mut output).unwrap();
+        assert!(output.is_empty());
+
+        // reloading reads back what was saved in memory
+        let password_store = password_store.load().unwrap();
+        assert!(password_store.find("service".to_string(), None).is_some());
+        password_store.update_master("wrong".to_string());
+        assert!(matches!(
+            password_store.load().err().unwrap(),
+            StoreError::WrongMasterPassword
+        ));
+    }
+
+    #[test]
+    fn test_stream_password_store() {
+        let mut password_store = PasswordStore::from_reader(
+            &mut (b"" as &[u8]),
+            TEST_MASTER_PASSWORD.to_string(),
+            StoreOptions::default(),
+        )
+        .unwrap();
+        assert!(password_store.is_stream());
+        assert!(password_store.file_path().is_none());
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "service".to_string(),
+                None,
+                "password".to_string(),
+                HashMap::new(),
+                false,
+            )
+            .unwrap();
+
+        let mut output = Vec::new();
+        password_store.write_to(&mut output).unwrap();
+        assert!(output.is_empty());
+        password_store.save().unwrap();
+        password_store.write_to(&mut output).unwrap();
+        assert!(!output.is_empty());
+
+        let mut password_store = PasswordStore::from_reader(
+            &mut output.as_slice(),
+            TEST_MASTER_PASSWORD.to_string(),
+            StoreOptions::default(),
+        )
+        .unwrap();
+        password_store.load().unwrap();
+        assert!(password_store.find("service".to_string(), None).is_some());
+        assert!(!password_store.is_modified_externally());
     }
 }